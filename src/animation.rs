@@ -0,0 +1,105 @@
+use std::{collections::HashMap, io::Cursor, sync::LazyLock, time::Duration};
+
+use image::{AnimationDecoder, DynamicImage, ImageResult, codecs::gif::GifDecoder};
+use tokio::sync::Mutex;
+use tokio_util::sync::CancellationToken;
+
+use crate::{TOKENS, device, watcher::device_tracker};
+
+/// Frames are shown at least this long, caps animations at 10 fps to protect the HID link
+const MIN_FRAME_DELAY: Duration = Duration::from_millis(100);
+
+/// Running animations, keyed by device id and OpenDeck position
+static ANIMATIONS: LazyLock<Mutex<HashMap<(String, u8), CancellationToken>>> =
+    LazyLock::new(|| Mutex::new(HashMap::new()));
+
+/// Frames of an animated image together with how long each is shown
+pub type Frames = Vec<(DynamicImage, Duration)>;
+
+/// Decodes all frames of a GIF, returns `None` for single frame images
+pub fn decode_gif(body: &[u8]) -> ImageResult<Option<Frames>> {
+    let frames = GifDecoder::new(Cursor::new(body))?
+        .into_frames()
+        .collect_frames()?;
+
+    if frames.len() < 2 {
+        return Ok(None);
+    }
+
+    let frames = frames
+        .into_iter()
+        .map(|frame| {
+            let delay = Duration::from(frame.delay()).max(MIN_FRAME_DELAY);
+
+            (DynamicImage::ImageRgba8(frame.into_buffer()), delay)
+        })
+        .collect();
+
+    Ok(Some(frames))
+}
+
+/// Starts looping frames on the key, replacing animation that was running there
+pub async fn start(id: &str, position: u8, frames: Frames) {
+    let Some(device_token) = TOKENS.read().await.get(id).cloned() else {
+        return;
+    };
+
+    let Some(tracker) = device_tracker(id).await else {
+        return;
+    };
+
+    // Stops together with the device
+    let token = device_token.child_token();
+
+    if let Some(previous) = ANIMATIONS
+        .lock()
+        .await
+        .insert((id.to_string(), position), token.clone())
+    {
+        previous.cancel();
+    }
+
+    tracker.spawn(animation_task(id.to_string(), position, frames, token));
+}
+
+/// Stops animation on the key, if there is one
+pub async fn stop(id: &str, position: u8) {
+    if let Some(token) = ANIMATIONS.lock().await.remove(&(id.to_string(), position)) {
+        token.cancel();
+    }
+}
+
+/// Stops all animations of the device
+pub async fn stop_all(id: &str) {
+    ANIMATIONS.lock().await.retain(|(animation_id, _), token| {
+        if animation_id == id {
+            token.cancel();
+        }
+
+        animation_id != id
+    });
+}
+
+async fn animation_task(id: String, position: u8, frames: Frames, token: CancellationToken) {
+    log::debug!("Animating {} frames on {} key {}", frames.len(), id, position);
+
+    // First frame is already shown by the set image handler
+    let mut index = 0;
+
+    loop {
+        tokio::select! {
+            _ = tokio::time::sleep(frames[index].1) => {},
+            _ = token.cancelled() => break,
+        }
+
+        index = (index + 1) % frames.len();
+        let image = &frames[index].0;
+
+        // Written by the image worker, so frames never cut into another upload
+        if !device::show_animation_frame(&id, position, image.clone(), token.clone()).await {
+            break;
+        }
+    }
+
+    log::debug!("Animation finished on {} key {}", id, position);
+}
@@ -0,0 +1,116 @@
+use std::{collections::HashMap, io::Cursor, path::PathBuf};
+
+use image::{ImageFormat, ImageReader};
+use mirajazz::error::MirajazzError;
+
+use crate::{
+    DEVICES,
+    backend::DeckDevice,
+    cache, compose,
+    device,
+    inputs::opendeck_to_device,
+    mappings::{Kind, get_image_format_for_key},
+    settings,
+};
+
+/// Reads pre-encoded JPEGs of the device's asset folder, keyed by OpenDeck position
+///
+/// Files are named by key index (`0.jpg`, `1.jpg`, ...) and must already have the exact size
+/// and orientation of the key, anything else is skipped with a warning
+pub async fn load(id: &str, kind: &Kind) -> HashMap<u8, Vec<u8>> {
+    let Some(dir) = settings::asset_dir_for(id).await else {
+        return HashMap::new();
+    };
+
+    let profile = settings::profile_for(id).await;
+
+    // Sizes are looked up before going blocking, formats follow the physical key
+    let sizes: Vec<(u8, (usize, usize))> = (0..kind.key_count() as u8)
+        .map(|position| {
+            let format = get_image_format_for_key(kind, profile.apply(kind, position));
+            (position, format.size)
+        })
+        .collect();
+
+    tokio::task::spawn_blocking(move || read_assets(PathBuf::from(dir), sizes))
+        .await
+        .expect("reading assets panicked")
+}
+
+fn read_assets(dir: PathBuf, sizes: Vec<(u8, (usize, usize))>) -> HashMap<u8, Vec<u8>> {
+    let mut assets = HashMap::new();
+
+    for (position, (width, height)) in sizes {
+        let path = dir.join(format!("{}.jpg", position));
+
+        let Ok(data) = std::fs::read(&path) else {
+            continue;
+        };
+
+        // Only the header is parsed, the point is not decoding anything
+        let dimensions =
+            ImageReader::with_format(Cursor::new(&data), ImageFormat::Jpeg).into_dimensions();
+
+        match dimensions {
+            Ok((w, h)) if (w as usize, h as usize) == (width, height) => {
+                assets.insert(position, data);
+            }
+            Ok((w, h)) => log::warn!(
+                "Skipping {}: {}x{} doesn't match key size {}x{}",
+                path.display(),
+                w,
+                h,
+                width,
+                height
+            ),
+            Err(e) => log::warn!("Skipping {}: {}", path.display(), e),
+        }
+    }
+
+    assets
+}
+
+/// Writes pre-encoded JPEG to the key at OpenDeck position as it is, without flushing
+///
+/// Returns the physical position written to
+pub async fn upload(
+    device: &dyn DeckDevice,
+    id: &str,
+    position: u8,
+    data: &[u8],
+) -> Result<u8, MirajazzError> {
+    let kind = device.kind();
+
+    // Layers can't be drawn on an encoded JPEG, redraws go back to the key image
+    compose::forget_base(id, position).await;
+
+    let position = settings::profile_for(id).await.apply(&kind, position);
+
+    // Bypasses the rendering pipeline, so regular uploads can't tell what the key shows
+    cache::forget_shown(id, position).await;
+
+    device
+        .write_image(opendeck_to_device(&kind, position), data)
+        .await?;
+
+    Ok(position)
+}
+
+/// Queues every asset of the device for the image worker, returns how many keys got one
+pub async fn show(id: &str) -> Result<usize, String> {
+    let kind = DEVICES
+        .read()
+        .await
+        .get(id)
+        .map(|device| device.kind())
+        .ok_or("unknown device")?;
+
+    let assets = load(id, &kind).await;
+    let count = assets.len();
+
+    if count > 0 && !device::show_assets(id, assets).await {
+        return Err("unknown device".to_string());
+    }
+
+    Ok(count)
+}
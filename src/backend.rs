@@ -0,0 +1,475 @@
+use std::sync::{
+    Arc,
+    atomic::{AtomicUsize, Ordering},
+};
+
+use async_hid::HidError;
+use async_trait::async_trait;
+use image::{DynamicImage, codecs::jpeg::JpegEncoder};
+use mirajazz::{
+    device::Device,
+    error::MirajazzError,
+    images::convert_image_with_format,
+    state::DeviceStateReader,
+    types::{DeviceInput, ImageFormat, ImageMode},
+};
+
+use crate::mappings::{CandidateDevice, Kind, Transport};
+
+/// Operations the plugin needs from a connected device
+///
+/// Keeps [crate::device::device_task] independent of the library used to talk to the hardware
+#[async_trait]
+pub trait DeckDevice: Send + Sync {
+    fn kind(&self) -> Kind;
+    fn serial_number(&self) -> String;
+    fn key_count(&self) -> usize;
+    fn encoder_count(&self) -> usize;
+
+    async fn set_mode(&self, mode: u8) -> Result<(), MirajazzError>;
+    async fn set_brightness(&self, percent: u8) -> Result<(), MirajazzError>;
+    /// Queues image encoded by [encode_key_image] until the next flush
+    ///
+    /// Key is in device numbering, see [crate::inputs::opendeck_to_device]
+    async fn write_image(&self, key: u8, data: &[u8]) -> Result<(), MirajazzError>;
+    async fn clear_button_image(&self, key: u8) -> Result<(), MirajazzError>;
+    async fn clear_all_button_images(&self) -> Result<(), MirajazzError>;
+    /// Sends the queued images, the queue is only emptied when that succeeded
+    ///
+    /// A flush that fails keeps every queued image, so the next one sends them from the start
+    async fn flush(&self) -> Result<(), MirajazzError>;
+    async fn keep_alive(&self) -> Result<(), MirajazzError>;
+    async fn shutdown(&self) -> Result<(), MirajazzError>;
+    /// Sends raw payload, first byte is the report id
+    async fn write_extended_data(&self, payload: &mut Vec<u8>) -> Result<(), MirajazzError>;
+
+    /// Returns source of raw input reports
+    fn report_reader(&self) -> Box<dyn ReportReader>;
+}
+
+/// Source of raw input reports, parsed by [crate::reader::InputReader]
+#[async_trait]
+pub trait ReportReader: Send + Sync {
+    /// Waits for the next report
+    async fn read_report(&self) -> Result<Vec<u8>, MirajazzError>;
+    fn protocol_version(&self) -> usize;
+    /// Whether device reports both key press and release
+    fn supports_both_states(&self) -> bool;
+}
+
+/// Encodes image for the key with the given format, JPEGs at the given quality (clamped to 1-100)
+///
+/// mirajazz encodes JPEGs at a fixed quality. Its conversion still does the resizing, rotation and
+/// mirroring, into a lossless BMP that is then encoded here.
+pub async fn encode_key_image(
+    format: ImageFormat,
+    image: DynamicImage,
+    quality: u8,
+) -> Result<Vec<u8>, MirajazzError> {
+    if !matches!(format.mode, ImageMode::JPEG) {
+        return Ok(convert_image_with_format(format, image).await?);
+    }
+
+    let bmp = ImageFormat {
+        mode: ImageMode::BMP,
+        ..format
+    };
+    let bmp = convert_image_with_format(bmp, image).await?;
+
+    let image = image::load_from_memory_with_format(&bmp, image::ImageFormat::Bmp)?;
+
+    let mut data = Vec::new();
+    JpegEncoder::new_with_quality(&mut data, quality).encode_image(&image.to_rgb8())?;
+
+    Ok(data)
+}
+
+/// Connects to the device using backend chosen by its transport
+pub async fn connect(candidate: &CandidateDevice) -> Result<Box<dyn DeckDevice>, MirajazzError> {
+    match &candidate.transport {
+        Transport::Hid(dev) => {
+            let device = Device::connect(
+                dev,
+                candidate.kind.protocol_version(),
+                candidate.kind.key_count(),
+                candidate.kind.encoder_count(),
+            )
+            .await?;
+
+            Ok(Box::new(MirajazzDevice {
+                device,
+                kind: candidate.kind.clone(),
+            }))
+        }
+        Transport::Mock => Ok(Box::new(MockDevice::new(
+            candidate.kind.clone(),
+            &candidate.id,
+        ))),
+    }
+}
+
+/// Devices talking the mirajazz protocol over HID
+pub struct MirajazzDevice {
+    device: Device,
+    kind: Kind,
+}
+
+#[async_trait]
+impl DeckDevice for MirajazzDevice {
+    fn kind(&self) -> Kind {
+        self.kind.clone()
+    }
+
+    fn serial_number(&self) -> String {
+        self.device.serial_number().to_string()
+    }
+
+    fn key_count(&self) -> usize {
+        self.device.key_count()
+    }
+
+    fn encoder_count(&self) -> usize {
+        self.device.encoder_count()
+    }
+
+    async fn set_mode(&self, mode: u8) -> Result<(), MirajazzError> {
+        self.device.set_mode(mode).await
+    }
+
+    async fn set_brightness(&self, percent: u8) -> Result<(), MirajazzError> {
+        self.device.set_brightness(percent).await
+    }
+
+    async fn write_image(&self, key: u8, data: &[u8]) -> Result<(), MirajazzError> {
+        self.device.write_image(key, data).await
+    }
+
+    async fn clear_button_image(&self, key: u8) -> Result<(), MirajazzError> {
+        self.device.clear_button_image(key).await
+    }
+
+    async fn clear_all_button_images(&self) -> Result<(), MirajazzError> {
+        self.device.clear_all_button_images().await
+    }
+
+    async fn flush(&self) -> Result<(), MirajazzError> {
+        self.device.flush().await
+    }
+
+    async fn keep_alive(&self) -> Result<(), MirajazzError> {
+        self.device.keep_alive().await
+    }
+
+    async fn shutdown(&self) -> Result<(), MirajazzError> {
+        self.device.shutdown().await
+    }
+
+    async fn write_extended_data(&self, payload: &mut Vec<u8>) -> Result<(), MirajazzError> {
+        self.device.write_extended_data(payload).await
+    }
+
+    fn report_reader(&self) -> Box<dyn ReportReader> {
+        // Input processing happens in InputReader, mirajazz only needs this for its own parsing
+        Box::new(self.device.get_reader(|_, _| Ok(DeviceInput::NoData)))
+    }
+}
+
+#[async_trait]
+impl ReportReader for Arc<DeviceStateReader> {
+    async fn read_report(&self) -> Result<Vec<u8>, MirajazzError> {
+        self.raw_read_data(512).await
+    }
+
+    fn protocol_version(&self) -> usize {
+        self.protocol_version
+    }
+
+    fn supports_both_states(&self) -> bool {
+        DeviceStateReader::supports_both_states(self)
+    }
+}
+
+/// Device that only logs what it's asked to do, for working on the plugin without hardware
+pub struct MockDevice {
+    kind: Kind,
+    serial: String,
+    /// Keys of the images written since the last flush
+    queue: std::sync::Mutex<Vec<u8>>,
+    /// Flushes still to fail, see [MockDevice::fail_flushes]
+    failing_flushes: AtomicUsize,
+    /// Key clears still to fail, see [MockDevice::fail_clears]
+    failing_clears: AtomicUsize,
+    /// Images that were flushed and keys that were cleared, in order
+    #[cfg(test)]
+    sent: Arc<std::sync::Mutex<Vec<MockWrite>>>,
+}
+
+/// What reached a [MockDevice]
+#[cfg(test)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MockWrite {
+    Image(u8),
+    Clear(u8),
+}
+
+impl MockDevice {
+    pub fn new(kind: Kind, serial: &str) -> Self {
+        Self {
+            kind,
+            serial: serial.to_string(),
+            queue: std::sync::Mutex::new(vec![]),
+            failing_flushes: AtomicUsize::new(0),
+            failing_clears: AtomicUsize::new(0),
+            #[cfg(test)]
+            sent: Arc::new(std::sync::Mutex::new(vec![])),
+        }
+    }
+
+    /// Makes the next `count` flushes fail like a broken off transfer
+    #[cfg(test)]
+    pub fn fail_flushes(&self, count: usize) {
+        self.failing_flushes.store(count, Ordering::Relaxed);
+    }
+
+    /// Makes the next `count` key clears fail like a broken off report write
+    #[cfg(test)]
+    pub fn fail_clears(&self, count: usize) {
+        self.failing_clears.store(count, Ordering::Relaxed);
+    }
+
+    /// Returns everything that reached the device so far
+    #[cfg(test)]
+    pub fn sent(&self) -> Vec<MockWrite> {
+        self.sent.lock().unwrap().clone()
+    }
+
+    /// Shares the record of [MockDevice::sent], for devices handed over to [crate::DEVICES]
+    #[cfg(test)]
+    pub fn sent_record(&self) -> Arc<std::sync::Mutex<Vec<MockWrite>>> {
+        self.sent.clone()
+    }
+}
+
+#[async_trait]
+impl DeckDevice for MockDevice {
+    fn kind(&self) -> Kind {
+        self.kind.clone()
+    }
+
+    fn serial_number(&self) -> String {
+        self.serial.clone()
+    }
+
+    fn key_count(&self) -> usize {
+        self.kind.key_count()
+    }
+
+    fn encoder_count(&self) -> usize {
+        self.kind.encoder_count()
+    }
+
+    async fn set_mode(&self, mode: u8) -> Result<(), MirajazzError> {
+        log::debug!("[mock {}] set_mode({})", self.serial, mode);
+        Ok(())
+    }
+
+    async fn set_brightness(&self, percent: u8) -> Result<(), MirajazzError> {
+        log::debug!("[mock {}] set_brightness({})", self.serial, percent);
+        Ok(())
+    }
+
+    async fn write_image(&self, key: u8, data: &[u8]) -> Result<(), MirajazzError> {
+        log::debug!(
+            "[mock {}] write_image({}, {} bytes)",
+            self.serial,
+            key,
+            data.len()
+        );
+
+        self.queue.lock().unwrap().push(key);
+        Ok(())
+    }
+
+    async fn clear_button_image(&self, key: u8) -> Result<(), MirajazzError> {
+        log::debug!("[mock {}] clear_button_image({})", self.serial, key);
+
+        let failing = self
+            .failing_clears
+            .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |count| {
+                count.checked_sub(1)
+            })
+            .is_ok();
+
+        if failing {
+            return Err(HidError::message("mock report write broke off").into());
+        }
+
+        #[cfg(test)]
+        self.sent.lock().unwrap().push(MockWrite::Clear(key));
+        Ok(())
+    }
+
+    async fn clear_all_button_images(&self) -> Result<(), MirajazzError> {
+        log::debug!("[mock {}] clear_all_button_images()", self.serial);
+        Ok(())
+    }
+
+    async fn flush(&self) -> Result<(), MirajazzError> {
+        let failing = self
+            .failing_flushes
+            .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |count| {
+                count.checked_sub(1)
+            })
+            .is_ok();
+
+        // Kept when the flush fails, like the queue of mirajazz
+        if failing {
+            let keys = self.queue.lock().unwrap().clone();
+            log::debug!("[mock {}] flush() of {:?} failed", self.serial, keys);
+            return Err(HidError::message("mock transfer broke off").into());
+        }
+
+        let keys = std::mem::take(&mut *self.queue.lock().unwrap());
+
+        log::debug!("[mock {}] flush() of {:?}", self.serial, keys);
+        #[cfg(test)]
+        self.sent
+            .lock()
+            .unwrap()
+            .extend(keys.into_iter().map(MockWrite::Image));
+        Ok(())
+    }
+
+    async fn keep_alive(&self) -> Result<(), MirajazzError> {
+        Ok(())
+    }
+
+    async fn shutdown(&self) -> Result<(), MirajazzError> {
+        log::debug!("[mock {}] shutdown()", self.serial);
+        Ok(())
+    }
+
+    async fn write_extended_data(&self, payload: &mut Vec<u8>) -> Result<(), MirajazzError> {
+        log::debug!(
+            "[mock {}] write_extended_data({:02x?})",
+            self.serial,
+            payload
+        );
+        Ok(())
+    }
+
+    fn report_reader(&self) -> Box<dyn ReportReader> {
+        Box::new(MockReportReader)
+    }
+}
+
+/// Mock device has no inputs, reads never resolve
+struct MockReportReader;
+
+#[async_trait]
+impl ReportReader for MockReportReader {
+    async fn read_report(&self) -> Result<Vec<u8>, MirajazzError> {
+        std::future::pending().await
+    }
+
+    fn protocol_version(&self) -> usize {
+        3
+    }
+
+    fn supports_both_states(&self) -> bool {
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use image::{Rgb, RgbImage};
+    use mirajazz::types::{ImageMirroring, ImageRotation};
+
+    use super::*;
+
+    /// Gradient with some noise, flat images compress to the same size at every quality
+    fn gradient(size: u32) -> DynamicImage {
+        DynamicImage::ImageRgb8(RgbImage::from_fn(size, size, |x, y| {
+            let noise = ((x * 7 + y * 13) % 17) as u8;
+
+            Rgb([
+                (x * 255 / size) as u8 ^ noise,
+                (y * 255 / size) as u8,
+                noise * 15,
+            ])
+        }))
+    }
+
+    fn jpeg(size: usize) -> ImageFormat {
+        ImageFormat {
+            mode: ImageMode::JPEG,
+            size: (size, size),
+            rotation: ImageRotation::Rot0,
+            mirror: ImageMirroring::None,
+        }
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn encoded_size_grows_with_quality() {
+        // Top LCDs and main keys of the N1
+        for size in [64, 96] {
+            let mut last = 0;
+
+            for quality in [10, 30, 50, 70, 90, 100] {
+                let data = encode_key_image(jpeg(size), gradient(128), quality)
+                    .await
+                    .unwrap();
+
+                assert!(
+                    data.len() > last,
+                    "{}x{} at quality {} is {} bytes, not more than {}",
+                    size,
+                    size,
+                    quality,
+                    data.len(),
+                    last
+                );
+                last = data.len();
+            }
+        }
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn encoded_image_has_the_size_and_orientation_of_the_key() {
+        // Left half white, so rotation and mirroring show where it ends up
+        let image = DynamicImage::ImageRgb8(RgbImage::from_fn(40, 40, |x, _| {
+            if x < 20 { Rgb([255; 3]) } else { Rgb([0; 3]) }
+        }));
+        let format = ImageFormat {
+            rotation: ImageRotation::Rot90,
+            mirror: ImageMirroring::Both,
+            ..jpeg(85)
+        };
+
+        let data = encode_key_image(format, image, 100).await.unwrap();
+        let decoded = image::load_from_memory_with_format(&data, image::ImageFormat::Jpeg)
+            .unwrap()
+            .to_rgb8();
+
+        assert_eq!(decoded.dimensions(), (85, 85));
+        // Rotated by 90° the white half is on top, mirrored vertically it's at the bottom
+        assert!(decoded.get_pixel(42, 5).0[0] < 32);
+        assert!(decoded.get_pixel(42, 80).0[0] > 224);
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn bmp_formats_are_left_to_mirajazz() {
+        let format = ImageFormat {
+            mode: ImageMode::BMP,
+            ..jpeg(32)
+        };
+
+        let data = encode_key_image(format, gradient(32), 10).await.unwrap();
+        let expected = convert_image_with_format(format, gradient(32))
+            .await
+            .unwrap();
+
+        assert_eq!(data, expected);
+    }
+}
@@ -1,10 +1,10 @@
-/// Comprehensive dial/encoder debug tool for N1
-/// Run with: cargo run --bin debug_dial
-/// 
-/// This tool will:
-/// 1. Find all HID interfaces on the N1
-/// 2. Connect to each one and show what inputs come through
-/// 3. Help identify which interface carries dial/face button events
+//! Comprehensive dial/encoder debug tool for N1
+//! Run with: cargo run --bin debug_dial
+//! 
+//! This tool will:
+//! 1. Find all HID interfaces on the N1
+//! 2. Connect to each one and show what inputs come through
+//! 3. Help identify which interface carries dial/face button events
 
 use async_hid::{HidBackend, AsyncHidRead, AsyncHidWrite};
 use futures_lite::StreamExt;
@@ -81,23 +81,17 @@ async fn test_interface(interface: &HidInterface, backend: &HidBackend, duration
     let start = std::time::Instant::now();
     
     while start.elapsed().as_secs() < duration_secs {
-        match tokio::time::timeout(
+        if let Ok(Ok(n)) = tokio::time::timeout(
             tokio::time::Duration::from_millis(100),
             reader.read_input_report(&mut buf)
-        ).await {
-            Ok(Ok(n)) => {
-                let n = n as usize;
-                if n > 0 {
-                    if let Some((input, state, classification)) = parse_report(&buf, n) {
-                        events.push(format!(
-                            "input={:3} state={} | {}",
-                            input, state, classification
-                        ));
-                    }
+        ).await
+            && n > 0
+                && let Some((input, state, classification)) = parse_report(&buf, n) {
+                    events.push(format!(
+                        "input={:3} state={} | {}",
+                        input, state, classification
+                    ));
                 }
-            }
-            _ => {}
-        }
     }
     
     Ok(events)
@@ -209,7 +203,6 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     loop {
         match reader.read_input_report(&mut buf).await {
             Ok(n) => {
-                let n = n as usize;
                 if n == 0 {
                     continue;
                 }
@@ -222,8 +215,8 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
                         print!("[{:3}] ", event_count);
                         
                         // Show hex bytes
-                        for i in 0..n.min(12) {
-                            print!("{:02x} ", buf[i]);
+                        for byte in buf.iter().take(n.min(12)) {
+                            print!("{:02x} ", byte);
                         }
                         
                         let state_str = if state == 1 {
@@ -236,7 +229,7 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
                         
                         println!("| {} | input={:3} ({:2}) | {}", 
                             state_str, input, classification, 
-                            if input >= 30 && input <= 51 { "★ SPECIAL ★" } else { "" }
+                            if (30..=51).contains(&input) { "★ SPECIAL ★" } else { "" }
                         );
                         
                         last_input = input;
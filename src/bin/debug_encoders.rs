@@ -1,5 +1,5 @@
-/// Debug encoder events for N1
-/// Shows exactly what inputs are received and what events are generated
+//! Debug encoder events for N1
+//! Shows exactly what inputs are received and what events are generated
 
 use mirajazz::{
     device::{list_devices, Device, DeviceQuery},
@@ -8,44 +8,18 @@ use mirajazz::{
     types::DeviceInput,
 };
 
-const N1_KEY_COUNT: usize = 18;
+// Button states from the key table of the plugin
+use opendeck_ajazz_n1::keymap::read_button_press_n1;
+
 const N1_FACE_BUTTON_LEFT: u8 = 30;
 const N1_FACE_BUTTON_RIGHT: u8 = 31;
 const N1_DIAL_PRESS: u8 = 35;
 const N1_DIAL_ROTATE_CCW: u8 = 50;
 const N1_DIAL_ROTATE_CW: u8 = 51;
 
-fn device_to_opendeck_n1(key: usize) -> usize {
-    match key {
-        16 => 0, 17 => 1, 18 => 2,
-        1..=15 => key + 2,
-        _ => key.saturating_sub(1),
-    }
-}
-
-fn read_button_states(states: &[u8], key_count: usize) -> Vec<bool> {
-    let mut bools = vec![];
-    for i in 0..key_count {
-        bools.push(states.get(i + 1).copied().unwrap_or(0) != 0);
-    }
-    bools
-}
-
-fn read_button_press_n1(input: u8, state: u8) -> Result<DeviceInput, MirajazzError> {
-    let mut button_states = vec![0x01];
-    button_states.extend(vec![0u8; N1_KEY_COUNT + 1]);
-    if input == 0 {
-        return Ok(DeviceInput::ButtonStateChange(read_button_states(&button_states, N1_KEY_COUNT)));
-    }
-    let pressed_index: usize = device_to_opendeck_n1(input as usize);
-    if pressed_index < N1_KEY_COUNT {
-        button_states[pressed_index + 1] = state;
-    }
-    Ok(DeviceInput::ButtonStateChange(read_button_states(&button_states, N1_KEY_COUNT)))
-}
-
 pub fn process_input_n1(input: u8, state: u8) -> Result<DeviceInput, MirajazzError> {
-    let result = match input {
+    
+    match input {
         1..=18 => read_button_press_n1(input, state),
         N1_FACE_BUTTON_LEFT => {
             println!("  → Mapping to EncoderStateChange([true, false, false])");
@@ -83,8 +57,7 @@ pub fn process_input_n1(input: u8, state: u8) -> Result<DeviceInput, MirajazzErr
             println!("  → Unknown input!");
             Err(MirajazzError::BadData)
         }
-    };
-    result
+    }
 }
 
 const AJAZZ_VID: u16 = 0x0300;
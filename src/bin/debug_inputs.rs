@@ -1,5 +1,5 @@
-/// Debug tool to read raw inputs from N1 device
-/// Run with: cargo run --bin debug_inputs
+//! Debug tool to read raw inputs from N1 device
+//! Run with: cargo run --bin debug_inputs
 
 use mirajazz::{
     device::{list_devices, Device, DeviceQuery},
@@ -76,7 +76,7 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     ];
     
     for (i, query) in queries.iter().enumerate() {
-        let devices = list_devices(&[query.clone()]).await?;
+        let devices = list_devices(std::slice::from_ref(query)).await?;
         if !devices.is_empty() {
             println!("Query {}: Found {} device(s)", i, devices.len());
             for dev in &devices {
@@ -1,5 +1,5 @@
-/// Test different device modes to see which one enables dial/face button events
-/// Run with: cargo run --bin debug_modes
+//! Test different device modes to see which one enables dial/face button events
+//! Run with: cargo run --bin debug_modes
 
 use async_hid::{HidBackend, AsyncHidRead, AsyncHidWrite};
 use futures_lite::StreamExt;
@@ -40,32 +40,27 @@ async fn test_mode(backend: &HidBackend, dev_info: &async_hid::DeviceInfo, mode:
     let start = std::time::Instant::now();
     
     while start.elapsed().as_secs() < duration_secs {
-        match tokio::time::timeout(
+        if let Ok(Ok(n)) = tokio::time::timeout(
             tokio::time::Duration::from_millis(50),
             reader.read_input_report(&mut buf)
-        ).await {
-            Ok(Ok(n)) => {
-                let n = n as usize;
-                if n >= 11 && buf[0] == 65 && buf[1] == 67 && buf[2] == 75 {
-                    let input = buf[9];
-                    let state = buf[10];
-                    
-                    let desc = match input {
-                        0 => "SYNC".to_string(),
-                        1..=18 => format!("Button {}", input),
-                        30 => "FaceBtnL".to_string(),
-                        31 => "FaceBtnR".to_string(),
-                        35 => "DialPress".to_string(),
-                        50 => "DialCCW".to_string(),
-                        51 => "DialCW".to_string(),
-                        _ => format!("Input{}", input),
-                    };
-                    
-                    events.push(format!("{} state={}", desc, state));
-                }
+        ).await
+            && n >= 11 && buf[0] == 65 && buf[1] == 67 && buf[2] == 75 {
+                let input = buf[9];
+                let state = buf[10];
+                
+                let desc = match input {
+                    0 => "SYNC".to_string(),
+                    1..=18 => format!("Button {}", input),
+                    30 => "FaceBtnL".to_string(),
+                    31 => "FaceBtnR".to_string(),
+                    35 => "DialPress".to_string(),
+                    50 => "DialCCW".to_string(),
+                    51 => "DialCW".to_string(),
+                    _ => format!("Input{}", input),
+                };
+                
+                events.push(format!("{} state={}", desc, state));
             }
-            _ => {}
-        }
     }
     
     events
@@ -84,7 +79,7 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     let mut n1_info = None;
     for dev in &devices {
         if dev.vendor_id == AJAZZ_VID && dev.product_id == N1_PID && dev.usage_page == 65440 {
-            n1_info = Some(dev.clone());
+            n1_info = Some(dev);
             break;
         }
     }
@@ -113,7 +108,7 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
         println!("   5. Press dial down");
         println!();
         
-        let events = test_mode(&backend, &n1_info, mode, 5).await;
+        let events = test_mode(&backend, n1_info, mode, 5).await;
         
         if events.is_empty() {
             println!("❌ No events received in mode {}\n", mode);
@@ -1,5 +1,5 @@
-/// Raw HID debug tool - reads directly from USB HID
-/// Run with: cargo run --bin debug_raw
+//! Raw HID debug tool - reads directly from USB HID
+//! Run with: cargo run --bin debug_raw
 
 use async_hid::{HidBackend, AsyncHidRead};
 use futures_lite::StreamExt;
@@ -29,7 +29,7 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
             println!("     Usage Page: {}, Usage: {}", dev.usage_page, dev.usage_id);
             
             if is_n1 {
-                n1_device = Some(dev.clone());
+                n1_device = Some(dev);
             }
         }
     }
@@ -63,11 +63,11 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     loop {
         match reader.read_input_report(&mut buf).await {
             Ok(n) => {
-                let n_usize: usize = n as usize;
+                let n_usize: usize = n;
                 if n_usize > 0 {
                     print!("[{} bytes] ", n_usize);
-                    for i in 0..n_usize.min(16) {
-                        print!("{:02x} ", buf[i]);
+                    for byte in buf.iter().take(n_usize.min(16)) {
+                        print!("{:02x} ", byte);
                     }
                     if n_usize > 16 {
                         print!("...");
@@ -1,5 +1,5 @@
-/// Raw HID Debug for N1 - simple version
-/// Run with: cargo run --bin debug_raw_hid
+//! Raw HID Debug for N1 - simple version
+//! Run with: cargo run --bin debug_raw_hid
 
 use async_hid::{HidBackend, AsyncHidRead};
 use futures_lite::StreamExt;
@@ -9,8 +9,8 @@ const N1_PID: u16 = 0x3007;
 
 fn format_bytes(bytes: &[u8], len: usize) -> String {
     let mut result = String::new();
-    for i in 0..len.min(bytes.len()).min(32) {
-        result.push_str(&format!("{:02x} ", bytes[i]));
+    for byte in bytes.iter().take(len.min(32)) {
+        result.push_str(&format!("{:02x} ", byte));
     }
     result
 }
@@ -30,7 +30,7 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
             println!("  Name: {}", dev.name);
             println!("  VID: {:04x}, PID: {:04x}", dev.vendor_id, dev.product_id);
             println!("  Usage Page: {}, Usage: {}", dev.usage_page, dev.usage_id);
-            n1_info = Some(dev.clone());
+            n1_info = Some(dev);
         }
     }
     
@@ -61,7 +61,6 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     loop {
         match reader.read_input_report(&mut buf).await {
             Ok(n) => {
-                let n = n as usize;
                 if n == 0 {
                     continue;
                 }
@@ -0,0 +1,173 @@
+//! Key size discovery for unknown clones
+//! Uploads framed test patterns at candidate sizes to every key, watches the device for
+//! error responses and asks you to confirm which pattern fits, then prints a descriptor entry
+//! Run with: cargo run --bin discover_key_sizes -- [vid] [pid] [protocol_version] [key_count]
+//! (hex vid/pid, defaults to the N1: 0300 3007 3 18)
+
+use image::{DynamicImage, Rgb, RgbImage};
+use mirajazz::{
+    device::{list_devices, Device, DeviceQuery},
+    types::{ImageFormat, ImageMirroring, ImageMode, ImageRotation},
+};
+use std::time::Duration;
+use tokio::io::{AsyncBufReadExt, BufReader};
+
+const CANDIDATE_SIZES: [usize; 9] = [60, 64, 72, 80, 85, 96, 100, 112, 120];
+
+/// Border and diagonals make cropping, offset and tiling visible at a glance
+fn test_pattern(size: usize) -> DynamicImage {
+    let size = size as u32;
+    let mut img = RgbImage::from_pixel(size, size, Rgb([0, 0, 0]));
+
+    for i in 0..size {
+        for t in 0..2 {
+            img.put_pixel(i, t, Rgb([255, 0, 0]));
+            img.put_pixel(i, size - 1 - t, Rgb([255, 0, 0]));
+            img.put_pixel(t, i, Rgb([0, 255, 0]));
+            img.put_pixel(size - 1 - t, i, Rgb([0, 255, 0]));
+        }
+
+        img.put_pixel(i, i, Rgb([255, 255, 255]));
+        img.put_pixel(size - 1 - i, i, Rgb([255, 255, 255]));
+    }
+
+    DynamicImage::ImageRgb8(img)
+}
+
+fn format_for(size: usize) -> ImageFormat {
+    ImageFormat {
+        mode: ImageMode::JPEG,
+        size: (size, size),
+        rotation: ImageRotation::Rot0,
+        mirror: ImageMirroring::None,
+    }
+}
+
+fn parse_hex_arg(args: &[String], index: usize, default: u16) -> u16 {
+    args.get(index)
+        .and_then(|v| u16::from_str_radix(v.trim_start_matches("0x"), 16).ok())
+        .unwrap_or(default)
+}
+
+#[tokio::main]
+async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let args: Vec<String> = std::env::args().collect();
+
+    let vid = parse_hex_arg(&args, 1, 0x0300);
+    let pid = parse_hex_arg(&args, 2, 0x3007);
+    let protocol_version: usize = args.get(3).and_then(|v| v.parse().ok()).unwrap_or(3);
+    let key_count: usize = args.get(4).and_then(|v| v.parse().ok()).unwrap_or(18);
+
+    println!("🔍 Looking for {:04x}:{:04x}...\n", vid, pid);
+
+    let devices = list_devices(&[DeviceQuery::new(65440, 1, vid, pid)]).await?;
+    let Some(dev_info) = devices.into_iter().next() else {
+        println!("❌ Device not found!");
+        return Ok(());
+    };
+
+    println!("✅ Found: {}\n", dev_info.name);
+
+    let device = Device::connect(&dev_info, protocol_version, key_count, 0).await?;
+    device.set_mode(3).await?;
+    tokio::time::sleep(Duration::from_millis(100)).await;
+    device.clear_all_button_images().await?;
+    device.flush().await?;
+
+    let reader = device.get_reader(|_, _| Ok(mirajazz::types::DeviceInput::NoData));
+    let mut stdin = BufReader::new(tokio::io::stdin()).lines();
+
+    println!("Each key will show a red/green frame with a white X.");
+    println!("The right size shows the whole frame, with the X meeting the corners.");
+    println!("Answer y (fits), n (try next size) or s (skip key).\n");
+
+    let mut sizes: Vec<Option<usize>> = Vec::with_capacity(key_count);
+    let mut last_size: Option<usize> = None;
+
+    for key in 0..key_count {
+        // Try the size of the previous key first, neighbouring keys usually match
+        let mut candidates: Vec<usize> = CANDIDATE_SIZES.to_vec();
+        if let Some(last) = last_size {
+            candidates.retain(|s| *s != last);
+            candidates.insert(0, last);
+        }
+
+        let mut found = None;
+
+        for size in candidates {
+            let uploaded = async {
+                device.clear_button_image(key as u8).await?;
+                device
+                    .set_button_image(key as u8, format_for(size), test_pattern(size))
+                    .await?;
+                device.flush().await
+            }
+            .await;
+
+            if let Err(e) = uploaded {
+                println!("  ⚠️  key {} rejected {}×{}: {:?}", key, size, size, e);
+                continue;
+            }
+
+            // Firmware doesn't ACK images, but some clones report errors right after a bad upload
+            if let Ok(Some(data)) = reader
+                .raw_read_data_with_timeout(512, Duration::from_millis(300))
+                .await
+                && !data.starts_with(&[65, 67, 75])
+            {
+                println!("  ⚠️  key {} answered {}×{} with {:02x?}", key, size, size, &data[..16]);
+                continue;
+            }
+
+            print!("Key {:2} at {}×{} - fits? [y/n/s] ", key, size, size);
+            use std::io::Write;
+            std::io::stdout().flush().ok();
+
+            let answer = stdin.next_line().await?.unwrap_or_default();
+
+            match answer.trim() {
+                "y" | "Y" => {
+                    found = Some(size);
+                    last_size = Some(size);
+                    break;
+                }
+                "s" | "S" => break,
+                _ => continue,
+            }
+        }
+
+        sizes.push(found);
+
+        device.clear_button_image(key as u8).await?;
+        device.flush().await?;
+    }
+
+    println!("\n📋 Key size table (device key indices, as passed to set_button_image):\n");
+    println!("    // {:04x}:{:04x}, protocol v{}", vid, pid, protocol_version);
+    println!("    const KEY_SIZES: &[KeySize] = &[");
+
+    let mut start = 0;
+    while start < key_count {
+        let mut end = start;
+        while end + 1 < key_count && sizes[end + 1] == sizes[start] {
+            end += 1;
+        }
+
+        match sizes[start] {
+            Some(size) => println!(
+                "        KeySize {{ keys: ({}, {}), size: ({}, {}), padding: 0 }},",
+                start, end, size, size
+            ),
+            None => println!("        // keys {}-{}: unknown", start, end),
+        }
+
+        start = end + 1;
+    }
+
+    println!("    ];");
+
+    device.clear_all_button_images().await?;
+    device.flush().await?;
+
+    Ok(())
+}
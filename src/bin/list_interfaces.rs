@@ -1,5 +1,5 @@
-/// List all HID interfaces for N1
-/// This helps identify if the dial/face buttons are on a different interface
+//! List all HID interfaces for N1
+//! This helps identify if the dial/face buttons are on a different interface
 
 use async_hid::HidBackend;
 use futures_lite::StreamExt;
@@ -1,6 +1,6 @@
-/// Simple button mapper - just logs all inputs
-/// Run with: cargo run --bin map_buttons
-/// Then press buttons and watch the output
+//! Simple button mapper - just logs all inputs
+//! Run with: cargo run --bin map_buttons
+//! Then press buttons and watch the output
 
 use mirajazz::{
     device::{list_devices, Device, DeviceQuery},
@@ -1,5 +1,5 @@
-/// Raw HID trace - shows exactly what bytes come from the device
-/// Run with: cargo run --bin raw_hid_trace
+//! Raw HID trace - shows exactly what bytes come from the device
+//! Run with: cargo run --bin raw_hid_trace
 
 use async_hid::{HidBackend, AsyncHidRead, AsyncHidWrite};
 use futures_lite::StreamExt;
@@ -34,7 +34,7 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
             println!("Found N1 interface:");
             println!("  Name: {}", dev.name);
             println!("  Usage Page: {}, Usage: {}", dev.usage_page, dev.usage_id);
-            n1_info = Some(dev.clone());
+            n1_info = Some(dev);
             break;
         }
     }
@@ -83,7 +83,6 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     loop {
         match reader.read_input_report(&mut buf).await {
             Ok(n) => {
-                let n = n as usize;
                 if n == 0 {
                     continue;
                 }
@@ -113,8 +112,8 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
                         };
                         
                         print!("[{:<3}] ", n);
-                        for i in 0..n.min(12) {
-                            print!("{:02x} ", buf[i]);
+                        for byte in buf.iter().take(n.min(12)) {
+                            print!("{:02x} ", byte);
                         }
                         
                         let state_str = if state == 1 { "PRESS" } else if state == 0 && input != 0 { "RELEASE" } else { "SYNC" };
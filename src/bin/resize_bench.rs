@@ -0,0 +1,72 @@
+//! Resize filter timings for picking the key image default
+//! Scales a synthetic icon with text-like detail down to the N1 key sizes with every filter
+//! and prints how long each took, no device needed
+//! Run with: cargo run --release --bin resize_bench -- [source size] [rounds]
+//! (defaults: 512 px, 50 rounds)
+
+use image::{DynamicImage, Rgb, RgbImage, imageops::FilterType};
+use std::time::{Duration, Instant};
+
+const FILTERS: [(&str, FilterType); 4] = [
+    ("nearest", FilterType::Nearest),
+    ("triangle", FilterType::Triangle),
+    ("catmullrom", FilterType::CatmullRom),
+    ("lanczos3", FilterType::Lanczos3),
+];
+
+/// Top LCDs and main buttons of the N1
+const TARGET_SIZES: [u32; 2] = [64, 96];
+
+/// Thin stripes and a checkerboard corner, where filters differ the most
+fn source_image(size: u32) -> DynamicImage {
+    let mut img = RgbImage::from_pixel(size, size, Rgb([20, 20, 20]));
+
+    for y in 0..size {
+        for x in 0..size {
+            let stripe = (x / 3) % 2 == 0 && y < size / 2;
+            let checker = ((x / 2) + (y / 2)) % 2 == 0 && y >= size / 2 && x >= size / 2;
+
+            if stripe || checker {
+                img.put_pixel(x, y, Rgb([240, 240, 240]));
+            }
+        }
+    }
+
+    DynamicImage::ImageRgb8(img)
+}
+
+fn main() {
+    let mut args = std::env::args().skip(1);
+    let size: u32 = args.next().and_then(|v| v.parse().ok()).unwrap_or(512);
+    let rounds: u32 = args.next().and_then(|v| v.parse().ok()).unwrap_or(50);
+
+    let source = source_image(size);
+
+    println!(
+        "Resizing {}×{} source, {} rounds per filter\n",
+        size, size, rounds
+    );
+    println!("{:<12} {:>10} {:>10}", "filter", "64 px", "96 px");
+
+    for (name, filter) in FILTERS {
+        let timings: Vec<Duration> = TARGET_SIZES
+            .iter()
+            .map(|&target| {
+                let started = Instant::now();
+
+                for _ in 0..rounds {
+                    std::hint::black_box(source.resize_exact(target, target, filter));
+                }
+
+                started.elapsed() / rounds
+            })
+            .collect();
+
+        println!(
+            "{:<12} {:>8.2}ms {:>8.2}ms",
+            name,
+            timings[0].as_secs_f64() * 1000.0,
+            timings[1].as_secs_f64() * 1000.0
+        );
+    }
+}
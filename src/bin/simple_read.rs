@@ -1,5 +1,5 @@
-/// Simple continuous reader - matches mirajazz N1 example
-/// Run with: cargo run --bin simple_read
+//! Simple continuous reader - matches mirajazz N1 example
+//! Run with: cargo run --bin simple_read
 
 use mirajazz::{
     device::{list_devices, Device, DeviceQuery},
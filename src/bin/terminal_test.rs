@@ -1,6 +1,6 @@
-/// Terminal-based visual test for N1
-/// Shows a live updating display in the terminal
-/// Run with: cargo run --bin terminal_test
+//! Terminal-based visual test for N1
+//! Shows a live updating display in the terminal
+//! Run with: cargo run --bin terminal_test
 
 use mirajazz::{
     device::{list_devices, Device, DeviceQuery},
@@ -10,42 +10,15 @@ use mirajazz::{
 use std::io::{self, Write};
 use mirajazz::types::DeviceInput;
 
-const N1_KEY_COUNT: usize = 18;
+// Button states from the key table of the plugin
+use opendeck_ajazz_n1::keymap::read_button_press_n1;
+
 const N1_FACE_BUTTON_LEFT: u8 = 30;
 const N1_FACE_BUTTON_RIGHT: u8 = 31;
 const N1_DIAL_PRESS: u8 = 35;
 const N1_DIAL_ROTATE_CCW: u8 = 50;
 const N1_DIAL_ROTATE_CW: u8 = 51;
 
-fn device_to_opendeck_n1(key: usize) -> usize {
-    match key {
-        16 => 0, 17 => 1, 18 => 2,
-        1..=15 => key + 2,
-        _ => key.saturating_sub(1),
-    }
-}
-
-fn read_button_states(states: &[u8], key_count: usize) -> Vec<bool> {
-    let mut bools = vec![];
-    for i in 0..key_count {
-        bools.push(states.get(i + 1).copied().unwrap_or(0) != 0);
-    }
-    bools
-}
-
-fn read_button_press_n1(input: u8, state: u8) -> Result<DeviceInput, MirajazzError> {
-    let mut button_states = vec![0x01];
-    button_states.extend(vec![0u8; N1_KEY_COUNT + 1]);
-    if input == 0 {
-        return Ok(DeviceInput::ButtonStateChange(read_button_states(&button_states, N1_KEY_COUNT)));
-    }
-    let pressed_index: usize = device_to_opendeck_n1(input as usize);
-    if pressed_index < N1_KEY_COUNT {
-        button_states[pressed_index + 1] = state;
-    }
-    Ok(DeviceInput::ButtonStateChange(read_button_states(&button_states, N1_KEY_COUNT)))
-}
-
 pub fn process_input_n1(input: u8, state: u8) -> Result<DeviceInput, MirajazzError> {
     match input {
         1..=18 => read_button_press_n1(input, state),
@@ -139,45 +112,42 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     draw_ui(face_left, face_right, dial_pressed, rotation);
     
     loop {
-        match tokio::time::timeout(
+        if let Ok(Ok(updates)) = tokio::time::timeout(
             tokio::time::Duration::from_millis(100),
             reader.read(None)
         ).await {
-            Ok(Ok(updates)) => {
-                let mut changed = false;
-                
-                for update in updates {
-                    match update {
-                        DeviceStateUpdate::EncoderDown(enc) => {
-                            println!("\n🔘 Encoder {} pressed", enc);
-                            // We can't distinguish which button from the event alone
-                            // But we know one of them was pressed
-                            face_left = true;
-                            dial_pressed = true;
-                            changed = true;
-                        }
-                        DeviceStateUpdate::EncoderUp(enc) => {
-                            println!("\n🔘 Encoder {} released", enc);
-                            face_left = false;
-                            face_right = false;
-                            dial_pressed = false;
-                            changed = true;
-                        }
-                        DeviceStateUpdate::EncoderTwist(enc, val) => {
-                            println!("\n🔄 Encoder {} twist: {}", enc, val);
-                            rotation += val as i32;
-                            last_activity = std::time::Instant::now();
-                            changed = true;
-                        }
-                        _ => {}
+            let mut changed = false;
+            
+            for update in updates {
+                match update {
+                    DeviceStateUpdate::EncoderDown(enc) => {
+                        println!("\n🔘 Encoder {} pressed", enc);
+                        // We can't distinguish which button from the event alone
+                        // But we know one of them was pressed
+                        face_left = true;
+                        dial_pressed = true;
+                        changed = true;
                     }
+                    DeviceStateUpdate::EncoderUp(enc) => {
+                        println!("\n🔘 Encoder {} released", enc);
+                        face_left = false;
+                        face_right = false;
+                        dial_pressed = false;
+                        changed = true;
+                    }
+                    DeviceStateUpdate::EncoderTwist(enc, val) => {
+                        println!("\n🔄 Encoder {} twist: {}", enc, val);
+                        rotation += val as i32;
+                        last_activity = std::time::Instant::now();
+                        changed = true;
+                    }
+                    _ => {}
                 }
-                
-                if changed {
-                    draw_ui(face_left, face_right, dial_pressed, rotation);
-                }
             }
-            _ => {}
+            
+            if changed {
+                draw_ui(face_left, face_right, dial_pressed, rotation);
+            }
         }
         
         // Reset rotation display after inactivity
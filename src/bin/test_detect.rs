@@ -1,5 +1,5 @@
-/// Quick test binary to check if the N1 device is detected
-/// Run with: cargo run --bin test_detect
+//! Quick test binary to check if the N1 device is detected
+//! Run with: cargo run --bin test_detect
 
 use mirajazz::device::{list_devices, DeviceQuery};
 
@@ -94,12 +94,11 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     let mut found_any = false;
     
     for (query, name) in SUPPORTED_QUERIES.iter() {
-        if let Ok(devices) = list_devices(&[query.clone()]).await {
-            if !devices.is_empty() {
+        if let Ok(devices) = list_devices(std::slice::from_ref(query)).await
+            && !devices.is_empty() {
                 found_any = true;
                 println!("✅ {} - found {} device(s)", name, devices.len());
             }
-        }
     }
     
     if !found_any {
@@ -1,4 +1,4 @@
-/// Simple encoder test - traces state vector initialization
+//! Simple encoder test - traces state vector initialization
 use mirajazz::{
     device::{list_devices, Device, DeviceQuery},
     state::DeviceStateUpdate,
@@ -6,36 +6,8 @@ use mirajazz::{
     types::DeviceInput,
 };
 
-const N1_KEY_COUNT: usize = 18;
-
-fn device_to_opendeck_n1(key: usize) -> usize {
-    match key {
-        16 => 0, 17 => 1, 18 => 2,
-        1..=15 => key + 2,
-        _ => key.saturating_sub(1),
-    }
-}
-
-fn read_button_states(states: &[u8], key_count: usize) -> Vec<bool> {
-    let mut bools = vec![];
-    for i in 0..key_count {
-        bools.push(states.get(i + 1).copied().unwrap_or(0) != 0);
-    }
-    bools
-}
-
-fn read_button_press_n1(input: u8, state: u8) -> Result<DeviceInput, MirajazzError> {
-    let mut button_states = vec![0x01];
-    button_states.extend(vec![0u8; N1_KEY_COUNT + 1]);
-    if input == 0 {
-        return Ok(DeviceInput::ButtonStateChange(read_button_states(&button_states, N1_KEY_COUNT)));
-    }
-    let pressed_index: usize = device_to_opendeck_n1(input as usize);
-    if pressed_index < N1_KEY_COUNT {
-        button_states[pressed_index + 1] = state;
-    }
-    Ok(DeviceInput::ButtonStateChange(read_button_states(&button_states, N1_KEY_COUNT)))
-}
+// Button states from the key table of the plugin
+use opendeck_ajazz_n1::keymap::read_button_press_n1;
 
 pub fn process_input_n1(input: u8, state: u8) -> Result<DeviceInput, MirajazzError> {
     match input {
@@ -86,27 +58,24 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     
     let start = std::time::Instant::now();
     while start.elapsed().as_secs() < 5 {
-        match tokio::time::timeout(
+        if let Ok(Ok(updates)) = tokio::time::timeout(
             tokio::time::Duration::from_millis(100),
             reader.read(None)
         ).await {
-            Ok(Ok(updates)) => {
-                for update in updates {
-                    match update {
-                        DeviceStateUpdate::EncoderDown(enc) => {
-                            println!("✅ ENCODER DOWN: encoder={}", enc);
-                        }
-                        DeviceStateUpdate::EncoderUp(enc) => {
-                            println!("✅ ENCODER UP: encoder={}", enc);
-                        }
-                        DeviceStateUpdate::EncoderTwist(enc, val) => {
-                            println!("✅ ENCODER TWIST: encoder={} value={}", enc, val);
-                        }
-                        _ => {}
+            for update in updates {
+                match update {
+                    DeviceStateUpdate::EncoderDown(enc) => {
+                        println!("✅ ENCODER DOWN: encoder={}", enc);
+                    }
+                    DeviceStateUpdate::EncoderUp(enc) => {
+                        println!("✅ ENCODER UP: encoder={}", enc);
+                    }
+                    DeviceStateUpdate::EncoderTwist(enc, val) => {
+                        println!("✅ ENCODER TWIST: encoder={} value={}", enc, val);
                     }
+                    _ => {}
                 }
             }
-            _ => {}
         }
     }
     
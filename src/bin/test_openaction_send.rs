@@ -1,5 +1,5 @@
-/// Test that OpenAction device_plugin functions work correctly
-/// This simulates what the main plugin does but with detailed error checking
+//! Test that OpenAction device_plugin functions work correctly
+//! This simulates what the main plugin does but with detailed error checking
 
 use mirajazz::{
     device::{list_devices, Device, DeviceQuery},
@@ -9,37 +9,10 @@ use mirajazz::{
 };
 use std::sync::Mutex;
 
-const N1_KEY_COUNT: usize = 18;
-static ENCODER_STATES: Mutex<[bool; 3]> = Mutex::new([false, false, false]);
-
-fn device_to_opendeck_n1(key: usize) -> usize {
-    match key {
-        16 => 0, 17 => 1, 18 => 2,
-        1..=15 => key + 2,
-        _ => key.saturating_sub(1),
-    }
-}
+// Button states from the key table of the plugin
+use opendeck_ajazz_n1::keymap::read_button_press_n1;
 
-fn read_button_press_n1(input: u8, state: u8) -> Result<DeviceInput, MirajazzError> {
-    let mut button_states = vec![0x01];
-    button_states.extend(vec![0u8; N1_KEY_COUNT + 1]);
-    if input == 0 {
-        return Ok(DeviceInput::ButtonStateChange(read_button_states(&button_states, N1_KEY_COUNT)));
-    }
-    let pressed_index: usize = device_to_opendeck_n1(input as usize);
-    if pressed_index < N1_KEY_COUNT {
-        button_states[pressed_index + 1] = state;
-    }
-    Ok(DeviceInput::ButtonStateChange(read_button_states(&button_states, N1_KEY_COUNT)))
-}
-
-fn read_button_states(states: &[u8], key_count: usize) -> Vec<bool> {
-    let mut bools = vec![];
-    for i in 0..key_count {
-        bools.push(states.get(i + 1).copied().unwrap_or(0) != 0);
-    }
-    bools
-}
+static ENCODER_STATES: Mutex<[bool; 3]> = Mutex::new([false, false, false]);
 
 pub fn process_input_n1(input: u8, state: u8) -> Result<DeviceInput, MirajazzError> {
     match input {
@@ -1,10 +1,10 @@
-/// X11 Input Injection Test for N1
-/// Injects fake keypresses/mouse events when you use the N1 dial/buttons
-/// This lets you test with xev, xinput, or any X11 tool
-/// Run with: cargo run --bin x11_inject
-///
-/// THEN in another terminal, run: xev
-/// and watch the events appear when you press N1 buttons!
+//! X11 Input Injection Test for N1
+//! Injects fake keypresses/mouse events when you use the N1 dial/buttons
+//! This lets you test with xev, xinput, or any X11 tool
+//! Run with: cargo run --bin x11_inject
+//!
+//! THEN in another terminal, run: xev
+//! and watch the events appear when you press N1 buttons!
 
 use mirajazz::{
     device::{list_devices, Device, DeviceQuery},
@@ -14,42 +14,15 @@ use mirajazz::{
 use std::process::Command;
 use mirajazz::types::DeviceInput;
 
-const N1_KEY_COUNT: usize = 18;
+// Button states from the key table of the plugin
+use opendeck_ajazz_n1::keymap::read_button_press_n1;
+
 const N1_FACE_BUTTON_LEFT: u8 = 30;
 const N1_FACE_BUTTON_RIGHT: u8 = 31;
 const N1_DIAL_PRESS: u8 = 35;
 const N1_DIAL_ROTATE_CCW: u8 = 50;
 const N1_DIAL_ROTATE_CW: u8 = 51;
 
-fn device_to_opendeck_n1(key: usize) -> usize {
-    match key {
-        16 => 0, 17 => 1, 18 => 2,
-        1..=15 => key + 2,
-        _ => key.saturating_sub(1),
-    }
-}
-
-fn read_button_states(states: &[u8], key_count: usize) -> Vec<bool> {
-    let mut bools = vec![];
-    for i in 0..key_count {
-        bools.push(states.get(i + 1).copied().unwrap_or(0) != 0);
-    }
-    bools
-}
-
-fn read_button_press_n1(input: u8, state: u8) -> Result<DeviceInput, MirajazzError> {
-    let mut button_states = vec![0x01];
-    button_states.extend(vec![0u8; N1_KEY_COUNT + 1]);
-    if input == 0 {
-        return Ok(DeviceInput::ButtonStateChange(read_button_states(&button_states, N1_KEY_COUNT)));
-    }
-    let pressed_index: usize = device_to_opendeck_n1(input as usize);
-    if pressed_index < N1_KEY_COUNT {
-        button_states[pressed_index + 1] = state;
-    }
-    Ok(DeviceInput::ButtonStateChange(read_button_states(&button_states, N1_KEY_COUNT)))
-}
-
 pub fn process_input_n1(input: u8, state: u8) -> Result<DeviceInput, MirajazzError> {
     match input {
         1..=18 => read_button_press_n1(input, state),
@@ -94,7 +67,7 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     println!("═══════════════════════════════════════════════════════════════\n");
     
     // Check for xdotool
-    if Command::new("which").arg("xdotool").output()?.status.success() == false {
+    if !Command::new("which").arg("xdotool").output()?.status.success() {
         println!("❌ xdotool not found! Install it with:");
         println!("   sudo apt install xdotool   # Debian/Ubuntu");
         println!("   sudo dnf install xdotool   # Fedora");
@@ -0,0 +1,268 @@
+use std::{
+    collections::{HashMap, VecDeque},
+    fmt,
+    hash::{DefaultHasher, Hash, Hasher},
+    sync::LazyLock,
+};
+
+use image::DynamicImage;
+
+use tokio::sync::Mutex;
+
+/// How many images are remembered per key
+const HISTORY_LEN: usize = 8;
+
+/// History of images sent to each key, `None` entries mean the key was cleared
+type KeyHistory = HashMap<u8, VecDeque<Option<String>>>;
+
+/// Image history per device, keyed by device id
+static HISTORY: LazyLock<Mutex<HashMap<String, KeyHistory>>> =
+    LazyLock::new(|| Mutex::new(HashMap::new()));
+
+/// Hash of the image last uploaded to each key, keyed by device id and physical position
+///
+/// Reflects what the device actually displays, so it's wiped whenever that becomes unknown
+static SHOWN: LazyLock<Mutex<HashMap<String, HashMap<u8, u64>>>> =
+    LazyLock::new(|| Mutex::new(HashMap::new()));
+
+/// Remembers image as shown on the key, returns false if the key already shows exactly this image
+pub async fn mark_shown(id: &str, position: u8, image: &DynamicImage) -> bool {
+    let mut hasher = DefaultHasher::new();
+    (image.width(), image.height(), image.color()).hash(&mut hasher);
+    image.as_bytes().hash(&mut hasher);
+    let hash = hasher.finish();
+
+    SHOWN
+        .lock()
+        .await
+        .entry(id.to_string())
+        .or_default()
+        .insert(position, hash)
+        != Some(hash)
+}
+
+/// Forgets what the key shows, after it got cleared or written past [mark_shown]
+pub async fn forget_shown(id: &str, position: u8) {
+    if let Some(keys) = SHOWN.lock().await.get_mut(id) {
+        keys.remove(&position);
+    }
+}
+
+/// Forgets what every key of the device shows, e.g. after clearing it or reconnecting
+pub async fn forget_all_shown(id: &str) {
+    SHOWN.lock().await.remove(id);
+}
+
+/// Remembers image (as data url) that was shown on the key, or `None` if key got cleared
+pub async fn record(id: &str, position: u8, image: Option<String>) {
+    let mut history = HISTORY.lock().await;
+    let entries = history
+        .entry(id.to_string())
+        .or_default()
+        .entry(position)
+        .or_default();
+
+    entries.push_back(image);
+
+    while entries.len() > HISTORY_LEN {
+        entries.pop_front();
+    }
+}
+
+/// Remembers that every key of the device got cleared
+pub async fn record_clear_all(id: &str) {
+    let positions: Vec<u8> = match HISTORY.lock().await.get(id) {
+        Some(keys) => keys.keys().copied().collect(),
+        None => return,
+    };
+
+    for position in positions {
+        record(id, position, None).await;
+    }
+}
+
+/// Drops the current image of the key and returns the one that was shown before it
+///
+/// Outer `None` means there is nothing to revert to
+pub async fn revert(id: &str, position: u8) -> Option<Option<String>> {
+    let mut history = HISTORY.lock().await;
+    let entries = history.get_mut(id)?.get_mut(&position)?;
+
+    if entries.len() < 2 {
+        return None;
+    }
+
+    entries.pop_back();
+
+    entries.back().cloned()
+}
+
+/// Returns image currently shown on the key, `None` if there is none or key was cleared
+pub async fn current(id: &str, position: u8) -> Option<String> {
+    HISTORY
+        .lock()
+        .await
+        .get(id)?
+        .get(&position)?
+        .back()
+        .cloned()
+        .flatten()
+}
+
+/// Returns images currently shown on the device, skipping cleared keys
+pub async fn current_all(id: &str) -> Vec<(u8, String)> {
+    let history = HISTORY.lock().await;
+    let Some(keys) = history.get(id) else {
+        return vec![];
+    };
+
+    let mut images: Vec<(u8, String)> = keys
+        .iter()
+        .filter_map(|(position, entries)| Some((*position, entries.back()?.clone()?)))
+        .collect();
+
+    images.sort_by_key(|(position, _)| *position);
+
+    images
+}
+
+/// Decoded image budget in MiB when `OPENDECK_N1_DECODE_CACHE_MB` isn't set
+const DEFAULT_DECODED_BUDGET_MB: usize = 32;
+
+/// Memory decoded images may take, `OPENDECK_N1_DECODE_CACHE_MB` at startup, 0 turns the cache off
+static DECODED_BUDGET: LazyLock<usize> = LazyLock::new(|| {
+    let megabytes = match std::env::var("OPENDECK_N1_DECODE_CACHE_MB") {
+        Err(_) => DEFAULT_DECODED_BUDGET_MB,
+        Ok(value) => value.parse::<usize>().unwrap_or_else(|_| {
+            log::warn!(
+                "Invalid OPENDECK_N1_DECODE_CACHE_MB {}, using {}",
+                value,
+                DEFAULT_DECODED_BUDGET_MB
+            );
+            DEFAULT_DECODED_BUDGET_MB
+        }),
+    };
+
+    megabytes * 1024 * 1024
+});
+
+/// Counters of the decoded image cache
+#[derive(Debug, Clone, Copy, Default)]
+pub struct DecodedStats {
+    pub hits: u64,
+    pub misses: u64,
+    pub evictions: u64,
+    pub entries: usize,
+    /// Pixel data held right now
+    pub bytes: usize,
+    pub budget: usize,
+}
+
+impl fmt::Display for DecodedStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "hits={} misses={} evictions={} entries={} bytes={}/{}",
+            self.hits, self.misses, self.evictions, self.entries, self.bytes, self.budget
+        )
+    }
+}
+
+/// Decoded image and when it was last used, in [DecodedCache::clock] ticks
+struct DecodedEntry {
+    image: DynamicImage,
+    used: u64,
+}
+
+/// Least recently used images go first once the budget is exceeded
+#[derive(Default)]
+struct DecodedCache {
+    /// Keyed by hash of the data url
+    entries: HashMap<u64, DecodedEntry>,
+    clock: u64,
+    stats: DecodedStats,
+}
+
+/// Images decoded from data urls, so redraws don't decode the same image again
+static DECODED: LazyLock<Mutex<DecodedCache>> =
+    LazyLock::new(|| Mutex::new(DecodedCache::default()));
+
+fn data_url_hash(data_url: &str) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    data_url.hash(&mut hasher);
+    hasher.finish()
+}
+
+/// Returns image decoded from the data url earlier, if it's still cached
+pub async fn decoded(data_url: &str) -> Option<DynamicImage> {
+    let mut cache = DECODED.lock().await;
+    cache.clock += 1;
+
+    let clock = cache.clock;
+    let image = cache
+        .entries
+        .get_mut(&data_url_hash(data_url))
+        .map(|entry| {
+            entry.used = clock;
+            entry.image.clone()
+        });
+
+    match image {
+        Some(_) => cache.stats.hits += 1,
+        None => cache.stats.misses += 1,
+    }
+
+    image
+}
+
+/// Remembers image decoded from the data url, evicting least recently used ones to stay in budget
+///
+/// Images larger than the whole budget aren't kept
+pub async fn store_decoded(data_url: &str, image: &DynamicImage) {
+    let budget = *DECODED_BUDGET;
+    let size = image.as_bytes().len();
+
+    if size > budget {
+        return;
+    }
+
+    let mut cache = DECODED.lock().await;
+    cache.clock += 1;
+
+    let entry = DecodedEntry {
+        image: image.clone(),
+        used: cache.clock,
+    };
+
+    if let Some(previous) = cache.entries.insert(data_url_hash(data_url), entry) {
+        cache.stats.bytes -= previous.image.as_bytes().len();
+    }
+
+    cache.stats.bytes += size;
+
+    while cache.stats.bytes > budget {
+        let Some(oldest) = cache
+            .entries
+            .iter()
+            .min_by_key(|(_, entry)| entry.used)
+            .map(|(hash, _)| *hash)
+        else {
+            break;
+        };
+
+        let evicted = cache.entries.remove(&oldest).expect("hash was just found");
+        cache.stats.bytes -= evicted.image.as_bytes().len();
+        cache.stats.evictions += 1;
+    }
+}
+
+/// Returns counters of the decoded image cache
+pub async fn decoded_stats() -> DecodedStats {
+    let cache = DECODED.lock().await;
+
+    DecodedStats {
+        entries: cache.entries.len(),
+        budget: *DECODED_BUDGET,
+        ..cache.stats
+    }
+}
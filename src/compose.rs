@@ -0,0 +1,164 @@
+use std::{collections::HashMap, sync::LazyLock};
+
+use image::DynamicImage;
+use tokio::sync::Mutex;
+
+use crate::{
+    cache, delivery,
+    device::{recompose_keys, set_device_images},
+    keylock,
+    overlay::{self, Badge},
+    placeholder, reaction,
+};
+
+/// Layers a key image is composed of, bottom to top
+///
+/// Each layer is kept on its own, so changing one redraws the key without touching the others:
+/// a badge or a flash doesn't decode, fit and tone the key image again.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Layer {
+    /// Image OpenDeck sent, or the placeholder of a cleared key, see [Base]
+    Base,
+    /// Title text, shown on keys without an image of their own
+    Title,
+    /// Status badge in the top right corner, see [overlay::Badge]
+    Badge,
+    /// Transient indicators on top of everything: lock glyph, refused press border, latency flash
+    Overlay,
+}
+
+/// Bottom layer of a key
+#[derive(Debug, Clone)]
+pub enum Base {
+    /// Image sent for the key
+    Image(DynamicImage),
+    /// Key was cleared, it shows its placeholder (if it has one) unless it has a title
+    Empty(Option<DynamicImage>),
+}
+
+impl Base {
+    /// Applies `f` to the image of the layer, if it has one
+    pub fn map(self, f: impl FnOnce(DynamicImage) -> DynamicImage) -> Self {
+        match self {
+            Self::Image(image) => Self::Image(f(image)),
+            Self::Empty(placeholder) => Self::Empty(placeholder.map(f)),
+        }
+    }
+}
+
+/// Base layer as rendered (fitted, themed and toned) and the content size it was rendered at
+type RenderedBase = (Base, (usize, usize));
+
+/// Base layer of every key as last rendered, by device id and OpenDeck position
+static BASES: LazyLock<Mutex<HashMap<(String, u8), RenderedBase>>> =
+    LazyLock::new(|| Mutex::new(HashMap::new()));
+
+/// Remembers the rendered base layer of the key, so the layers above can be redrawn on it
+pub async fn store_base(id: &str, position: u8, base: Base, size: (usize, usize)) {
+    BASES
+        .lock()
+        .await
+        .insert((id.to_string(), position), (base, size));
+}
+
+/// Forgets the base layer of the key, after something was written to it past the compositor
+pub async fn forget_base(id: &str, position: u8) {
+    BASES.lock().await.remove(&(id.to_string(), position));
+}
+
+/// Returns the rendered base layer of the key, `None` if it's unknown or was rendered for another size
+pub async fn base(id: &str, position: u8, size: (usize, usize)) -> Option<Base> {
+    BASES
+        .lock()
+        .await
+        .get(&(id.to_string(), position))
+        .filter(|(_, rendered)| *rendered == size)
+        .map(|(base, _)| base.clone())
+}
+
+/// Layers drawn above the base of a key, as they are right now
+#[derive(Debug, Default)]
+pub struct Layers {
+    title: Option<String>,
+    badge: Option<Badge>,
+    locked: bool,
+    failed: bool,
+    lit: bool,
+}
+
+impl Layers {
+    /// Looks up the layers of the key at OpenDeck position, they follow the key across mapping profiles
+    pub async fn of(id: &str, position: u8) -> Self {
+        Self {
+            title: placeholder::title(id, position).await,
+            badge: overlay::badge(id, position).await,
+            locked: keylock::is_locked(id).await,
+            failed: delivery::is_flashing(id, position).await,
+            lit: reaction::is_lit(id, position).await,
+        }
+    }
+
+    /// Whether anything would be drawn on a key without image or placeholder
+    fn is_empty(&self) -> bool {
+        self.title.is_none() && self.badge.is_none() && !self.locked && !self.failed && !self.lit
+    }
+}
+
+/// Draws the layers on the base, at the content size of the key
+///
+/// Returns `None` for a cleared key without placeholder that has nothing else to show either,
+/// the key is cleared then instead of showing a black image.
+pub fn compose(base: Base, layers: &Layers, size: (usize, usize)) -> Option<DynamicImage> {
+    let image = match (base, &layers.title) {
+        (Base::Image(image), _) => image,
+        // Drawn at the content size, so the bitmap font isn't resampled
+        (Base::Empty(_), Some(title)) => placeholder::render_title(title, size),
+        (Base::Empty(Some(placeholder)), None) => placeholder,
+        (Base::Empty(None), None) if layers.is_empty() => return None,
+        (Base::Empty(None), None) => DynamicImage::new_rgb8(size.0 as u32, size.1 as u32),
+    };
+
+    let image = overlay::apply(layers.badge.as_ref(), image);
+    let image = if layers.locked {
+        overlay::apply_lock(image)
+    } else {
+        image
+    };
+    let image = if layers.failed {
+        overlay::apply_error_border(image)
+    } else {
+        image
+    };
+
+    // The flash covers everything
+    Some(if layers.lit {
+        overlay::apply_flash(image)
+    } else {
+        image
+    })
+}
+
+/// Redraws a layer of the keys (OpenDeck positions), returns false if there is no such device
+///
+/// Layers above the base are drawn again on the stored base. A changed base, or one that isn't
+/// stored, is rendered again from the cached image of the key.
+pub async fn invalidate(id: &str, positions: &[u8], layer: Layer) -> bool {
+    log::debug!(
+        "Redrawing {:?} layer of keys {:?} of {}",
+        layer,
+        positions,
+        id
+    );
+
+    if layer != Layer::Base {
+        return recompose_keys(id, positions).await;
+    }
+
+    for &position in positions {
+        if !set_device_images(id, &[position], cache::current(id, position).await).await {
+            return false;
+        }
+    }
+
+    true
+}
@@ -0,0 +1,90 @@
+use std::{
+    collections::{HashMap, HashSet},
+    sync::LazyLock,
+    time::Duration,
+};
+
+use tokio::sync::Mutex;
+
+use crate::{
+    compose::{self, Layer},
+    watcher::device_tracker,
+};
+
+/// Events in a row OpenDeck has to refuse before presses get flagged on the key
+const FAILURE_THRESHOLD: u32 = 3;
+
+/// How long the pressed key shows the red border
+const FLASH_DURATION: Duration = Duration::from_secs(1);
+
+/// Events OpenDeck refused in a row, per device
+static FAILURES: LazyLock<Mutex<HashMap<String, u32>>> =
+    LazyLock::new(|| Mutex::new(HashMap::new()));
+
+/// Keys showing the border, by device id and OpenDeck position
+static FLASHING: LazyLock<Mutex<HashSet<(String, u8)>>> =
+    LazyLock::new(|| Mutex::new(HashSet::new()));
+
+/// Returns true while the key shows that a press didn't reach OpenDeck
+pub async fn is_flashing(id: &str, position: u8) -> bool {
+    FLASHING.lock().await.contains(&(id.to_string(), position))
+}
+
+/// Records whether OpenDeck accepted an event, `pressed` is the key (OpenDeck position) of a key press
+///
+/// Once events keep failing, every further press flashes a red border on its key, so presses
+/// don't silently do nothing while the connection to OpenDeck is broken
+pub async fn record(id: &str, pressed: Option<u8>, accepted: bool) {
+    let failures = {
+        let mut failures = FAILURES.lock().await;
+
+        if accepted {
+            failures.remove(id);
+            return;
+        }
+
+        let count = failures.entry(id.to_string()).or_default();
+        *count += 1;
+        *count
+    };
+
+    if failures < FAILURE_THRESHOLD {
+        return;
+    }
+
+    if let Some(position) = pressed {
+        flash(id, position).await;
+    }
+}
+
+async fn flash(id: &str, position: u8) {
+    if !FLASHING.lock().await.insert((id.to_string(), position)) {
+        return;
+    }
+
+    let Some(tracker) = device_tracker(id).await else {
+        FLASHING.lock().await.remove(&(id.to_string(), position));
+        return;
+    };
+
+    log::warn!(
+        "OpenDeck keeps refusing events of {}, flagging key {}",
+        id,
+        position
+    );
+
+    redraw(id, position).await;
+
+    let id = id.to_string();
+
+    tracker.spawn(async move {
+        tokio::time::sleep(FLASH_DURATION).await;
+
+        FLASHING.lock().await.remove(&(id.clone(), position));
+        redraw(&id, position).await;
+    });
+}
+
+async fn redraw(id: &str, position: u8) {
+    compose::invalidate(id, &[position], Layer::Overlay).await;
+}
@@ -0,0 +1,86 @@
+use serde_json::{Value, json};
+
+use crate::mappings::{
+    DEVICE_NAMESPACE, Kind, Presentation, get_image_format_for_key,
+};
+
+/// Runs `opendeck-ajazz-n1 descriptors`, printing every supported device as JSON
+///
+/// Meant for tools that need the supported hardware without duplicating it (udev rule
+/// generators, docs), so the output only grows: fields are added, never renamed.
+pub fn run() {
+    println!(
+        "{}",
+        serde_json::to_string_pretty(&descriptors()).expect("descriptors serialize")
+    );
+}
+
+fn descriptors() -> Value {
+    json!({
+        "plugin": env!("CARGO_PKG_NAME"),
+        "version": env!("CARGO_PKG_VERSION"),
+        "namespace": DEVICE_NAMESPACE,
+        "devices": Kind::ALL.iter().map(descriptor).collect::<Vec<_>>(),
+    })
+}
+
+fn descriptor(kind: &Kind) -> Value {
+    let (vid, pid, usage_page, usage_id) = kind.hid_ids();
+    let (rows, cols) = kind.layout();
+    let display = kind.display_format();
+
+    let keys: Vec<Value> = (0..kind.key_count() as u8)
+        .map(|position| {
+            let format = get_image_format_for_key(kind, position);
+
+            json!({
+                "position": position,
+                "width": format.size.0,
+                "height": format.size.1,
+            })
+        })
+        .collect();
+
+    let presentations: Vec<Value> = [Presentation::Grid, Presentation::Strip]
+        .iter()
+        .filter(|presentation| presentation.supported_by(kind))
+        .map(|presentation| {
+            let (rows, cols, encoders) = presentation.layout(kind);
+
+            json!({
+                "name": presentation,
+                "rows": rows,
+                "cols": cols,
+                "encoders": encoders,
+            })
+        })
+        .collect();
+
+    json!({
+        "kind": format!("{:?}", kind),
+        "name": kind.human_name(),
+        "vendor_id": format!("{:04x}", vid),
+        "product_id": format!("{:04x}", pid),
+        "usage_page": usage_page,
+        "usage_id": usage_id,
+        "protocol_version": kind.protocol_version(),
+        "rows": rows,
+        "cols": cols,
+        "key_count": kind.key_count(),
+        "encoder_count": kind.encoder_count(),
+        "color_order": kind.color_order(),
+        "rotation": format!("{:?}", display.rotation),
+        "mirror": format!("{:?}", display.mirror),
+        "keys": keys,
+        "lcd_strip": kind
+            .lcd_strip()
+            .iter()
+            .map(|(position, left)| json!({ "position": position, "left": left }))
+            .collect::<Vec<_>>(),
+        "dial_screens": (0..kind.encoder_count() as u8)
+            .filter_map(|encoder| kind.dial_screen(encoder).map(|key| (encoder, key)))
+            .map(|(encoder, key)| json!({ "encoder": encoder, "key": key }))
+            .collect::<Vec<_>>(),
+        "presentations": presentations,
+    })
+}
@@ -1,49 +1,176 @@
-use std::time::Duration;
+use std::{
+    collections::HashMap,
+    fmt,
+    io::Cursor,
+    sync::{LazyLock, atomic::Ordering},
+    time::{Duration, Instant},
+};
 
+use async_hid::HidError;
 use data_url::DataUrl;
-use image::load_from_memory_with_format;
-use mirajazz::{device::Device, error::MirajazzError, state::DeviceStateUpdate};
+use image::{DynamicImage, ImageDecoder, ImageReader, metadata::Orientation};
+use mirajazz::{error::MirajazzError, state::DeviceStateUpdate};
 use openaction::global_events::SetImageEvent;
-use tokio::time::interval;
+use tokio::{
+    sync::{Mutex, Notify, mpsc, oneshot},
+    task::JoinHandle,
+};
 use tokio_util::sync::CancellationToken;
 
 use crate::{
-    DEVICES, TOKENS,
+    DEVICES, SHUTTING_DOWN, TOKENS, animation, assets,
+    backend::{self, DeckDevice, encode_key_image},
+    cache,
+    compose::{self, Base},
+    delivery, dnd, face,
     inputs::opendeck_to_device,
-    mappings::{
-        CandidateDevice, Kind,
-        get_image_format_for_key,
-    },
+    keylock, latency,
+    longpress::LongPress,
+    mirror::{self, Route},
+    mappings::{CandidateDevice, ColorOrder, Kind, Quirks, get_image_format_for_key},
+    placeholder, reaction,
+    reader::{InputQueue, InputReader, InputUpdate, Pushed},
+    scale, schedule,
+    settings, stats, theme,
+    twist::TwistVelocity,
+    watcher::{self, device_tracker},
 };
 
+#[cfg(all(unix, feature = "video"))]
+use crate::video;
+
+/// Place of a device in the registration order
+///
+/// Devices found together at startup initialize in parallel, but register one after another,
+/// so OpenDeck lists them in the same order every run. Default turn doesn't wait for anyone.
+#[derive(Debug, Default)]
+pub struct RegistrationTurn {
+    wait: Option<oneshot::Receiver<()>>,
+    done: Option<oneshot::Sender<()>>,
+}
+
+impl RegistrationTurn {
+    /// Returns `count` turns, each waiting for the previous one to finish
+    pub fn chain(count: usize) -> Vec<Self> {
+        let mut turns: Vec<Self> = (0..count).map(|_| Self::default()).collect();
+
+        for index in 1..count {
+            let (tx, rx) = oneshot::channel();
+            turns[index - 1].done = Some(tx);
+            turns[index].wait = Some(rx);
+        }
+
+        turns
+    }
+
+    /// Waits until the previous device registered, or gave up on it
+    async fn wait(&mut self) {
+        if let Some(wait) = self.wait.take() {
+            // Error means the previous device task finished without registering, that's fine
+            wait.await.ok();
+        }
+    }
+}
+
+/// Registers the device with OpenDeck, using layout of its presentation
+pub async fn register(id: &str, kind: &Kind) -> openaction::OpenActionResult<()> {
+    let presentation = settings::presentation_for(id, kind).await;
+    let (rows, cols, encoder_count) = presentation.layout(kind);
+    // Held dial and long press are the encoders after all others, see [Presentation::opendeck_update]
+    let encoder_count = encoder_count
+        + settings::hold_rotate_for(id).await as usize
+        + settings::dial_long_press_for(id).await.is_some() as usize;
+
+    log::info!(
+        "Registering device {} as {:?}: {} rows, {} cols, {} encoders",
+        id,
+        presentation,
+        rows,
+        cols,
+        encoder_count
+    );
+
+    openaction::device_plugin::register_device(
+        id.to_string(),
+        kind.human_name(),
+        rows as u8,
+        cols as u8,
+        encoder_count as u8,
+        0,
+    )
+    .await?;
+
+    log::info!("Device registered successfully with {} encoders", encoder_count);
+
+    Ok(())
+}
+
 /// Initializes a device and listens for events
-pub async fn device_task(candidate: CandidateDevice, token: CancellationToken) {
+pub async fn device_task(
+    candidate: CandidateDevice,
+    token: CancellationToken,
+    mut turn: RegistrationTurn,
+) {
     log::info!("Running device task for {:?}", candidate);
 
     // Wrap in a closure so we can use `?` operator
-    let device = async || -> Result<Device, MirajazzError> {
+    let device = async {
         let device = connect(&candidate).await?;
 
-        // N1 requires software mode to be set for control
-        log::info!("Setting device mode to 3 (software mode)...");
-        device.set_mode(3).await?;  // 3 = Software mode
-        log::info!("Device mode set successfully, waiting 100ms...");
-        tokio::time::sleep(tokio::time::Duration::from_millis(100)).await;
+        if let Some(mode) = candidate.kind.software_mode() {
+            log::info!("Setting device mode to {} (software mode)...", mode);
+            device.set_mode(mode).await?;
+            log::info!("Device mode set successfully, waiting 100ms...");
+            tokio::time::sleep(tokio::time::Duration::from_millis(100)).await;
+        }
+
+        // Protocol has no way to read back current brightness, so the persisted value
+        // is the source of truth. Applying it here means OpenDeck's own brightness event
+        // after registration usually turns into a no-op instead of a visible flash.
+        let brightness = target_brightness(&candidate.id).await;
+        log::info!("Restoring brightness to {}...", brightness);
+        apply_brightness(&candidate.id, device.as_ref(), brightness).await?;
+
+        // What the keys show after a reconnect is unknown, everything gets uploaded again
+        cache::forget_all_shown(&candidate.id).await;
+
+        let cached: Vec<u8> = cache::current_all(&candidate.id)
+            .await
+            .into_iter()
+            .map(|(position, _)| position)
+            .collect();
+
+        if cached.is_empty() {
+            log::info!("Clearing all button images...");
+            device.clear_all_button_images().await?;
+        } else {
+            // Keys with cached images keep their old content until it's replayed after
+            // registration, clearing them first would flash black on quick reconnects
+            log::info!("Clearing button images that aren't cached...");
+            let profile = settings::profile_for(&candidate.id).await;
+
+            for position in 0..device.key_count() as u8 {
+                if !cached.contains(&position) {
+                    let position = profile.apply(&candidate.kind, position);
+                    device
+                        .clear_button_image(opendeck_to_device(&candidate.kind, position))
+                        .await?;
+                }
+            }
+        }
 
-        log::info!("Setting brightness to 50...");
-        device.set_brightness(50).await?;
-        log::info!("Clearing all button images...");
-        device.clear_all_button_images().await?;
         log::info!("Flushing device...");
         device.flush().await?;
         log::info!("Device initialization complete");
 
         Ok(device)
-    }()
+    }
     .await;
 
-    let device: Device = match device {
+    let device: Box<dyn DeckDevice> = match device {
         Ok(device) => device,
+        // Nothing was registered yet, the watcher tries again until udev rules are in place
+        Err(err) if is_permission_denied(&err) => return,
         Err(err) => {
             handle_error(&candidate.id, err).await;
 
@@ -56,33 +183,32 @@ pub async fn device_task(candidate: CandidateDevice, token: CancellationToken) {
         }
     };
 
-    log::info!("Registering device {}", candidate.id);
-    let (rows, cols) = candidate.kind.layout();
-    let encoder_count = candidate.kind.encoder_count() as u8;
-    log::info!("Device layout: {} rows, {} cols, {} encoders", rows, cols, encoder_count);
-    
-    if let Err(e) = openaction::device_plugin::register_device(
-        candidate.id.clone(),
-        candidate.kind.human_name(),
-        rows as u8,
-        cols as u8,
-        encoder_count,
-        0,
-    ).await {
+    turn.wait().await;
+
+    if let Err(e) = register(&candidate.id, &candidate.kind).await {
         log::error!("Failed to register device: {}", e);
         return;
     }
-    log::info!("Device registered successfully with {} encoders", encoder_count);
+
+    // Next device can register now
+    drop(turn);
 
     DEVICES.write().await.insert(candidate.id.clone(), device);
+    KEEPALIVE_WAKE.notify_one();
+
+    let image_worker = start_image_worker(&candidate.id, &token).await;
+
+    // Image history is kept by device id across reconnects, OpenDeck often doesn't resend
+    // images until the page changes, so the keys would stay blank otherwise
+    redraw_from_cache(&candidate.id).await;
+    show_splash(&candidate.id, &token).await;
 
     tokio::select! {
         result = device_events_task(&candidate) => {
             log::error!("device_events_task exited with: {:?}", result);
         },
-        result = keepalive_task(&candidate) => {
-            log::error!("keepalive_task exited with: {:?}", result);
-        },
+        _ = dnd::dnd_task(candidate.id.clone(), token.clone()) => {},
+        _ = theme::theme_task(candidate.id.clone(), token.clone()) => {},
         _ = token.cancelled() => {
             log::info!("Cancellation token triggered, shutting down");
         }
@@ -90,22 +216,246 @@ pub async fn device_task(candidate: CandidateDevice, token: CancellationToken) {
 
     log::info!("Shutting down device {:?}", candidate);
 
+    // Half written uploads leave the panel garbled, they have to finish before anything else
+    if let Some(image_worker) = image_worker {
+        image_worker.drain(&candidate.id).await;
+    }
+
     if let Some(device) = DEVICES.read().await.get(&candidate.id) {
+        // Disconnected devices are already gone from the list, this only runs on plugin exit
+        if SHUTTING_DOWN.load(Ordering::Acquire) {
+            blank_on_exit(device.as_ref(), &candidate.id).await;
+        }
+
         device.shutdown().await.ok();
     }
 
     log::info!("Device task finished for {:?}", candidate);
 }
 
+/// How long keys OpenDeck didn't send anything for keep showing the splash
+const SPLASH_DURATION: Duration = Duration::from_secs(10);
+
+/// Queues the splash together with the device's assets, see [handle_splash]
+async fn show_splash(id: &str, token: &CancellationToken) {
+    let Some(kind) = DEVICES.read().await.get(id).map(|device| device.kind()) else {
+        return;
+    };
+
+    // Read before queueing, the worker shouldn't wait for files while holding the device
+    let assets = assets::load(id, &kind).await;
+
+    queue_image_job(id, ImageJob::Splash(assets, token.clone())).await;
+}
+
+/// Longest time a device gets to clear its keys when the plugin exits
+const EXIT_CLEAR_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// Brightness left on the device after the plugin exits
+const EXIT_BRIGHTNESS: u8 = 10;
+
+/// Clears keys and dims the device, so images of a plugin that's gone don't linger on it
+async fn blank_on_exit(device: &dyn DeckDevice, id: &str) {
+    log::info!("Clearing button images of {} before exiting...", id);
+
+    let blank = async {
+        device.clear_all_button_images().await?;
+        device.flush().await?;
+        device.set_brightness(EXIT_BRIGHTNESS).await
+    };
+
+    match tokio::time::timeout(EXIT_CLEAR_TIMEOUT, blank).await {
+        Ok(Ok(())) => {}
+        Ok(Err(e)) => log::warn!("Unable to clear {} before exiting: {}", id, e),
+        Err(_) => log::warn!("Clearing {} before exiting timed out", id),
+    }
+}
+
+/// Returns brightness the device should have right now
+///
+/// Zero while the device is blanked by "do not disturb" or a locked screen, stored brightness otherwise
+pub async fn target_brightness(id: &str) -> u8 {
+    if is_blanked(id).await {
+        return 0;
+    }
+
+    settings::brightness_for(id).await
+}
+
+/// Returns true while "do not disturb" or a locked screen keeps the device blank
+pub async fn is_blanked(id: &str) -> bool {
+    #[cfg(all(target_os = "linux", feature = "lock-blank"))]
+    if crate::lock::is_locked() {
+        return true;
+    }
+
+    dnd::is_active(id).await
+}
+
+/// Returns workarounds the device needs, found for it first and then known for its kind
+pub async fn quirks(id: &str, kind: &Kind) -> Quirks {
+    settings::quirks_for(id).await.or(kind.quirks())
+}
+
+/// Returns highest brightness the device may be set to, the lower of its limit and its quirk
+pub async fn brightness_limit(id: &str, kind: &Kind) -> u8 {
+    let limit = settings::max_brightness_for(id).await.unwrap_or(100);
+    let quirk = quirks(id, kind).await.max_brightness.unwrap_or(100);
+
+    limit.min(quirk).min(100)
+}
+
+/// Connection lost this soon after a brightness write is blamed on the write
+const BRIGHTNESS_CRASH_WINDOW: Duration = Duration::from_secs(2);
+
+/// Brightness quirks found after crashes never go below this, lower is more likely a wrong guess
+const BRIGHTNESS_QUIRK_FLOOR: u8 = 50;
+
+/// Step the brightness quirk is lowered by after every crash
+const BRIGHTNESS_QUIRK_STEP: u8 = 10;
+
+/// Last brightness written to every device and when
+static BRIGHTNESS_WRITES: LazyLock<Mutex<HashMap<String, (u8, Instant)>>> =
+    LazyLock::new(|| Mutex::new(HashMap::new()));
+
+/// Sets brightness of the device, capped at [brightness_limit]
+///
+/// Every brightness write with a known device id goes through here, so the stored value stays what
+/// was asked for and the cap can be raised again later. Waits afterwards for devices with a
+/// `brightness_delay_ms` quirk.
+pub async fn apply_brightness(
+    id: &str,
+    device: &dyn DeckDevice,
+    percent: u8,
+) -> Result<(), MirajazzError> {
+    let kind = device.kind();
+    let limit = brightness_limit(id, &kind).await;
+
+    if percent > limit {
+        log::debug!("Capping brightness of {} from {} to {}", id, percent, limit);
+    }
+
+    let percent = percent.min(limit);
+
+    BRIGHTNESS_WRITES
+        .lock()
+        .await
+        .insert(id.to_string(), (percent, Instant::now()));
+
+    device.set_brightness(percent).await?;
+
+    if let Some(delay) = quirks(id, &kind).await.brightness_delay_ms {
+        tokio::time::sleep(Duration::from_millis(delay)).await;
+    }
+
+    Ok(())
+}
+
+/// Lowers the brightness quirk of a device that was lost right after a brightness write
+///
+/// Some clone firmwares drop the connection at high brightness, without this the device would
+/// reconnect, get the same brightness and drop again forever
+async fn adapt_brightness_quirk(id: &str) {
+    let Some((percent, written)) = BRIGHTNESS_WRITES.lock().await.remove(id) else {
+        return;
+    };
+
+    if written.elapsed() > BRIGHTNESS_CRASH_WINDOW || percent <= BRIGHTNESS_QUIRK_FLOOR {
+        return;
+    }
+
+    let max_brightness = percent
+        .saturating_sub(BRIGHTNESS_QUIRK_STEP)
+        .max(BRIGHTNESS_QUIRK_FLOOR);
+
+    log::warn!(
+        "{} was lost right after setting brightness {}, limiting it to {} from now on",
+        id,
+        percent,
+        max_brightness
+    );
+
+    let mut quirks = settings::quirks_for(id).await;
+    quirks.max_brightness = Some(max_brightness);
+    settings::store_quirks(id, quirks).await;
+}
+
+/// Least time between two identical alerts, redraws and mirrors repeat the same failure
+const KEY_ALERT_INTERVAL: Duration = Duration::from_secs(10);
+
+/// When every alert was last sent, by device id and message
+static KEY_ALERTS: LazyLock<Mutex<HashMap<(String, String), Instant>>> =
+    LazyLock::new(|| Mutex::new(HashMap::new()));
+
+/// Tells OpenDeck that keys (OpenDeck positions) of the device can't show what they were sent
+///
+/// Otherwise a blank key is all the user gets. Device plugins have no action context to show an
+/// alert on, so this goes out as a log message naming the device and keys.
+pub async fn alert_key_failure(id: &str, positions: &[u8], reason: &str) {
+    let keys = positions
+        .iter()
+        .map(|position| position.to_string())
+        .collect::<Vec<_>>()
+        .join(", ");
+
+    let message = format!(
+        "{}: {} {} can't show its image: {}",
+        id,
+        if positions.len() == 1 { "key" } else { "keys" },
+        keys,
+        reason
+    );
+
+    {
+        let mut alerts = KEY_ALERTS.lock().await;
+        let now = Instant::now();
+
+        alerts.retain(|_, sent| now - *sent < KEY_ALERT_INTERVAL);
+
+        if alerts
+            .insert((id.to_string(), message.clone()), now)
+            .is_some()
+        {
+            return;
+        }
+    }
+
+    log::warn!("{}", message);
+
+    if let Err(e) = openaction::log_message(message).await {
+        log::debug!("Unable to send alert to OpenDeck: {}", e);
+    }
+}
+
+/// Returns true for errors the device lives through, see [handle_error]
+fn is_non_fatal(err: &MirajazzError) -> bool {
+    matches!(err, MirajazzError::ImageError(_) | MirajazzError::BadData)
+}
+
+/// Returns true for a failed report write to a device that is still there
+///
+/// The only errors worth trying again, a device that is gone fails with
+/// [HidError::Disconnected] and anything else fails the same way every time
+fn is_transient(err: &MirajazzError) -> bool {
+    matches!(
+        err,
+        MirajazzError::HidError(HidError::Message(_) | HidError::Other(_))
+    )
+}
+
 /// Handles errors, returning true if should continue, returning false if an error is fatal
 pub async fn handle_error(id: &String, err: MirajazzError) -> bool {
     log::error!("Device {} error: {}", id, err);
 
     // Some errors are not critical and can be ignored without sending disconnected event
-    if matches!(err, MirajazzError::ImageError(_) | MirajazzError::BadData) {
+    if is_non_fatal(&err) {
         return true;
     }
 
+    if !SHUTTING_DOWN.load(Ordering::Acquire) {
+        adapt_brightness_quirk(id).await;
+    }
+
     log::info!("Deregistering device {}", id);
     if let Err(e) = openaction::device_plugin::unregister_device(id.clone()).await {
         log::error!("Failed to unregister device: {}", e);
@@ -118,23 +468,34 @@ pub async fn handle_error(id: &String, err: MirajazzError) -> bool {
 
     log::info!("Removing device {} from the list", id);
     DEVICES.write().await.remove(id);
+    cache::forget_all_shown(id).await;
 
     log::info!("Finished clean-up for {}", id);
 
     false
 }
 
-pub async fn connect(candidate: &CandidateDevice) -> Result<Device, MirajazzError> {
-    let result = Device::connect(
-        &candidate.dev,
-        candidate.kind.protocol_version(),
-        candidate.kind.key_count(),
-        candidate.kind.encoder_count(),
-    )
-    .await;
+pub async fn connect(candidate: &CandidateDevice) -> Result<Box<dyn DeckDevice>, MirajazzError> {
+    let result = backend::connect(candidate).await;
 
     match result {
-        Ok(device) => Ok(device),
+        Ok(device) => {
+            watcher::mark_opened(&candidate.id).await;
+
+            Ok(device)
+        }
+        Err(e) if is_permission_denied(&e) => {
+            if watcher::mark_unopenable(&candidate.id).await {
+                log::error!(
+                    "No permission to open {}, install the udev rules, retrying until they apply: {e}",
+                    candidate.id
+                );
+            } else {
+                log::debug!("Still no permission to open {}: {e}", candidate.id);
+            }
+
+            Err(e)
+        }
         Err(e) => {
             log::error!("Error while connecting to device: {e}");
 
@@ -143,15 +504,171 @@ pub async fn connect(candidate: &CandidateDevice) -> Result<Device, MirajazzErro
     }
 }
 
+/// Updates waiting to be forwarded to OpenDeck before the oldest twists get dropped
+const INPUT_QUEUE_CAPACITY: usize = 64;
+
+/// Window of twists summed up before they are sent when `OPENDECK_N1_TWIST_WINDOW_MS` isn't set
+const DEFAULT_TWIST_WINDOW: Duration = Duration::from_millis(15);
+
+/// How long a twist waits for more of the same dial, `OPENDECK_N1_TWIST_WINDOW_MS` at startup
+///
+/// A fast spin reports every detent on its own, one `encoder_change` per detent floods OpenDeck
+/// and sliders lag behind the dial. 0 sends every twist right away.
+static TWIST_WINDOW: LazyLock<Duration> = LazyLock::new(|| {
+    let Ok(value) = std::env::var("OPENDECK_N1_TWIST_WINDOW_MS") else {
+        return DEFAULT_TWIST_WINDOW;
+    };
+
+    value
+        .parse()
+        .map(Duration::from_millis)
+        .unwrap_or_else(|_| {
+            log::warn!(
+                "Invalid OPENDECK_N1_TWIST_WINDOW_MS {}, using {:?}",
+                value,
+                DEFAULT_TWIST_WINDOW
+            );
+            DEFAULT_TWIST_WINDOW
+        })
+});
+
+/// Sums up twists of the same dial and direction that follow the update within [TWIST_WINDOW]
+///
+/// Returns the update to send and the one that ended the window early, which goes next: a press,
+/// another dial or the dial turning back is never held up or reordered.
+async fn coalesce_twists(
+    id: &str,
+    queue: &InputQueue,
+    first: InputUpdate,
+) -> (InputUpdate, Option<InputUpdate>) {
+    let DeviceStateUpdate::EncoderTwist(encoder, mut delta) = first.update else {
+        return (first, None);
+    };
+
+    if TWIST_WINDOW.is_zero() {
+        return (first, None);
+    }
+
+    let deadline = tokio::time::Instant::now() + *TWIST_WINDOW;
+    let mut next = None;
+
+    while let Ok(update) = tokio::time::timeout_at(deadline, queue.pop()).await {
+        match update.update {
+            DeviceStateUpdate::EncoderTwist(other, more)
+                if other == encoder && more.signum() == delta.signum() =>
+            {
+                let Some(sum) = delta.checked_add(more) else {
+                    next = Some(update);
+                    break;
+                };
+
+                delta = sum;
+                stats::count_coalesced_twist(id).await;
+            }
+            _ => {
+                next = Some(update);
+                break;
+            }
+        }
+    }
+
+    (DeviceStateUpdate::EncoderTwist(encoder, delta).into(), next)
+}
+
+/// Returns the OpenDeck encoder long dial presses are sent on, after every other one
+///
+/// Already an OpenDeck index, [crate::mappings::Presentation::opendeck_update] leaves it alone.
+async fn long_press_encoder(id: &str, kind: &Kind) -> u8 {
+    let (_, _, encoders) = settings::presentation_for(id, kind).await.layout(kind);
+
+    (encoders + settings::hold_rotate_for(id).await as usize) as u8
+}
+
+/// Consecutive read timeouts within this window tear the device down
+const TIMEOUT_BURST_WINDOW: Duration = Duration::from_secs(5);
+
+/// How many consecutive timeouts within [TIMEOUT_BURST_WINDOW] count as the device being gone
+const TIMEOUT_BURST: u32 = 50;
+
+/// Run of read timeouts without a successful read in between
+#[derive(Debug, Default)]
+struct TimeoutRun {
+    started: Option<Instant>,
+    count: u32,
+}
+
+impl TimeoutRun {
+    /// Adds a timeout, returns true once they come faster than a working device would produce
+    fn push(&mut self) -> bool {
+        let now = Instant::now();
+
+        // Spread out timeouts are the benign kind, start counting again
+        let started = match self.started {
+            Some(started) if now - started <= TIMEOUT_BURST_WINDOW => started,
+            _ => {
+                self.count = 0;
+                now
+            }
+        };
+
+        self.started = Some(started);
+        self.count += 1;
+
+        self.count >= TIMEOUT_BURST
+    }
+
+    fn clear(&mut self) {
+        *self = Self::default();
+    }
+}
+
+/// Returns true for errors of reads that timed out, the device may still be fine
+fn is_timeout(err: &MirajazzError) -> bool {
+    let MirajazzError::HidError(err) = err else {
+        return false;
+    };
+
+    match err {
+        HidError::Other(err) => err
+            .downcast_ref::<std::io::Error>()
+            .is_some_and(|err| err.kind() == std::io::ErrorKind::TimedOut)
+            || err.to_string().to_lowercase().contains("timed out"),
+        HidError::Message(message) => message.to_lowercase().contains("timed out"),
+        _ => false,
+    }
+}
+
+/// Returns true for errors of opening a device node the user has no access to
+fn is_permission_denied(err: &MirajazzError) -> bool {
+    let MirajazzError::HidError(err) = err else {
+        return false;
+    };
+
+    match err {
+        HidError::Other(err) => {
+            err.downcast_ref::<std::io::Error>()
+                .is_some_and(|err| err.kind() == std::io::ErrorKind::PermissionDenied)
+                || err.to_string().to_lowercase().contains("permission denied")
+        }
+        HidError::Message(message) => message.to_lowercase().contains("permission denied"),
+        _ => false,
+    }
+}
+
 /// Handles events from device to OpenDeck
 async fn device_events_task(candidate: &CandidateDevice) -> Result<(), MirajazzError> {
     log::info!("Connecting to {} for incoming events", candidate.id);
 
-    let process_input = crate::inputs::process_input_n1;
+    let dedupe = settings::dedupe_reports_for(&candidate.id).await;
 
     let devices_lock = DEVICES.read().await;
-    let reader = match devices_lock.get(&candidate.id) {
-        Some(device) => device.get_reader(process_input),
+    let mut reader = match devices_lock.get(&candidate.id) {
+        Some(device) => InputReader::new(
+            &candidate.id,
+            device.as_ref(),
+            candidate.kind.input_processor(),
+            dedupe,
+        ),
         None => return Ok(()),
     };
     drop(devices_lock);
@@ -160,171 +677,2020 @@ async fn device_events_task(candidate: &CandidateDevice) -> Result<(), MirajazzE
 
     log::info!("Reader is ready for {}", candidate.id);
 
-    loop {
-        log::info!("Reading updates...");
+    let queue = InputQueue::new(INPUT_QUEUE_CAPACITY);
+    let mut velocity = TwistVelocity::default();
+    let mut timeouts = TimeoutRun::default();
 
-        let updates = match reader.read(None).await {
-            Ok(updates) => updates,
-            Err(e) => {
-                if !handle_error(&candidate.id, e).await {
-                    break;
-                }
+    // Reading keeps going while OpenDeck is slow to take events, the queue absorbs bursts
+    let read = async {
+        loop {
+            log::info!("Reading updates...");
 
-                continue;
-            }
-        };
+            reader.set_hold_rotate(settings::hold_rotate_for(&candidate.id).await);
 
-        for update in updates {
-            match &update {
-                DeviceStateUpdate::EncoderDown(enc) => {
-                    log::info!("🎯 ENCODER DOWN: encoder={}", enc);
-                }
-                DeviceStateUpdate::EncoderUp(enc) => {
-                    log::info!("🎯 ENCODER UP: encoder={}", enc);
-                }
-                DeviceStateUpdate::EncoderTwist(enc, val) => {
-                    log::info!("🎯 ENCODER TWIST: encoder={} value={}", enc, val);
-                }
-                _ => {
-                    log::info!("New update: {:#?}", update);
+            // Reads of a held dial give up at its deadline, its release report may be lost
+            let deadline = settings::dial_release_timeout_for(&candidate.id)
+                .await
+                .and_then(|timeout| reader.dial_release_deadline(timeout));
+
+            let read = async {
+                match deadline {
+                    Some(deadline) => tokio::time::timeout_at(deadline.into(), reader.read())
+                        .await
+                        .ok(),
+                    None => Some(reader.read().await),
                 }
-            }
+            };
 
-            let id = candidate.id.clone();
+            let updates = match read.await {
+                None => {
+                    log::warn!(
+                        "Dial of {} is held without twisting for too long, releasing it",
+                        candidate.id
+                    );
+                    stats::count_synthetic_release(&candidate.id).await;
 
-            let result = match update {
-                DeviceStateUpdate::ButtonDown(key) => {
-                    log::info!("📤 Sending key_down(id={}, key={})", id, key);
-                    let result = openaction::device_plugin::key_down(id.clone(), key).await;
-                    if let Err(ref e) = result {
-                        log::error!("Failed to send key_down: {}", e);
-                    }
-                    result
+                    reader.release_dial()
                 }
-                DeviceStateUpdate::ButtonUp(key) => {
-                    log::info!("📤 Sending key_up(id={}, key={})", id, key);
-                    let result = openaction::device_plugin::key_up(id, key).await;
-                    if let Err(ref e) = result {
-                        log::error!("Failed to send key_up: {}", e);
-                    }
-                    result
+                Some(Ok(updates)) => {
+                    timeouts.clear();
+                    updates
                 }
-                DeviceStateUpdate::EncoderDown(encoder) => {
-                    log::info!("📤 Sending encoder_down(id={}, encoder={})", id, encoder);
-                    let result = openaction::device_plugin::encoder_down(id, encoder).await;
-                    if let Err(ref e) = result {
-                        log::error!("Failed to send encoder_down: {}", e);
+                // Some kernels time reads out now and then, only a burst means the device is gone
+                Some(Err(e)) if is_timeout(&e) => {
+                    stats::count_read_timeout(&candidate.id).await;
+
+                    if !timeouts.push() {
+                        log::debug!("Read from {} timed out: {}", candidate.id, e);
+                        continue;
                     }
-                    result
-                }
-                DeviceStateUpdate::EncoderUp(encoder) => {
-                    log::info!("📤 Sending encoder_up(id={}, encoder={})", id, encoder);
-                    let result = openaction::device_plugin::encoder_up(id, encoder).await;
-                    if let Err(ref e) = result {
-                        log::error!("Failed to send encoder_up: {}", e);
+
+                    log::error!("Reads from {} keep timing out", candidate.id);
+
+                    if !handle_error(&candidate.id, e).await {
+                        break;
                     }
-                    result
+
+                    continue;
                 }
-                DeviceStateUpdate::EncoderTwist(encoder, val) => {
-                    log::info!("📤 Sending encoder_change(id={}, encoder={}, val={})", id, encoder, val);
-                    let result = openaction::device_plugin::encoder_change(
-                        id, encoder, val as i16
-                    ).await;
-                    if let Err(ref e) = result {
-                        log::error!("Failed to send encoder_change: {}", e);
+                Some(Err(e)) => {
+                    if !handle_error(&candidate.id, e).await {
+                        break;
                     }
-                    result
+
+                    continue;
                 }
             };
 
-            if let Err(e) = result {
-                log::error!("Failed to send event to OpenAction: {}", e);
+            let curve = settings::twist_curve_for(&candidate.id).await;
+            let steps = settings::twist_steps_for(&candidate.id).await;
+
+            for update in updates {
+                // Speed is measured here, queueing would distort the timing
+                let update = velocity.scale(curve, &steps, update);
+
+                match queue.push(update).await {
+                    Pushed::Queued => {}
+                    Pushed::Merged => stats::count_merged_twist(&candidate.id).await,
+                    Pushed::DroppedTwist => {
+                        log::warn!(
+                            "Input queue of {} is full, dropped a dial twist",
+                            candidate.id
+                        );
+                        stats::count_dropped_twist(&candidate.id).await;
+                    }
+                }
             }
         }
-    }
+    };
 
-    Ok(())
-}
+    let forward = async {
+        let mut next = None;
+        let mut long_press = LongPress::default();
 
-/// Sends periodic keepalives to the device to maintain connection
-async fn keepalive_task(candidate: &CandidateDevice) -> Result<(), MirajazzError> {
-    let mut interval = interval(Duration::from_secs(10));
+        loop {
+            let update = match (next.take(), long_press.deadline()) {
+                (Some(update), _) => Some(update),
+                (None, Some(deadline)) => tokio::time::timeout_at(deadline, queue.pop()).await.ok(),
+                (None, None) => Some(queue.pop().await),
+            };
 
-    loop {
-        interval.tick().await;
+            let mut updates = match update {
+                Some(update) => {
+                    let threshold = settings::dial_long_press_for(&candidate.id).await;
+                    let encoder = long_press_encoder(&candidate.id, &candidate.kind).await;
 
-        log::debug!("Sending keepalive to {}", candidate.id);
+                    long_press.push(update, threshold, encoder)
+                }
+                // Dial is still held at the long press threshold
+                None => long_press.expire(),
+            };
 
-        let devices_lock = DEVICES.read().await;
+            // Forwarding is where OpenDeck gets flooded, reading and the input state stay per detent
+            if let Some(last) = updates.pop() {
+                let (last, following) = coalesce_twists(&candidate.id, &queue, last).await;
+                next = following;
+                updates.push(last);
+            }
 
-        let device = match devices_lock.get(&candidate.id) {
-            Some(device) => device,
-            None => return Ok(()),
-        };
+            for InputUpdate { update, held } in updates {
+                latency::before_input().await;
 
-        match device.keep_alive().await {
-            Ok(_) => log::debug!("Keepalive sent successfully to {}", candidate.id),
-            Err(e) => {
-                log::error!("Keepalive failed for {}: {:?}", candidate.id, e);
-                drop(devices_lock);
-                if !handle_error(&candidate.id, e).await {
-                    break;
-                }
+                forward_update(&candidate.id, &candidate.kind, update, held).await;
             }
         }
+    };
+
+    tokio::select! {
+        _ = read => {},
+        _ = forward => {},
     }
 
     Ok(())
 }
 
-/// Handles different combinations of "set image" event, including clearing the specific buttons and whole device
-pub async fn handle_set_image(device: &Device, evt: SetImageEvent) -> Result<(), MirajazzError> {
-    // Get position from the event - it's Option<u8> in v2
-    let position = evt.position;
-    
-    match (position, evt.image) {
-        (Some(position), Some(image)) => {
-            log::info!("Setting image for button {}", position);
+/// Translates update into OpenDeck positions and sends it, unless "do not disturb" swallows it
+async fn forward_update(id: &str, kind: &Kind, update: DeviceStateUpdate, held: Option<Duration>) {
+    let profile = settings::profile_for(id).await;
+    let presentation = settings::presentation_for(id, kind).await;
 
-            // OpenDeck sends image as a data url, so parse it using a library
-            let url = DataUrl::process(image.as_str()).unwrap(); // Isn't expected to fail, so unwrap it is
-            let (body, _fragment) = url.decode_to_vec().unwrap(); // Same here
+    // Translate physical key positions into OpenDeck ones, face buttons aren't part of the grid
+    let update = match face::route(kind, presentation, &update) {
+        Some(routed) => routed,
+        None => match update {
+            DeviceStateUpdate::ButtonDown(key) => {
+                DeviceStateUpdate::ButtonDown(profile.apply(kind, key))
+            }
+            DeviceStateUpdate::ButtonUp(key) => {
+                DeviceStateUpdate::ButtonUp(profile.apply(kind, key))
+            }
+            update => update,
+        },
+    };
 
-            // Allow only image/jpeg mime for now
-            if url.mime_type().subtype != "jpeg" {
-                log::error!("Incorrect mime type: {}", url.mime_type());
+    if dnd::filter_update(id, &update, held).await {
+        log::debug!("Do not disturb is active, not forwarding {:?}", update);
+        return;
+    }
 
-                return Ok(()); // Not a fatal error, enough to just log it
-            }
+    if keylock::filter_update(id, &update).await {
+        log::debug!("Keys are locked, not forwarding {:?}", update);
+        return;
+    }
 
-            let image = load_from_memory_with_format(body.as_slice(), image::ImageFormat::Jpeg)?;
+    if reaction::filter_update(id, &update).await {
+        log::debug!("Latency measurement is running, not forwarding {:?}", update);
+        return;
+    }
 
-            let kind = Kind::from_vid_pid(device.vid, device.pid).unwrap(); // Safe to unwrap here, because device is already filtered
+    // Key that shows it if the press doesn't reach OpenDeck, face buttons have no display
+    let pressed = match update {
+        DeviceStateUpdate::ButtonDown(key) if (key as usize) < kind.key_count() => Some(key),
+        _ => None,
+    };
 
-            device
-                .set_button_image(
-                    opendeck_to_device(position),
-                    get_image_format_for_key(&kind, position),
-                    image,
-                )
-                .await?;
-            device.flush().await?;
-            
-            // Small delay for N1 to ensure device processes the image
-            tokio::time::sleep(Duration::from_millis(20)).await;
+    // Mirrored LCD segments press the keys of the device they show
+    let (target, update) = match mirror::route(id, update).await {
+        Route::Own(update) => (id.to_string(), presentation.opendeck_update(kind, update)),
+        Route::Source(source, source_kind, update) => {
+            let presentation = settings::presentation_for(&source, &source_kind).await;
+            (source, presentation.opendeck_update(&source_kind, update))
         }
-        (Some(position), None) => {
-            device
-                .clear_button_image(opendeck_to_device(position))
-                .await?;
-            device.flush().await?;
+        Route::Nowhere => {
+            log::debug!("Mirrored device isn't connected, not forwarding {:?}", update);
+            return;
         }
-        (None, None) => {
-            device.clear_all_button_images().await?;
-            device.flush().await?;
+    };
+
+    match &update {
+        DeviceStateUpdate::EncoderDown(enc) => {
+            log::info!("🎯 ENCODER DOWN: encoder={}", enc);
+        }
+        DeviceStateUpdate::EncoderUp(enc) => {
+            log::info!("🎯 ENCODER UP: encoder={}", enc);
+        }
+        DeviceStateUpdate::EncoderTwist(enc, val) => {
+            log::info!("🎯 ENCODER TWIST: encoder={} value={}", enc, val);
+        }
+        _ => {
+            log::info!("New update: {:#?}", update);
         }
-        _ => {}
     }
 
-    Ok(())
+    let device_id = id;
+    let id = target;
+
+    let is_encoder = !matches!(
+        update,
+        DeviceStateUpdate::ButtonDown(_) | DeviceStateUpdate::ButtonUp(_)
+    );
+    let started = Instant::now();
+
+    let result = match update {
+        DeviceStateUpdate::ButtonDown(key) => {
+            log::info!("📤 Sending key_down(id={}, key={})", id, key);
+            let result = openaction::device_plugin::key_down(id.clone(), key).await;
+            if let Err(ref e) = result {
+                log::error!("Failed to send key_down: {}", e);
+            }
+            result
+        }
+        DeviceStateUpdate::ButtonUp(key) => {
+            // OpenAction's key_up has no room for the hold duration, so it is only logged
+            log::info!("📤 Sending key_up(id={}, key={}, held={:?})", id, key, held);
+            let result = openaction::device_plugin::key_up(id, key).await;
+            if let Err(ref e) = result {
+                log::error!("Failed to send key_up: {}", e);
+            }
+            result
+        }
+        DeviceStateUpdate::EncoderDown(encoder) => {
+            log::info!("📤 Sending encoder_down(id={}, encoder={})", id, encoder);
+            let result = openaction::device_plugin::encoder_down(id, encoder).await;
+            if let Err(ref e) = result {
+                log::error!("Failed to send encoder_down: {}", e);
+            }
+            result
+        }
+        DeviceStateUpdate::EncoderUp(encoder) => {
+            log::info!("📤 Sending encoder_up(id={}, encoder={}, held={:?})", id, encoder, held);
+            let result = openaction::device_plugin::encoder_up(id, encoder).await;
+            if let Err(ref e) = result {
+                log::error!("Failed to send encoder_up: {}", e);
+            }
+            result
+        }
+        DeviceStateUpdate::EncoderTwist(encoder, val) => {
+            log::info!("📤 Sending encoder_change(id={}, encoder={}, val={})", id, encoder, val);
+            let result = openaction::device_plugin::encoder_change(
+                id, encoder, val as i16
+            ).await;
+            if let Err(ref e) = result {
+                log::error!("Failed to send encoder_change: {}", e);
+            }
+            result
+        }
+    };
+
+    if is_encoder {
+        stats::record_encoder_event(device_id, started.elapsed(), result.is_ok()).await;
+    }
+
+    delivery::record(device_id, pressed, result.is_ok()).await;
+
+    if let Err(e) = result {
+        log::error!("Failed to send event to OpenAction: {}", e);
+    }
+}
+
+/// Interval between keepalives sent to every connected device
+const KEEPALIVE_INTERVAL: Duration = Duration::from_secs(10);
+
+/// Wakes up the keepalive task when a device gets registered
+static KEEPALIVE_WAKE: Notify = Notify::const_new();
+
+/// When the keepalive round that is still sending started
+static KEEPALIVE_STARTED: std::sync::Mutex<Option<Instant>> = std::sync::Mutex::new(None);
+
+/// Returns true if a keepalive round has been stuck for several intervals, e.g. on a hung HID write
+#[cfg(target_os = "linux")]
+pub fn keepalive_stalled() -> bool {
+    KEEPALIVE_STARTED
+        .lock()
+        .unwrap()
+        .is_some_and(|started| started.elapsed() > KEEPALIVE_INTERVAL * 3)
+}
+
+/// Sends periodic keepalives to connected devices to maintain connection
+///
+/// Parks without any timers while there are no devices, so an idle plugin doesn't wake up
+pub async fn keepalive_task(token: CancellationToken) {
+    loop {
+        if DEVICES.read().await.is_empty() {
+            log::debug!("No devices connected, parking keepalive task");
+
+            tokio::select! {
+                _ = KEEPALIVE_WAKE.notified() => {},
+                _ = token.cancelled() => break,
+            }
+
+            log::debug!("Device connected, resuming keepalive task");
+            continue;
+        }
+
+        tokio::select! {
+            _ = tokio::time::sleep(KEEPALIVE_INTERVAL) => {},
+            _ = token.cancelled() => break,
+        }
+
+        let ids: Vec<String> = DEVICES.read().await.keys().cloned().collect();
+
+        *KEEPALIVE_STARTED.lock().unwrap() = Some(Instant::now());
+
+        for id in ids {
+            log::debug!("Sending keepalive to {}", id);
+
+            let devices_lock = DEVICES.read().await;
+
+            let device = match devices_lock.get(&id) {
+                Some(device) => device,
+                None => continue,
+            };
+
+            match device.keep_alive().await {
+                Ok(_) => log::debug!("Keepalive sent successfully to {}", id),
+                Err(e) => {
+                    log::error!("Keepalive failed for {}: {:?}", id, e);
+                    drop(devices_lock);
+                    handle_error(&id, e).await;
+                }
+            }
+        }
+
+        *KEEPALIVE_STARTED.lock().unwrap() = None;
+    }
+
+    log::info!("Keepalive task finished");
+}
+
+/// Image work for a device, done by its worker in the order it was queued
+#[derive(Clone)]
+enum ImageJob {
+    Event(SetImageEvent),
+    Keys(Vec<u8>, Option<String>),
+    /// Layers above the base changed on these keys, see [compose::invalidate]
+    Recompose(Vec<u8>),
+    /// One wide image over the LCD strip, see [handle_set_strip]
+    Strip(DynamicImage),
+    /// Splash on keys without a cached image and the device's assets, see [handle_splash]
+    Splash(HashMap<u8, Vec<u8>>, CancellationToken),
+    /// Pre-encoded JPEGs keyed by OpenDeck position, see [assets::upload]
+    Assets(HashMap<u8, Vec<u8>>),
+    /// Next frame of the animation on a key, dropped once the animation was stopped
+    Frame(u8, DynamicImage, CancellationToken),
+    /// Tiles of a video frame that changed, dropped once the video was stopped
+    #[cfg(all(unix, feature = "video"))]
+    Video(Vec<(u8, image::RgbImage)>, CancellationToken),
+}
+
+/// Jobs waiting per device before OpenDeck handlers have to wait too, a page switch is 18 images
+const IMAGE_QUEUE_CAPACITY: usize = 64;
+
+/// Queues of image workers, keyed by device id
+static IMAGE_QUEUES: LazyLock<Mutex<HashMap<String, mpsc::Sender<ImageJob>>>> =
+    LazyLock::new(|| Mutex::new(HashMap::new()));
+
+/// Longest wait for queued images to be written when the device task finishes
+const IMAGE_DRAIN_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// Worker doing all image uploads of a device, see [start_image_worker]
+struct ImageWorker {
+    queue: mpsc::WeakSender<ImageJob>,
+    task: JoinHandle<()>,
+}
+
+impl ImageWorker {
+    /// Stops taking new jobs and waits for the queued ones, up to [IMAGE_DRAIN_TIMEOUT]
+    ///
+    /// A worker that doesn't finish in time is aborted, shutting down can't wait forever
+    async fn drain(self, id: &str) {
+        // Only our own queue, a newer connection of the device may have replaced it already
+        if let Some(queue) = self.queue.upgrade() {
+            IMAGE_QUEUES
+                .lock()
+                .await
+                .retain(|_, sender| !sender.same_channel(&queue));
+        }
+
+        let mut task = self.task;
+
+        if tokio::time::timeout(IMAGE_DRAIN_TIMEOUT, &mut task)
+            .await
+            .is_err()
+        {
+            log::warn!("Image uploads of {} didn't finish in time, aborting them", id);
+            task.abort();
+        }
+    }
+}
+
+/// Starts the worker doing all image uploads of the device
+///
+/// Handlers only queue images, so uploads reach the device one after another and in order,
+/// clears included, instead of interleaving with each other and with keepalives. The worker
+/// runs until its queue is closed by [ImageWorker::drain] or the device is gone, see [device_gone].
+async fn start_image_worker(id: &str, token: &CancellationToken) -> Option<ImageWorker> {
+    let tracker = device_tracker(id).await?;
+
+    let (sender, jobs) = mpsc::channel(IMAGE_QUEUE_CAPACITY);
+    let queue = sender.downgrade();
+
+    // Replaces the queue of a previous connection, its worker is already draining
+    IMAGE_QUEUES.lock().await.insert(id.to_string(), sender);
+
+    let (id, token) = (id.to_string(), token.clone());
+
+    let task = tracker.spawn(async move {
+        image_worker(&id, jobs, token).await;
+
+        log::info!("Image worker finished for {}", id);
+    });
+
+    Some(ImageWorker { queue, task })
+}
+
+/// Most jobs written before the device gets flushed
+const IMAGE_BATCH_LIMIT: usize = 32;
+
+/// Resolves once the device was unplugged, released or failed, while the token of its task is cancelled
+///
+/// Shutting down the plugin cancels every token too, but then queued images are still written, see
+/// [ImageWorker::drain], so this never resolves
+async fn device_gone(token: &CancellationToken) {
+    token.cancelled().await;
+
+    if SHUTTING_DOWN.load(Ordering::Acquire) {
+        std::future::pending::<()>().await;
+    }
+}
+
+/// Takes everything queued at once and writes it with a single flush, see [ImageBatch]
+///
+/// A batch is abandoned halfway once the device is gone: the transfer can't complete anyway, and
+/// the watcher waits for the [DEVICES] lock the batch holds before it can remove the device.
+///
+/// A job whose report write to the device failed is tried again, up to [UPLOAD_ATTEMPTS] times.
+/// It's put back together with every job after it, so images of a key can't overtake each
+/// other, and the next batch starts with them after a backoff, waited out without the turn and
+/// the [DEVICES] lock. Images that can't be shown are reported once and never tried again.
+async fn image_worker(id: &str, mut jobs: mpsc::Receiver<ImageJob>, token: CancellationToken) {
+    let id = id.to_string();
+
+    // Jobs put back after a failure, and the attempt the first of them is at
+    let mut postponed: Vec<ImageJob> = vec![];
+    let mut attempt = 1;
+
+    loop {
+        let mut pending = std::mem::take(&mut postponed);
+
+        if pending.is_empty() {
+            let Some(job) = jobs.recv().await else {
+                return;
+            };
+
+            pending.push(job);
+        }
+
+        // Smaller batches while other devices are around, so they get turns in between
+        let queues = IMAGE_QUEUES.lock().await.len();
+        let limit = schedule::slice(queues, IMAGE_BATCH_LIMIT);
+
+        while pending.len() < limit
+            && let Ok(job) = jobs.try_recv()
+        {
+            pending.push(job);
+        }
+
+        let started = Instant::now();
+        let count = pending.len();
+
+        // Taken before the device lock, waiting for it while holding the lock would block
+        // removal of devices behind the turn
+        let turn = tokio::select! {
+            biased;
+            _ = device_gone(&token) => None,
+            turn = schedule::turn(&id) => Some(turn),
+        };
+
+        let Some(turn) = turn else {
+            log::info!("{} is gone, abandoned {} image jobs", id, count);
+            return;
+        };
+
+        let devices = DEVICES.read().await;
+
+        let Some(device) = devices.get(&id) else {
+            return;
+        };
+
+        let write = async {
+            let mut batch = ImageBatch::default();
+            let mut errors = vec![];
+            let mut postponed = vec![];
+            let mut next_attempt = 1;
+
+            let mut pending = pending.into_iter().enumerate();
+
+            // A job that fails for good doesn't stop the ones queued after it
+            while let Some((index, job)) = pending.next() {
+                let retry = job.clone();
+
+                let result = match job {
+                    ImageJob::Event(event) => {
+                        handle_set_image(device.as_ref(), event, &mut batch).await
+                    }
+                    ImageJob::Keys(positions, image) => {
+                        handle_set_images(device.as_ref(), &id, &positions, image, &mut batch)
+                            .await
+                    }
+                    ImageJob::Recompose(positions) => {
+                        handle_recompose(device.as_ref(), &id, &positions, &mut batch).await
+                    }
+                    ImageJob::Strip(image) => {
+                        handle_set_strip(device.as_ref(), &id, image, &mut batch).await
+                    }
+                    ImageJob::Splash(assets, token) => {
+                        handle_splash(device.as_ref(), &id, assets, token, &mut batch).await
+                    }
+                    ImageJob::Assets(assets) => {
+                        handle_assets(device.as_ref(), &id, &assets, &mut batch).await
+                    }
+                    ImageJob::Frame(position, image, token) => {
+                        handle_frame(device.as_ref(), &id, position, image, &token, &mut batch)
+                            .await
+                    }
+                    #[cfg(all(unix, feature = "video"))]
+                    ImageJob::Video(tiles, token) => {
+                        handle_video(device.as_ref(), &id, tiles, &token, &mut batch).await
+                    }
+                };
+
+                let Err(err) = result else {
+                    continue;
+                };
+
+                // Only the first job of the batch can be one that was put back
+                let tried = if index == 0 { attempt } else { 1 };
+
+                if is_transient(&err) && tried < UPLOAD_ATTEMPTS {
+                    log::warn!(
+                        "Image job {} of {} failed ({}, attempt {}), trying it again",
+                        index + 1,
+                        id,
+                        err,
+                        tried
+                    );
+
+                    postponed.push(retry);
+                    postponed.extend(pending.by_ref().map(|(_, job)| job));
+                    next_attempt = tried + 1;
+                } else {
+                    errors.push(err);
+                }
+            }
+
+            let result = batch.finish(device.as_ref(), &id).await;
+
+            (errors, result, postponed, next_attempt)
+        };
+
+        let written = tokio::select! {
+            biased;
+            _ = device_gone(&token) => None,
+            written = write => Some(written),
+        };
+
+        drop(devices);
+        drop(turn);
+
+        // Skips the flush and the errors of the cut off writes, the device was already dealt with
+        let Some((errors, result, retries, next_attempt)) = written else {
+            log::info!("{} is gone, abandoned {} image jobs", id, count);
+            return;
+        };
+
+        log::info!(
+            "Wrote {} image jobs to {} in {:?}",
+            count - retries.len(),
+            id,
+            started.elapsed()
+        );
+
+        for err in errors.into_iter().chain(result.err()) {
+            if !handle_error(&id, err).await {
+                return;
+            }
+        }
+
+        if !retries.is_empty() {
+            stats::count_upload_retry(&id).await;
+
+            // Doubled for every further attempt
+            let backoff = UPLOAD_RETRY_BACKOFF * 2u32.pow(next_attempt - 2);
+
+            tokio::select! {
+                _ = device_gone(&token) => return,
+                _ = tokio::time::sleep(backoff) => {},
+            }
+        }
+
+        postponed = retries;
+        attempt = next_attempt;
+    }
+}
+
+/// Image jobs written to the device, but not flushed yet
+///
+/// Writes are queued by the device in order, so clears and images keep their order within
+/// the batch. The device is flushed and given its settle delay once for the whole batch.
+#[derive(Default)]
+pub struct ImageBatch {
+    /// Upload start per key size, recorded once the device got flushed
+    uploads: Vec<(usize, Instant)>,
+    /// Anything was written or cleared
+    written: bool,
+    /// Keys (device numbering) images were written to, cleared when the flush has to be retried
+    keys: Vec<u8>,
+    /// Animations to start once their first frame is shown
+    animations: Vec<(u8, animation::Frames)>,
+}
+
+impl ImageBatch {
+    /// Drops animations of the keys, a later job in the batch replaced them
+    fn replace(&mut self, positions: &[u8]) {
+        self.animations
+            .retain(|(position, _)| !positions.contains(position));
+    }
+
+    /// Records an image written to the key at physical position
+    fn uploaded(&mut self, kind: &Kind, position: u8) {
+        self.keys.push(opendeck_to_device(kind, position));
+        self.written = true;
+    }
+
+    /// Flushes the batch, restarting the transfer once if it breaks off
+    ///
+    /// Images go out as many HID reports during the flush, one failed report leaves the key
+    /// garbled. The device keeps the images of a failed flush queued, so the keys are cleared and
+    /// one more flush sends every image of the batch from the start. Only failed report writes
+    /// are restarted: a device that is gone fails the same way every time. Non-fatal errors of
+    /// the jobs are retried by the worker instead, see [image_worker].
+    async fn flush(&self, device: &dyn DeckDevice, id: &str) -> Result<(), MirajazzError> {
+        let err = match device.flush().await {
+            Ok(()) => return Ok(()),
+            Err(err) => err,
+        };
+
+        if !is_transient(&err) || self.keys.is_empty() {
+            return Err(err);
+        }
+
+        log::warn!(
+            "Transfer of {} images to {} broke off ({}), restarting it",
+            self.keys.len(),
+            id,
+            err
+        );
+
+        stats::count_restarted_transfer(id).await;
+
+        for &key in &self.keys {
+            device.clear_button_image(key).await?;
+        }
+
+        device.flush().await
+    }
+
+    async fn finish(self, device: &dyn DeckDevice, id: &str) -> Result<(), MirajazzError> {
+        // Nothing was written when every key already showed the image
+        if self.written {
+            self.flush(device, id).await?;
+        }
+
+        if !self.uploads.is_empty() {
+            for (size, started) in self.uploads {
+                stats::record_upload(id, size, started.elapsed()).await;
+            }
+
+            // Small delay for N1 to ensure device processes the image
+            tokio::time::sleep(Duration::from_millis(20)).await;
+        }
+
+        for (position, frames) in self.animations {
+            animation::start(id, position, frames).await;
+        }
+
+        Ok(())
+    }
+}
+
+/// Queues job for the image worker of the device, returns false if there is no such device
+async fn queue_image_job(id: &str, job: ImageJob) -> bool {
+    let Some(queue) = IMAGE_QUEUES.lock().await.get(id).cloned() else {
+        return false;
+    };
+
+    queue.send(job).await.is_ok()
+}
+
+/// Sets image on a registered device, returns false if there is no such device
+pub async fn set_device_image(event: SetImageEvent) -> bool {
+    let id = event.device.clone();
+
+    queue_image_job(&id, ImageJob::Event(event)).await
+}
+
+/// Sets the same image on several keys of a registered device, returns false if there is no such device
+pub async fn set_device_images(id: &str, positions: &[u8], image: Option<String>) -> bool {
+    queue_image_job(id, ImageJob::Keys(positions.to_vec(), image)).await
+}
+
+/// Draws the layers above the base of the keys again, returns false if there is no such device
+pub async fn recompose_keys(id: &str, positions: &[u8]) -> bool {
+    queue_image_job(id, ImageJob::Recompose(positions.to_vec())).await
+}
+
+/// Shows pre-encoded JPEGs on the keys (OpenDeck positions), returns false if there is no such device
+pub async fn show_assets(id: &str, assets: HashMap<u8, Vec<u8>>) -> bool {
+    queue_image_job(id, ImageJob::Assets(assets)).await
+}
+
+/// Shows the next frame of an animation on the key, returns false if there is no such device
+pub async fn show_animation_frame(
+    id: &str,
+    position: u8,
+    image: DynamicImage,
+    token: CancellationToken,
+) -> bool {
+    queue_image_job(id, ImageJob::Frame(position, image, token)).await
+}
+
+/// Shows tiles (OpenDeck positions) of a video frame, returns false if there is no such device
+#[cfg(all(unix, feature = "video"))]
+pub async fn show_video_tiles(
+    id: &str,
+    tiles: Vec<(u8, image::RgbImage)>,
+    token: CancellationToken,
+) -> bool {
+    queue_image_job(id, ImageJob::Video(tiles, token)).await
+}
+
+/// Shows cached images on every key again, without asking OpenDeck to resend them
+pub async fn redraw_from_cache(id: &str) {
+    // Keys sharing an image (e.g. placeholders) are written in one go
+    let mut groups: Vec<(String, Vec<u8>)> = vec![];
+
+    for (position, image) in cache::current_all(id).await {
+        match groups.iter_mut().find(|(group, _)| *group == image) {
+            Some((_, positions)) => positions.push(position),
+            None => groups.push((image, vec![position])),
+        }
+    }
+
+    for (image, positions) in groups {
+        set_device_images(id, &positions, Some(image)).await;
+    }
+}
+
+/// Shows one wide image across the LCD strip, split into a tile per segment
+///
+/// Returns false if there is no such device
+pub async fn set_device_strip_image(id: &str, image: DynamicImage) -> bool {
+    queue_image_job(id, ImageJob::Strip(image)).await
+}
+
+/// Splits image into tiles of the LCD segments and writes them, flushed with the rest of the batch
+///
+/// Tiles are cut before anything is written, so only a failing device write can interrupt the
+/// upload. Every segment is forgotten then and re-uploaded next time.
+async fn handle_set_strip(
+    device: &dyn DeckDevice,
+    id: &str,
+    image: DynamicImage,
+    batch: &mut ImageBatch,
+) -> Result<(), MirajazzError> {
+    let kind = device.kind();
+    let strip = kind.lcd_strip();
+
+    // The control socket refuses these already, a kind without a strip isn't an error of the device
+    let Some(&(first, _)) = strip.first() else {
+        log::warn!("{} has no LCD strip, dropped the strip image", id);
+        return Ok(());
+    };
+
+    let (segment_width, height) = get_image_format_for_key(&kind, first).size;
+    let width = strip
+        .iter()
+        .map(|(_, x)| *x + segment_width as u32)
+        .max()
+        .unwrap_or_default();
+
+    let background = settings::background_for(id).await;
+    let image = scale::flatten(image, background);
+    let image = scale::fit(
+        image,
+        (width as usize, height),
+        settings::scale_mode_for(id).await,
+        background,
+        settings::resize_filter_for(id).await,
+    );
+
+    let tiles: Vec<(u8, DynamicImage)> = strip
+        .iter()
+        .map(|(position, x)| {
+            (
+                *position,
+                image.crop_imm(*x, 0, segment_width as u32, height as u32),
+            )
+        })
+        .collect();
+
+    let positions: Vec<u8> = strip.iter().map(|(position, _)| *position).collect();
+
+    for &position in &positions {
+        animation::stop(id, position).await;
+    }
+    batch.replace(&positions);
+
+    let profile = settings::profile_for(id).await;
+
+    for (opendeck_position, tile) in tiles {
+        let position = profile.apply(&kind, opendeck_position);
+        let started = Instant::now();
+
+        match upload_key_image(device, id, opendeck_position, tile).await {
+            Ok(true) => {
+                batch.uploads.push((segment_width, started));
+                batch.uploaded(&kind, position);
+            }
+            Ok(false) => {}
+            Err(err) => {
+                for &position in &positions {
+                    cache::forget_shown(id, profile.apply(&kind, position)).await;
+                }
+
+                return Err(err);
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Shows the splash on keys without a cached image, so the device looks connected right away
+///
+/// Keys with a pre-encoded asset show that instead, see [assets::load].
+/// Real images replace it through the regular upload path. Keys OpenDeck leaves alone are
+/// cleared once [SPLASH_DURATION] passes, as OpenDeck doesn't send anything for empty slots.
+async fn handle_splash(
+    device: &dyn DeckDevice,
+    id: &str,
+    assets: HashMap<u8, Vec<u8>>,
+    token: CancellationToken,
+    batch: &mut ImageBatch,
+) -> Result<(), MirajazzError> {
+    // Looked up here, images queued before the splash are cached by now
+    let cached: Vec<u8> = cache::current_all(id)
+        .await
+        .into_iter()
+        .map(|(position, _)| position)
+        .collect();
+
+    let kind = device.kind();
+    let profile = settings::profile_for(id).await;
+
+    let (with_asset, positions): (Vec<u8>, Vec<u8>) = (0..device.key_count() as u8)
+        .filter(|position| !cached.contains(position))
+        .partition(|position| assets.contains_key(position));
+
+    // Pre-encoded assets go to the device as they are, and stay until OpenDeck replaces them
+    for position in with_asset {
+        let position = assets::upload(device, id, position, &assets[&position]).await?;
+        batch.uploaded(&kind, position);
+    }
+
+    for &position in &positions {
+        // Placeholders and titles are what the user wants on empty keys, the splash only
+        // fills in for them
+        let base = match placeholder::get(id, position).await {
+            None if placeholder::title(id, position).await.is_none() => {
+                Base::Image(placeholder::splash())
+            }
+            placeholder => Base::Empty(placeholder),
+        };
+
+        if upload_key_base(device, id, position, base).await? == Upload::Written {
+            batch.uploaded(&kind, profile.apply(&kind, position));
+        }
+    }
+
+    let Some(tracker) = device_tracker(id).await else {
+        return Ok(());
+    };
+
+    let id = id.to_string();
+
+    tracker.spawn(async move {
+        tokio::select! {
+            _ = tokio::time::sleep(SPLASH_DURATION) => {},
+            _ = token.cancelled() => return,
+        }
+
+        let mut untouched = vec![];
+
+        for position in positions {
+            if cache::current(&id, position).await.is_none() {
+                untouched.push(position);
+            }
+        }
+
+        if !untouched.is_empty() {
+            set_device_images(&id, &untouched, None).await;
+        }
+    });
+
+    Ok(())
+}
+
+/// Writes pre-encoded JPEGs (OpenDeck positions) as they are, see [assets::show]
+async fn handle_assets(
+    device: &dyn DeckDevice,
+    id: &str,
+    assets: &HashMap<u8, Vec<u8>>,
+    batch: &mut ImageBatch,
+) -> Result<(), MirajazzError> {
+    let kind = device.kind();
+
+    for (&position, data) in assets {
+        // Nothing to animate under an encoded JPEG
+        animation::stop(id, position).await;
+        batch.replace(&[position]);
+
+        let position = assets::upload(device, id, position, data).await?;
+        batch.uploaded(&kind, position);
+    }
+
+    Ok(())
+}
+
+/// Writes the next frame of an animation, unless the key got a newer image meanwhile
+///
+/// Frames are queued behind whatever else the key is getting, an image handled in between stops
+/// the animation, which cancels the token.
+async fn handle_frame(
+    device: &dyn DeckDevice,
+    id: &str,
+    position: u8,
+    image: DynamicImage,
+    token: &CancellationToken,
+    batch: &mut ImageBatch,
+) -> Result<(), MirajazzError> {
+    if token.is_cancelled() {
+        return Ok(());
+    }
+
+    let kind = device.kind();
+
+    if upload_key_image(device, id, position, image).await? {
+        batch.uploaded(&kind, settings::profile_for(id).await.apply(&kind, position));
+    }
+
+    Ok(())
+}
+
+/// Writes tiles (OpenDeck positions) of a video frame, unless the video was stopped meanwhile
+#[cfg(all(unix, feature = "video"))]
+async fn handle_video(
+    device: &dyn DeckDevice,
+    id: &str,
+    tiles: Vec<(u8, image::RgbImage)>,
+    token: &CancellationToken,
+    batch: &mut ImageBatch,
+) -> Result<(), MirajazzError> {
+    if token.is_cancelled() {
+        return Ok(());
+    }
+
+    let kind = device.kind();
+
+    for (position, tile) in tiles {
+        let position = video::upload_tile(device, id, position, tile).await?;
+        batch.uploaded(&kind, position);
+    }
+
+    Ok(())
+}
+
+/// How much of a malformed data url is logged, they can be huge
+const DATA_URL_LOG_PREFIX: usize = 48;
+
+/// Image carried by a data url, ready to be decoded
+#[derive(Debug)]
+pub struct ImageData {
+    pub body: Vec<u8>,
+    pub format: image::ImageFormat,
+}
+
+/// Why a data url doesn't carry a usable image
+#[derive(Debug)]
+pub enum DataUrlError {
+    /// Not a data url at all
+    Malformed,
+    /// Base64 or percent-encoded payload doesn't decode, or is empty
+    CorruptPayload(String),
+    /// Payload isn't in a format we can decode
+    UnsupportedMime(String),
+}
+
+impl fmt::Display for DataUrlError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Malformed => write!(f, "not a data url"),
+            Self::CorruptPayload(reason) => write!(f, "corrupt payload: {}", reason),
+            Self::UnsupportedMime(mime) => write!(f, "unsupported image, mime type: {}", mime),
+        }
+    }
+}
+
+/// Parses data url as sent by OpenDeck or actions
+///
+/// Both base64 and percent-encoded payloads are accepted, whitespace inside them is ignored
+/// and mime type parameters like `;charset=` don't affect format detection
+pub fn parse_image_data_url(value: &str) -> Result<ImageData, DataUrlError> {
+    let url = DataUrl::process(value).map_err(|_| DataUrlError::Malformed)?;
+    let (body, _fragment) = url
+        .decode_to_vec()
+        .map_err(|e| DataUrlError::CorruptPayload(format!("{:?}", e)))?;
+
+    if body.is_empty() {
+        return Err(DataUrlError::CorruptPayload("empty".to_string()));
+    }
+
+    let mime = url.mime_type();
+    let mime = format!("{}/{}", mime.type_, mime.subtype);
+
+    let format = detect_format(&body, &mime).ok_or(DataUrlError::UnsupportedMime(mime))?;
+
+    Ok(ImageData { body, format })
+}
+
+/// Returns format to decode the image with
+///
+/// Some actions declare a mime type that doesn't match the data, so the bytes are trusted first
+/// and the declared mime type is only used when they can't be recognized
+pub fn detect_format(body: &[u8], mime: &str) -> Option<image::ImageFormat> {
+    use image::ImageFormat::*;
+
+    image::guess_format(body)
+        .ok()
+        .or_else(|| image::ImageFormat::from_mime_type(mime))
+        // GIFs decode to their first frame
+        .filter(|format| matches!(format, Jpeg | Png | Gif | Bmp | WebP))
+}
+
+/// Handles different combinations of "set image" event, including clearing the specific buttons and whole device
+pub async fn handle_set_image(
+    device: &dyn DeckDevice,
+    evt: SetImageEvent,
+    batch: &mut ImageBatch,
+) -> Result<(), MirajazzError> {
+    match (evt.position, evt.image) {
+        (Some(position), image) => {
+            handle_set_images(device, &evt.device, &[position], image, batch).await
+        }
+        (None, None) => {
+            animation::stop_all(&evt.device).await;
+            batch.animations.clear();
+
+            cache::forget_all_shown(&evt.device).await;
+            device.clear_all_button_images().await?;
+            batch.written = true;
+
+            // Same flush shows the placeholders of the cleared keys
+            let kind = device.kind();
+
+            let profile = settings::profile_for(&evt.device).await;
+
+            // Keys left empty are cleared already, their base is still stored for the layers above
+            for position in 0..device.key_count() as u8 {
+                let base = Base::Empty(placeholder::get(&evt.device, position).await);
+
+                if upload_key_base(device, &evt.device, position, base).await? == Upload::Written {
+                    batch.uploaded(&kind, profile.apply(&kind, position));
+                }
+            }
+
+            Ok(())
+        }
+        (None, Some(image)) => handle_fill_image(device, &evt.device, image, batch).await,
+    }
+}
+
+/// Why an image sent for keys can't be shown
+#[derive(Debug)]
+enum Undisplayable {
+    /// Data url is broken, or the image is in a format the device can't show
+    DataUrl(DataUrlError),
+    /// Larger than [scale::MAX_SOURCE_SIZE] on a side
+    TooLarge(u32, u32),
+    /// Payload doesn't decode as the format it claims
+    Decode(image::ImageError),
+}
+
+impl fmt::Display for Undisplayable {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::DataUrl(e) => write!(f, "{}", e),
+            Self::TooLarge(width, height) => write!(
+                f,
+                "image of {}x{} is larger than {} pixels on a side",
+                width,
+                height,
+                scale::MAX_SOURCE_SIZE
+            ),
+            Self::Decode(e) => write!(f, "unable to decode image: {}", e),
+        }
+    }
+}
+
+impl Undisplayable {
+    /// Tells OpenDeck about the keys that stay as they are
+    ///
+    /// Nothing the device did wrong, and the same image fails the same way every time, so it
+    /// neither goes to [handle_error] nor gets retried by the [image_worker]. Telling the user
+    /// is all there is to do.
+    async fn report(self, id: &str, positions: &[u8]) {
+        alert_key_failure(id, positions, &self.to_string()).await;
+    }
+}
+
+/// Decodes image sent by OpenDeck, together with its frames if it's an animated GIF
+async fn decode_image(
+    image: String,
+) -> Result<(DynamicImage, Option<animation::Frames>), Undisplayable> {
+    // Redraws, theme switches and mirrors send the same data url again, animations aren't cached
+    if let Some(decoded) = cache::decoded(&image).await {
+        return Ok((decoded, None));
+    }
+
+    // Device always gets JPEG, mirajazz re-encodes whatever we decode here
+    let ImageData { body, format } = match parse_image_data_url(&image) {
+        Ok(data) => data,
+        Err(e @ DataUrlError::UnsupportedMime(_)) => {
+            log::error!("{}", e);
+
+            return Err(Undisplayable::DataUrl(e));
+        }
+        Err(e) => {
+            log::error!(
+                "Malformed data url ({}): {}...",
+                e,
+                image.chars().take(DATA_URL_LOG_PREFIX).collect::<String>()
+            );
+
+            return Err(Undisplayable::DataUrl(e));
+        }
+    };
+
+    // Only the header is read, a huge screenshot is refused before decoding it
+    let dimensions = ImageReader::with_format(Cursor::new(&body), format).into_dimensions();
+
+    if let Ok((width, height)) = dimensions
+        && width.max(height) > scale::MAX_SOURCE_SIZE
+    {
+        let e = Undisplayable::TooLarge(width, height);
+        log::error!("{}, not showing it", e);
+
+        return Err(e);
+    }
+
+    // Decoding is CPU bound, keep it off the workers that read inputs
+    let decoded = tokio::task::spawn_blocking(move || {
+        let frames = if format == image::ImageFormat::Gif {
+            animation::decode_gif(&body)?
+        } else {
+            None
+        };
+
+        Ok::<_, image::ImageError>((decode_oriented(&body, format)?, frames))
+    })
+    .await
+    .expect("decoding image panicked")
+    .map_err(Undisplayable::Decode)?;
+
+    if decoded.1.is_none() {
+        cache::store_decoded(&image, &decoded.0).await;
+    }
+
+    Ok(decoded)
+}
+
+/// Decodes the image and turns it upright according to its EXIF orientation, if it has one
+///
+/// Phone photos are often stored sideways with an orientation tag. This only undoes that, rotation
+/// and mirroring of the key format are applied on top of the upright image when encoding for the device.
+pub fn decode_oriented(body: &[u8], format: image::ImageFormat) -> image::ImageResult<DynamicImage> {
+    let mut decoder = ImageReader::with_format(Cursor::new(body), format).into_decoder()?;
+    let orientation = decoder.orientation()?;
+
+    let mut image = DynamicImage::from_decoder(decoder)?;
+
+    if orientation != Orientation::NoTransforms {
+        log::debug!("Applying EXIF orientation {:?}", orientation);
+        image.apply_orientation(orientation);
+    }
+
+    Ok(image)
+}
+
+/// Shows the image on every key of the device
+///
+/// Image is fitted once per distinct key size. A key that fails to upload doesn't stop the
+/// others, the first error is returned once all keys were written.
+async fn handle_fill_image(
+    device: &dyn DeckDevice,
+    id: &str,
+    image: String,
+    batch: &mut ImageBatch,
+) -> Result<(), MirajazzError> {
+    let kind = device.kind();
+    let profile = settings::profile_for(id).await;
+    let positions: Vec<u8> = (0..device.key_count() as u8).collect();
+
+    animation::stop_all(id).await;
+    batch.animations.clear();
+
+    let (image, frames) = match decode_image(image).await {
+        Ok(decoded) => decoded,
+        Err(e) => {
+            e.report(id, &positions).await;
+            return Ok(());
+        }
+    };
+
+    let background = settings::background_for(id).await;
+    let scale_mode = settings::scale_mode_for(id).await;
+    let filter = settings::resize_filter_for(id).await;
+
+    let mut sizes: Vec<(usize, usize)> = positions
+        .iter()
+        .map(|&position| content_size(&kind, profile.apply(&kind, position)))
+        .collect();
+    sizes.sort_unstable();
+    sizes.dedup();
+
+    // Fitting again in upload_key_image is a no-op for an image that already has the content size
+    let fitted = tokio::task::spawn_blocking(move || {
+        let image = scale::flatten(image, background);
+
+        sizes
+            .into_iter()
+            .map(|size| {
+                (
+                    size,
+                    scale::fit(image.clone(), size, scale_mode, background, filter),
+                )
+            })
+            .collect::<HashMap<_, _>>()
+    })
+    .await
+    .expect("rendering key image panicked");
+
+    let mut first_error = None;
+
+    for &opendeck_position in &positions {
+        let position = profile.apply(&kind, opendeck_position);
+        let size = content_size(&kind, position);
+
+        match upload_key_image(device, id, opendeck_position, fitted[&size].clone()).await {
+            Ok(true) => batch.uploaded(&kind, position),
+            Ok(false) => {}
+            Err(e) => {
+                log::warn!("Unable to set image for key {} of {}: {}", opendeck_position, id, e);
+                first_error.get_or_insert(e);
+            }
+        }
+    }
+
+    if let Some(frames) = frames {
+        for &position in &positions {
+            batch.animations.push((position, frames.clone()));
+        }
+    }
+
+    first_error.map_or(Ok(()), Err)
+}
+
+/// Shows the same image on several keys (OpenDeck positions), or clears them with `None`
+///
+/// Image is decoded once, the device is flushed with the rest of the batch.
+/// Animated GIFs show their first frame with the batch and keep animating in the background.
+pub async fn handle_set_images(
+    device: &dyn DeckDevice,
+    id: &str,
+    positions: &[u8],
+    image: Option<String>,
+    batch: &mut ImageBatch,
+) -> Result<(), MirajazzError> {
+    let kind = device.kind();
+    let profile = settings::profile_for(id).await;
+
+    // New image or clearing replaces whatever was animating on the key
+    for &position in positions {
+        animation::stop(id, position).await;
+    }
+    batch.replace(positions);
+
+    let (image, frames) = match image {
+        Some(image) => match decode_image(image).await {
+            Ok((image, frames)) => (Some(image), frames),
+            Err(e) => {
+                e.report(id, positions).await;
+                return Ok(());
+            }
+        },
+        None => (None, None),
+    };
+
+    for &opendeck_position in positions {
+        // Cleared keys show their placeholder, if one was set
+        let base = match &image {
+            Some(image) => Base::Image(image.clone()),
+            None => Base::Empty(placeholder::get(id, opendeck_position).await),
+        };
+
+        // Translated into the physical position
+        let position = profile.apply(&kind, opendeck_position);
+        let format = get_image_format_for_key(&kind, position);
+        let started = Instant::now();
+
+        match upload_key_base(device, id, opendeck_position, base).await? {
+            Upload::Written => {
+                log::info!("Set image for button {}", position);
+                batch.uploads.push((format.size.0, started));
+                batch.uploaded(&kind, position);
+            }
+            Upload::Unchanged => {
+                log::debug!("Button {} already shows this image, skipping", position);
+            }
+            Upload::Empty => {
+                clear_key(device, id, position).await?;
+                batch.written = true;
+            }
+        }
+    }
+
+    if let Some(frames) = frames {
+        for &position in positions {
+            batch.animations.push((position, frames.clone()));
+        }
+    }
+
+    Ok(())
+}
+
+/// Writes the keys (OpenDeck positions) with their layers drawn again on the stored base
+///
+/// Keys without a stored base of the right size are rendered from their cached image instead.
+/// Animations keep running, their next frame is composed with the new layers anyway.
+async fn handle_recompose(
+    device: &dyn DeckDevice,
+    id: &str,
+    positions: &[u8],
+    batch: &mut ImageBatch,
+) -> Result<(), MirajazzError> {
+    let kind = device.kind();
+    let profile = settings::profile_for(id).await;
+
+    for &opendeck_position in positions {
+        let position = profile.apply(&kind, opendeck_position);
+
+        let Some(base) = compose::base(id, opendeck_position, content_size(&kind, position)).await
+        else {
+            let image = cache::current(id, opendeck_position).await;
+            handle_set_images(device, id, &[opendeck_position], image, batch).await?;
+
+            continue;
+        };
+
+        match write_composed(device, id, opendeck_position, base).await? {
+            Upload::Written => batch.uploaded(&kind, position),
+            Upload::Unchanged => {}
+            Upload::Empty => {
+                clear_key(device, id, position).await?;
+                batch.written = true;
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Returns size key content is fitted to, the key (physical position) image size without padding
+fn content_size(kind: &Kind, position: u8) -> (usize, usize) {
+    kind.display_format().key_size(position).content()
+}
+
+/// Returns channel order images have to be sent in
+pub async fn device_color_order(id: &str, kind: &Kind) -> ColorOrder {
+    settings::color_order_for(id)
+        .await
+        .unwrap_or_else(|| kind.color_order())
+}
+
+/// Reorders channels if the device doesn't render RGB, last step before upload
+pub fn convert_colors(order: ColorOrder, image: DynamicImage) -> DynamicImage {
+    match order {
+        ColorOrder::Rgb => image,
+        ColorOrder::Bgr => {
+            let mut image = image.to_rgb8();
+
+            for pixel in image.pixels_mut() {
+                pixel.0.swap(0, 2);
+            }
+
+            DynamicImage::ImageRgb8(image)
+        }
+    }
+}
+
+/// Attempts at an image job failing to write to the device before it goes to [handle_error]
+const UPLOAD_ATTEMPTS: u32 = 3;
+
+/// Wait before the first retry of a failed image job, doubled for every further one
+const UPLOAD_RETRY_BACKOFF: Duration = Duration::from_millis(10);
+
+/// What [upload_key_base] did with a key
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Upload {
+    /// Image was written, the device has to be flushed
+    Written,
+    /// Key already shows the resulting image, nothing was written
+    Unchanged,
+    /// Nothing to show, the key has to be cleared, see [compose::compose]
+    Empty,
+}
+
+/// Writes image to the key at OpenDeck position with every layer on top, without flushing
+///
+/// Returns false without writing anything if the key already shows the resulting image
+pub async fn upload_key_image(
+    device: &dyn DeckDevice,
+    id: &str,
+    opendeck_position: u8,
+    image: DynamicImage,
+) -> Result<bool, MirajazzError> {
+    let upload = upload_key_base(device, id, opendeck_position, Base::Image(image)).await?;
+
+    Ok(upload == Upload::Written)
+}
+
+/// Renders the base layer of the key at OpenDeck position, stores it and writes the composed image
+///
+/// The base is fitted to the key, themed and toned here; [recompose_keys] draws the layers above
+/// it again later without repeating that.
+pub async fn upload_key_base(
+    device: &dyn DeckDevice,
+    id: &str,
+    opendeck_position: u8,
+    base: Base,
+) -> Result<Upload, MirajazzError> {
+    let kind = device.kind();
+
+    // Translated into the physical position
+    let position = settings::profile_for(id).await.apply(&kind, opendeck_position);
+    let content = content_size(&kind, position);
+
+    let background = settings::background_for(id).await;
+    let scale_mode = settings::scale_mode_for(id).await;
+    let filter = settings::resize_filter_for(id).await;
+    // Themes are tied to OpenDeck positions, so they follow the key across mapping profiles
+    let key_theme = theme::for_key(id, opendeck_position).await;
+    let tone = settings::tone_for(id, &kind).await;
+    let night = settings::night_for(id).await;
+
+    // Rendering is CPU bound, keep it off the workers that read inputs. Events from OpenDeck are
+    // handled one after another and this is awaited, so images of a key still land in order.
+    let base = tokio::task::spawn_blocking(move || {
+        base.map(|image| {
+            // Transparent parts would turn into whatever the JPEG conversion makes of them, same for LCDs and keys
+            let image = scale::flatten(image, background);
+
+            // Non-square images would get stretched by the device conversion, and badges have to land on the key corner
+            let image = scale::fit(image, content, scale_mode, background, filter);
+
+            let image = key_theme.apply(image);
+            // Layers above keep their exact colors, only the base goes through the curve and night tint
+            tone.apply(image, night)
+        })
+    })
+    .await
+    .expect("rendering key image panicked");
+
+    compose::store_base(id, opendeck_position, base.clone(), content).await;
+
+    write_composed(device, id, opendeck_position, base).await
+}
+
+/// Draws the layers on the rendered base and writes the result to the key, without flushing
+///
+/// The image is encoded at the JPEG quality of the device, see [settings::jpeg_quality_for]
+async fn write_composed(
+    device: &dyn DeckDevice,
+    id: &str,
+    opendeck_position: u8,
+    base: Base,
+) -> Result<Upload, MirajazzError> {
+    let kind = device.kind();
+
+    // Translated into the physical position
+    let position = settings::profile_for(id).await.apply(&kind, opendeck_position);
+
+    let format = get_image_format_for_key(&kind, position);
+    let key_size = kind.display_format().key_size(position);
+
+    let background = settings::background_for(id).await;
+    let color_order = device_color_order(id, &kind).await;
+    let layers = compose::Layers::of(id, opendeck_position).await;
+
+    let (content, padding, size) = (key_size.content(), key_size.padding, format.size);
+    let image = tokio::task::spawn_blocking(move || {
+        let image = compose::compose(base, &layers, content)?;
+        // Padding sits outside of everything drawn, on the edge the panel doesn't show
+        let image = scale::pad(image, padding, background);
+        // Whatever came before, the firmware gets exactly the size it declared
+        let image = scale::exact(image, size, background);
+
+        Some(convert_colors(color_order, image))
+    })
+    .await
+    .expect("rendering key image panicked");
+
+    let Some(image) = image else {
+        return Ok(Upload::Empty);
+    };
+
+    // OpenDeck resends the same images on page refreshes and profile reloads, skip the HID transfer
+    if !cache::mark_shown(id, position, &image).await {
+        return Ok(Upload::Unchanged);
+    }
+
+    latency::before_upload().await;
+
+    let key = opendeck_to_device(&kind, position);
+    let quality = settings::jpeg_quality_for(id).await;
+
+    let result = async {
+        let data = encode_key_image(format, image, quality).await?;
+        device.write_image(key, &data).await
+    }
+    .await;
+
+    if let Err(e) = &result {
+        cache::forget_shown(id, position).await;
+        alert_key_failure(id, &[opendeck_position], &format!("upload failed: {}", e)).await;
+    }
+
+    result.map(|()| Upload::Written)
+}
+
+/// Clears the key at physical position, without flushing
+async fn clear_key(device: &dyn DeckDevice, id: &str, position: u8) -> Result<(), MirajazzError> {
+    cache::forget_shown(id, position).await;
+
+    device
+        .clear_button_image(opendeck_to_device(&device.kind(), position))
+        .await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::backend::{MockDevice, MockWrite};
+    use mirajazz::types::{ImageMirroring, ImageRotation};
+
+    /// PNG signature, enough for the format to be recognized
+    const PNG: &[u8] = b"\x89PNG\r\n\x1a\n";
+
+    #[test]
+    fn image_data_urls_are_parsed() {
+        let cases = [
+            ("data:image/png;base64,iVBORw0KGgo=", image::ImageFormat::Png),
+            // Whitespace inside the payload is ignored
+            ("data:image/png;base64,iVBO Rw0K\nGgo=", image::ImageFormat::Png),
+            // Mime type parameters don't matter
+            ("data:image/png;charset=utf-8;base64,iVBORw0KGgo=", image::ImageFormat::Png),
+            // Percent-encoded instead of base64
+            ("data:image/png,%89PNG%0D%0A%1A%0A", image::ImageFormat::Png),
+            // Bytes win over a wrong mime type
+            ("data:image/jpeg;base64,iVBORw0KGgo=", image::ImageFormat::Png),
+        ];
+
+        for (url, format) in cases {
+            let data = parse_image_data_url(url).unwrap_or_else(|e| panic!("{:?}: {}", url, e));
+
+            assert_eq!(data.body, PNG, "{:?}", url);
+            assert_eq!(data.format, format, "{:?}", url);
+        }
+    }
+
+    /// Percent-encodes the image as a PNG data url, the way an action could send it
+    fn png_data_url(image: &DynamicImage) -> String {
+        let mut body = vec![];
+        image
+            .write_to(&mut Cursor::new(&mut body), image::ImageFormat::Png)
+            .unwrap();
+
+        let encoded: String = body.iter().map(|byte| format!("%{:02X}", byte)).collect();
+
+        format!("data:image/png,{}", encoded)
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn png_data_urls_are_decoded_and_uploaded() {
+        let image = DynamicImage::ImageRgb8(image::RgbImage::from_fn(8, 4, |x, y| {
+            image::Rgb([x as u8 * 30, y as u8 * 60, 200])
+        }));
+        let url = png_data_url(&image);
+
+        let (decoded, frames) = decode_image(url.clone()).await.unwrap();
+        assert_eq!(decoded.to_rgb8(), image.to_rgb8());
+        assert!(frames.is_none());
+
+        let id = "N1-png";
+        let device = MockDevice::new(Kind::N1, id);
+        let mut batch = ImageBatch::default();
+
+        handle_set_images(&device, id, &[0], Some(url), &mut batch)
+            .await
+            .unwrap();
+        batch.finish(&device, id).await.unwrap();
+
+        assert_eq!(device.sent(), vec![MockWrite::Image(15)]);
+    }
+
+    #[test]
+    fn malformed_data_urls_are_errors() {
+        for url in ["", "image.png", "https://example.com/image.png", "data:image/png;base64"] {
+            assert!(
+                matches!(parse_image_data_url(url), Err(DataUrlError::Malformed)),
+                "{:?}",
+                url
+            );
+        }
+    }
+
+    #[test]
+    fn corrupt_payloads_are_errors() {
+        for url in ["data:image/png;base64,!!!!", "data:image/png;base64,"] {
+            assert!(
+                matches!(parse_image_data_url(url), Err(DataUrlError::CorruptPayload(_))),
+                "{:?}",
+                url
+            );
+        }
+    }
+
+    #[test]
+    fn unknown_formats_are_unsupported() {
+        assert!(matches!(
+            parse_image_data_url("data:text/plain;base64,aGVsbG8="),
+            Err(DataUrlError::UnsupportedMime(mime)) if mime == "text/plain"
+        ));
+    }
+
+    /// Registers a device tracker the way the watcher does, so image workers can be started
+    async fn track(id: &str) {
+        crate::DEVICE_TRACKERS
+            .lock()
+            .await
+            .push((id.to_string(), tokio_util::task::TaskTracker::new()));
+    }
+
+    #[tokio::test]
+    async fn drained_worker_takes_no_more_jobs() {
+        let id = "N1-drain-closed";
+        track(id).await;
+
+        let worker = start_image_worker(id, &CancellationToken::new()).await.unwrap();
+
+        let started = Instant::now();
+        worker.drain(id).await;
+
+        // An idle worker finishes right away instead of running into the timeout
+        assert!(started.elapsed() < IMAGE_DRAIN_TIMEOUT);
+        assert!(!set_device_images(id, &[0], None).await);
+    }
+
+    /// Takes the device and its tracker away again, they're shared by all tests
+    async fn untrack(id: &str) {
+        DEVICES.write().await.remove(id);
+        crate::DEVICE_TRACKERS
+            .lock()
+            .await
+            .retain(|(tracked, _)| tracked != id);
+    }
+
+    #[tokio::test]
+    async fn job_failing_to_write_is_tried_again_in_order() {
+        let id = "N1-worker-retry";
+        track(id).await;
+
+        let device = MockDevice::new(Kind::N1, id);
+        let sent = device.sent_record();
+        device.fail_clears(1);
+        DEVICES.write().await.insert(id.to_string(), Box::new(device));
+
+        let worker = start_image_worker(id, &CancellationToken::new()).await.unwrap();
+
+        // Queued after the failing job, it has to wait for the retry instead of overtaking
+        assert!(set_device_images(id, &[1], None).await);
+        assert!(set_device_images(id, &[0], None).await);
+        worker.drain(id).await;
+
+        assert_eq!(
+            *sent.lock().unwrap(),
+            vec![MockWrite::Clear(16), MockWrite::Clear(15)]
+        );
+        assert_eq!(stats::get(id).await.upload_retries, 1);
+        assert!(DEVICES.read().await.contains_key(id));
+
+        untrack(id).await;
+    }
+
+    #[tokio::test]
+    async fn undisplayable_image_is_not_tried_again() {
+        let id = "N1-worker-undisplayable";
+        track(id).await;
+
+        let device = MockDevice::new(Kind::N1, id);
+        let sent = device.sent_record();
+        DEVICES.write().await.insert(id.to_string(), Box::new(device));
+
+        let worker = start_image_worker(id, &CancellationToken::new()).await.unwrap();
+
+        assert!(
+            set_device_images(id, &[0], Some("data:image/png;base64,!!!!".to_string())).await
+        );
+        assert!(set_device_images(id, &[1], None).await);
+        worker.drain(id).await;
+
+        // Reported and dropped, the job after it still runs and the device stays
+        assert_eq!(*sent.lock().unwrap(), vec![MockWrite::Clear(16)]);
+        assert_eq!(stats::get(id).await.upload_retries, 0);
+        assert!(DEVICES.read().await.contains_key(id));
+
+        untrack(id).await;
+    }
+
+    #[tokio::test]
+    async fn drain_keeps_the_queue_of_a_newer_connection() {
+        let id = "N1-drain-reconnected";
+        track(id).await;
+
+        let old = start_image_worker(id, &CancellationToken::new()).await.unwrap();
+        let new = start_image_worker(id, &CancellationToken::new()).await.unwrap();
+
+        old.drain(id).await;
+        assert!(IMAGE_QUEUES.lock().await.contains_key(id));
+
+        new.drain(id).await;
+        assert!(!IMAGE_QUEUES.lock().await.contains_key(id));
+    }
+
+    /// Encodes the image as PNG carrying the EXIF orientation tag
+    fn png_with_orientation(image: &DynamicImage, orientation: u16) -> Vec<u8> {
+        use image::{ImageEncoder, codecs::png::PngEncoder};
+
+        // Big endian TIFF header and a single IFD entry: orientation, SHORT, count 1
+        let mut exif = b"MM\0\x2a\0\0\0\x08\0\x01\x01\x12\0\x03\0\0\0\x01".to_vec();
+        exif.extend(orientation.to_be_bytes());
+        exif.extend([0; 6]);
+
+        let image = image.to_rgb8();
+        let mut data = vec![];
+        let mut encoder = PngEncoder::new(&mut data);
+
+        encoder.set_exif_metadata(exif).unwrap();
+        encoder
+            .write_image(
+                image.as_raw(),
+                image.width(),
+                image.height(),
+                image::ExtendedColorType::Rgb8,
+            )
+            .unwrap();
+
+        data
+    }
+
+    /// Turns the image for a key format the way the backend does when encoding
+    fn for_key(
+        image: &DynamicImage,
+        rotation: ImageRotation,
+        mirror: ImageMirroring,
+    ) -> DynamicImage {
+        let image = match rotation {
+            ImageRotation::Rot0 => image.clone(),
+            ImageRotation::Rot90 => image.rotate90(),
+            ImageRotation::Rot180 => image.rotate180(),
+            ImageRotation::Rot270 => image.rotate270(),
+        };
+
+        match mirror {
+            ImageMirroring::None => image,
+            ImageMirroring::X => image.fliph(),
+            ImageMirroring::Y => image.flipv(),
+            ImageMirroring::Both => image.fliph().flipv(),
+        }
+    }
+
+    #[test]
+    fn exif_orientation_is_undone_before_the_key_format() {
+        // 3x2 with every pixel different, so any turn or flip shows
+        let upright = DynamicImage::ImageRgb8(image::RgbImage::from_fn(3, 2, |x, y| {
+            image::Rgb([x as u8 * 80, y as u8 * 120, 0])
+        }));
+
+        // EXIF orientation and how a camera stores the upright image with it
+        let stored = [
+            (1, upright.clone()),
+            (2, upright.fliph()),
+            (3, upright.rotate180()),
+            (4, upright.flipv()),
+            (6, upright.rotate270()),
+            (8, upright.rotate90()),
+        ];
+
+        // Key format, and the size and top left pixel it makes of the upright image
+        let formats = [
+            (ImageRotation::Rot0, ImageMirroring::None, (3, 2), (0, 0)),
+            (ImageRotation::Rot90, ImageMirroring::None, (2, 3), (0, 1)),
+            (ImageRotation::Rot180, ImageMirroring::None, (3, 2), (2, 1)),
+            (ImageRotation::Rot270, ImageMirroring::X, (2, 3), (2, 1)),
+            (ImageRotation::Rot0, ImageMirroring::Both, (3, 2), (2, 1)),
+        ];
+
+        for (orientation, image) in stored {
+            let body = png_with_orientation(&image, orientation);
+            let decoded = decode_oriented(&body, image::ImageFormat::Png).unwrap();
+
+            assert_eq!(
+                decoded.to_rgb8(),
+                upright.to_rgb8(),
+                "orientation {}",
+                orientation
+            );
+
+            // The key format turns the upright image once, whatever the EXIF tag was
+            for (rotation, mirror, size, corner) in formats {
+                let turned = for_key(&decoded, rotation, mirror).to_rgb8();
+
+                assert_eq!(
+                    (turned.dimensions(), turned.get_pixel(0, 0)),
+                    (size, upright.to_rgb8().get_pixel(corner.0, corner.1)),
+                    "orientation {} with {:?} {:?}",
+                    orientation,
+                    rotation,
+                    mirror
+                );
+            }
+        }
+    }
+
+    /// Writes one image per key the way the upload path does, recording it in the batch
+    async fn batch_of(device: &MockDevice, positions: &[u8]) -> ImageBatch {
+        let mut batch = ImageBatch::default();
+
+        for &position in positions {
+            let key = opendeck_to_device(&Kind::N1, position);
+
+            device.write_image(key, &[position]).await.unwrap();
+            batch.uploaded(&Kind::N1, position);
+        }
+
+        batch
+    }
+
+    #[tokio::test]
+    async fn broken_off_flush_is_restarted_with_the_whole_batch() {
+        let id = "N1-batch-restart";
+        let device = MockDevice::new(Kind::N1, id);
+        let batch = batch_of(&device, &[3, 4]).await;
+
+        device.fail_flushes(1);
+        batch.finish(&device, id).await.unwrap();
+
+        assert_eq!(
+            device.sent(),
+            vec![
+                MockWrite::Clear(0),
+                MockWrite::Clear(1),
+                MockWrite::Image(0),
+                MockWrite::Image(1),
+            ]
+        );
+
+        let stats = stats::get(id).await;
+        assert_eq!(stats.restarted_transfers, 1);
+        assert_eq!(stats.upload_retries, 0);
+    }
+
+    #[tokio::test]
+    async fn transfer_breaking_off_twice_is_restarted_only_once() {
+        let id = "N1-batch-restart-once";
+        let device = MockDevice::new(Kind::N1, id);
+        let batch = batch_of(&device, &[5]).await;
+
+        device.fail_flushes(2);
+
+        assert!(matches!(
+            batch.finish(&device, id).await,
+            Err(MirajazzError::HidError(_))
+        ));
+
+        // Cleared before the restart, never shown
+        assert_eq!(device.sent(), vec![MockWrite::Clear(2)]);
+
+        let stats = stats::get(id).await;
+        assert_eq!(stats.restarted_transfers, 1);
+        assert_eq!(stats.upload_retries, 0);
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn strip_image_goes_out_with_the_batch_as_a_tile_per_segment() {
+        let id = "N1-strip";
+        let device = MockDevice::new(Kind::N1, id);
+        let image = DynamicImage::new_rgb8(192, 64);
+        let mut batch = ImageBatch::default();
+
+        handle_set_strip(&device, id, image, &mut batch).await.unwrap();
+        assert!(device.sent().is_empty());
+
+        batch.finish(&device, id).await.unwrap();
+
+        assert_eq!(
+            device.sent(),
+            vec![
+                MockWrite::Image(15),
+                MockWrite::Image(16),
+                MockWrite::Image(17),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn strip_image_is_dropped_for_kinds_without_a_strip() {
+        let id = "AKP153E-strip";
+        let device = MockDevice::new(Kind::Akp153E, id);
+        let mut batch = ImageBatch::default();
+
+        handle_set_strip(&device, id, DynamicImage::new_rgb8(192, 64), &mut batch)
+            .await
+            .unwrap();
+        batch.finish(&device, id).await.unwrap();
+
+        assert!(device.sent().is_empty());
+    }
+
+    #[tokio::test]
+    async fn frame_of_a_stopped_animation_is_dropped() {
+        let id = "N1-frame-stopped";
+        let device = MockDevice::new(Kind::N1, id);
+        let token = CancellationToken::new();
+        let mut batch = ImageBatch::default();
+
+        token.cancel();
+
+        handle_frame(&device, id, 0, DynamicImage::new_rgb8(96, 96), &token, &mut batch)
+            .await
+            .unwrap();
+        batch.finish(&device, id).await.unwrap();
+
+        assert!(device.sent().is_empty());
+    }
+
+    #[tokio::test]
+    async fn assets_go_out_with_the_batch() {
+        let id = "N1-assets";
+        let device = MockDevice::new(Kind::N1, id);
+        let assets = HashMap::from([(0, vec![0xff, 0xd8]), (17, vec![0xff, 0xd8])]);
+        let mut batch = ImageBatch::default();
+
+        handle_assets(&device, id, &assets, &mut batch).await.unwrap();
+        assert!(device.sent().is_empty());
+
+        batch.finish(&device, id).await.unwrap();
+
+        // Assets are keyed by position, they go out in no particular order
+        let sent = device.sent();
+
+        assert_eq!(sent.len(), 2);
+        assert!(sent.contains(&MockWrite::Image(14)) && sent.contains(&MockWrite::Image(15)));
+    }
+
+    #[tokio::test]
+    async fn flushed_batch_sends_every_image_once() {
+        let id = "N1-batch-flushed";
+        let device = MockDevice::new(Kind::N1, id);
+        let batch = batch_of(&device, &[0, 17]).await;
+
+        batch.finish(&device, id).await.unwrap();
+
+        assert_eq!(
+            device.sent(),
+            vec![MockWrite::Image(15), MockWrite::Image(14)]
+        );
+        assert_eq!(stats::get(id).await.upload_retries, 0);
+    }
 }
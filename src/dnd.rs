@@ -0,0 +1,163 @@
+use std::{collections::HashSet, sync::LazyLock, time::Duration};
+
+use chrono::{Local, Timelike};
+use mirajazz::state::DeviceStateUpdate;
+use serde::{Deserialize, Serialize};
+use tokio::sync::Mutex;
+use tokio_util::sync::CancellationToken;
+
+use crate::{
+    DEVICES,
+    device::{apply_brightness, handle_error, target_brightness},
+    settings,
+};
+
+/// How long a key has to be held to override "do not disturb" until the window ends
+const OVERRIDE_HOLD: Duration = Duration::from_secs(2);
+
+/// How often schedules are re-evaluated
+const CHECK_INTERVAL: Duration = Duration::from_secs(30);
+
+/// Daily time window during which the device is blanked and inputs are suppressed
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DndWindow {
+    /// Local time in `HH:MM` format
+    pub start: String,
+    /// Local time in `HH:MM` format, may be before `start` for overnight windows
+    pub end: String,
+}
+
+impl DndWindow {
+    /// Parses window in `HH:MM-HH:MM` format
+    pub fn parse(value: &str) -> Option<Self> {
+        let (start, end) = value.split_once('-')?;
+
+        let window = Self {
+            start: start.trim().to_string(),
+            end: end.trim().to_string(),
+        };
+
+        window.minutes().map(|_| window)
+    }
+
+    /// Returns (start, end) as minutes since midnight
+    fn minutes(&self) -> Option<(u32, u32)> {
+        Some((parse_time(&self.start)?, parse_time(&self.end)?))
+    }
+
+    /// Returns true if minute since midnight falls into the window
+    pub fn contains(&self, minute: u32) -> bool {
+        let Some((start, end)) = self.minutes() else {
+            return false;
+        };
+
+        if start <= end {
+            (start..end).contains(&minute)
+        } else {
+            minute >= start || minute < end
+        }
+    }
+}
+
+fn parse_time(value: &str) -> Option<u32> {
+    let (hours, minutes) = value.split_once(':')?;
+    let (hours, minutes) = (hours.parse::<u32>().ok()?, minutes.parse::<u32>().ok()?);
+
+    (hours < 24 && minutes < 60).then_some(hours * 60 + minutes)
+}
+
+/// Devices that are currently blanked
+static ACTIVE: LazyLock<Mutex<HashSet<String>>> = LazyLock::new(|| Mutex::new(HashSet::new()));
+
+/// Devices where user overrode the current window by holding a key
+static OVERRIDDEN: LazyLock<Mutex<HashSet<String>>> = LazyLock::new(|| Mutex::new(HashSet::new()));
+
+/// Returns true if device is currently in "do not disturb" mode
+pub async fn is_active(id: &str) -> bool {
+    ACTIVE.lock().await.contains(id)
+}
+
+async fn in_window(id: &str) -> bool {
+    let now = Local::now();
+    let minute = now.hour() * 60 + now.minute();
+
+    settings::dnd_for(id)
+        .await
+        .iter()
+        .any(|window| window.contains(minute))
+}
+
+/// Keeps device blanked during its "do not disturb" windows
+pub async fn dnd_task(id: String, token: CancellationToken) {
+    loop {
+        if settings::dnd_for(&id).await.is_empty() && !is_active(&id).await {
+            // Nothing scheduled, only wake up if settings change
+            tokio::select! {
+                _ = settings::changed() => continue,
+                _ = token.cancelled() => break,
+            }
+        }
+
+        let in_window = in_window(&id).await;
+
+        if !in_window {
+            OVERRIDDEN.lock().await.remove(&id);
+        }
+
+        let should_block = in_window && !OVERRIDDEN.lock().await.contains(&id);
+        let blocked = is_active(&id).await;
+
+        if should_block != blocked {
+            set_active(&id, should_block).await;
+        }
+
+        tokio::select! {
+            _ = tokio::time::sleep(CHECK_INTERVAL) => {},
+            _ = settings::changed() => {},
+            _ = token.cancelled() => break,
+        }
+    }
+
+    ACTIVE.lock().await.remove(&id);
+}
+
+async fn set_active(id: &str, active: bool) {
+    if active {
+        log::info!("Entering do not disturb for {}", id);
+        ACTIVE.lock().await.insert(id.to_string());
+    } else {
+        log::info!("Leaving do not disturb for {}", id);
+        ACTIVE.lock().await.remove(id);
+    }
+
+    let brightness = target_brightness(id).await;
+
+    let devices = DEVICES.read().await;
+
+    if let Some(device) = devices.get(id)
+        && let Err(err) = apply_brightness(id, device.as_ref(), brightness).await
+    {
+        drop(devices);
+        handle_error(&id.to_string(), err).await;
+    }
+}
+
+/// Looks at the update while in "do not disturb", returns true if it should be swallowed
+///
+/// Holding any key for [OVERRIDE_HOLD] lifts "do not disturb" until the current window ends
+pub async fn filter_update(id: &str, update: &DeviceStateUpdate, held: Option<Duration>) -> bool {
+    if !is_active(id).await {
+        return false;
+    }
+
+    if let DeviceStateUpdate::ButtonUp(key) = update
+        && held.is_some_and(|held| held >= OVERRIDE_HOLD)
+    {
+        log::info!("Do not disturb overridden for {} by holding key {}", id, key);
+
+        OVERRIDDEN.lock().await.insert(id.to_string());
+        set_active(id, false).await;
+    }
+
+    true
+}
@@ -0,0 +1,224 @@
+use std::{path::PathBuf, time::Duration};
+
+use mirajazz::device::list_devices;
+use tokio::net::TcpStream;
+
+use crate::mappings::{AJAZZ_VID, DEVICE_NAMESPACE, N1_PID, QUERIES};
+
+/// Port OpenDeck listens on for plugin connections, unless started with a different one
+const OPENDECK_PORT: u16 = 57116;
+
+/// Locations udev rules are loaded from
+#[cfg(target_os = "linux")]
+const UDEV_RULE_DIRS: [&str; 3] = [
+    "/etc/udev/rules.d",
+    "/usr/lib/udev/rules.d",
+    "/lib/udev/rules.d",
+];
+
+#[cfg(target_os = "linux")]
+const UDEV_RULES_FILE: &str = "40-opendeck-ajazz-n1.rules";
+
+struct Check {
+    name: &'static str,
+    passed: bool,
+    details: String,
+}
+
+impl Check {
+    fn pass(name: &'static str, details: impl Into<String>) -> Self {
+        Self {
+            name,
+            passed: true,
+            details: details.into(),
+        }
+    }
+
+    fn fail(name: &'static str, details: impl Into<String>) -> Self {
+        Self {
+            name,
+            passed: false,
+            details: details.into(),
+        }
+    }
+}
+
+/// Runs `opendeck-ajazz-n1 doctor [--port <port>]`, printing a table of checks
+///
+/// Returns true if every check passed
+pub async fn run(args: &[String]) -> bool {
+    let port = args
+        .iter()
+        .position(|arg| arg == "--port")
+        .and_then(|index| args.get(index + 1))
+        .and_then(|port| port.parse().ok())
+        .unwrap_or(OPENDECK_PORT);
+
+    let mut checks = vec![];
+
+    checks.extend(check_hid().await);
+    #[cfg(target_os = "linux")]
+    {
+        checks.push(check_udev_rules());
+        checks.push(check_permissions());
+    }
+    checks.push(check_opendeck(port).await);
+    checks.push(check_manifest());
+
+    let width = checks
+        .iter()
+        .map(|check| check.name.len())
+        .max()
+        .unwrap_or(0);
+
+    println!("{:width$}  Result  Details", "Check");
+
+    for check in &checks {
+        println!(
+            "{:width$}  {:6}  {}",
+            check.name,
+            if check.passed { "PASS" } else { "FAIL" },
+            check.details
+        );
+    }
+
+    checks.iter().all(|check| check.passed)
+}
+
+/// HID backend works at all, and sees the device
+async fn check_hid() -> Vec<Check> {
+    match list_devices(&QUERIES).await {
+        Ok(devices) => vec![
+            Check::pass("HID backend", "devices can be enumerated"),
+            if devices.is_empty() {
+                Check::fail(
+                    "Device visible",
+                    "no N1 found, check the cable and USB mode",
+                )
+            } else {
+                let names: Vec<String> = devices
+                    .iter()
+                    .map(|dev| {
+                        format!(
+                            "{} ({})",
+                            dev.name,
+                            dev.serial_number.as_deref().unwrap_or("no serial")
+                        )
+                    })
+                    .collect();
+
+                Check::pass("Device visible", names.join(", "))
+            },
+        ],
+        Err(e) => vec![
+            Check::fail("HID backend", e.to_string()),
+            Check::fail("Device visible", "HID backend is not available"),
+        ],
+    }
+}
+
+#[cfg(target_os = "linux")]
+fn check_udev_rules() -> Check {
+    let found = UDEV_RULE_DIRS
+        .iter()
+        .map(|dir| PathBuf::from(dir).join(UDEV_RULES_FILE))
+        .find(|path| path.exists());
+
+    match found {
+        Some(path) => Check::pass("udev rules", path.display().to_string()),
+        None => Check::fail(
+            "udev rules",
+            format!("{} is not installed, see README", UDEV_RULES_FILE),
+        ),
+    }
+}
+
+/// Every hidraw node of the N1 can be opened for reading and writing
+#[cfg(target_os = "linux")]
+fn check_permissions() -> Check {
+    let Ok(entries) = std::fs::read_dir("/sys/class/hidraw") else {
+        return Check::fail("Permissions", "/sys/class/hidraw is not readable");
+    };
+
+    // HID_ID line of uevent, e.g. HID_ID=0003:00000300:00003007
+    let hid_id = format!("{:08X}:{:08X}", AJAZZ_VID, N1_PID);
+
+    let mut nodes = vec![];
+    let mut denied = vec![];
+
+    for entry in entries.flatten() {
+        let uevent =
+            std::fs::read_to_string(entry.path().join("device/uevent")).unwrap_or_default();
+
+        if !uevent.contains(&hid_id) {
+            continue;
+        }
+
+        let node = PathBuf::from("/dev").join(entry.file_name());
+
+        if let Err(e) = std::fs::OpenOptions::new()
+            .read(true)
+            .write(true)
+            .open(&node)
+        {
+            denied.push(format!("{} ({})", node.display(), e));
+        }
+
+        nodes.push(node);
+    }
+
+    if nodes.is_empty() {
+        Check::pass("Permissions", "no hidraw nodes to check")
+    } else if denied.is_empty() {
+        Check::pass(
+            "Permissions",
+            format!("{} hidraw node(s) accessible", nodes.len()),
+        )
+    } else {
+        Check::fail("Permissions", denied.join(", "))
+    }
+}
+
+async fn check_opendeck(port: u16) -> Check {
+    let connect = TcpStream::connect(("127.0.0.1", port));
+
+    match tokio::time::timeout(Duration::from_secs(2), connect).await {
+        Ok(Ok(_)) => Check::pass("OpenDeck reachable", format!("port {}", port)),
+        Ok(Err(e)) => Check::fail("OpenDeck reachable", format!("port {}: {}", port, e)),
+        Err(_) => Check::fail("OpenDeck reachable", format!("port {}: timed out", port)),
+    }
+}
+
+/// Manifest next to the binary is valid and matches this build
+fn check_manifest() -> Check {
+    let Some(dir) = std::env::current_exe()
+        .ok()
+        .and_then(|exe| exe.parent().map(PathBuf::from))
+    else {
+        return Check::fail("Manifest", "unable to locate plugin directory");
+    };
+
+    let path = dir.join("manifest.json");
+
+    let manifest = match std::fs::read_to_string(&path) {
+        Ok(manifest) => manifest,
+        Err(e) => return Check::fail("Manifest", format!("{}: {}", path.display(), e)),
+    };
+
+    let manifest: serde_json::Value = match serde_json::from_str(&manifest) {
+        Ok(manifest) => manifest,
+        Err(e) => return Check::fail("Manifest", format!("{}: {}", path.display(), e)),
+    };
+
+    match manifest["DeviceNamespace"].as_str() {
+        Some(DEVICE_NAMESPACE) => Check::pass("Manifest", path.display().to_string()),
+        Some(namespace) => Check::fail(
+            "Manifest",
+            format!(
+                "DeviceNamespace is {}, expected {}",
+                namespace, DEVICE_NAMESPACE
+            ),
+        ),
+        None => Check::fail("Manifest", "DeviceNamespace is missing"),
+    }
+}
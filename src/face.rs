@@ -0,0 +1,117 @@
+use mirajazz::state::DeviceStateUpdate;
+
+use crate::mappings::{Kind, Presentation};
+
+/// Translates the update if it's a face button, `None` for any other input
+///
+/// Face buttons come in as keys after the display keys (grid positions). They have no display and
+/// no place in the grid OpenDeck shows, so each presses an OpenDeck encoder of its own, registered
+/// after the ones of the presentation, see [Presentation::face_encoder].
+pub fn route(
+    kind: &Kind,
+    presentation: Presentation,
+    update: &DeviceStateUpdate,
+) -> Option<DeviceStateUpdate> {
+    let (key, down) = match *update {
+        DeviceStateUpdate::ButtonDown(key) => (key, true),
+        DeviceStateUpdate::ButtonUp(key) => (key, false),
+        _ => return None,
+    };
+
+    let face = key.checked_sub(kind.key_count() as u8)?;
+
+    if face as usize >= kind.face_button_count() {
+        return None;
+    }
+
+    let encoder = presentation.face_encoder(kind, face);
+
+    Some(if down {
+        DeviceStateUpdate::EncoderDown(encoder)
+    } else {
+        DeviceStateUpdate::EncoderUp(encoder)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::inputs::{N1_DIAL, N1_HELD_DIAL};
+
+    const PRESENTATIONS: [Presentation; 2] = [Presentation::Grid, Presentation::Strip];
+
+    /// Routes press of the face button and translates it the way it reaches OpenDeck
+    fn press(presentation: Presentation, face: u8) -> DeviceStateUpdate {
+        let kind = Kind::N1;
+        let update = DeviceStateUpdate::ButtonDown(kind.key_count() as u8 + face);
+        let routed = route(&kind, presentation, &update).unwrap();
+
+        presentation.opendeck_update(&kind, routed)
+    }
+
+    #[test]
+    fn face_buttons_press_encoders_of_their_own() {
+        let kind = Kind::N1;
+
+        for presentation in PRESENTATIONS {
+            let (_, _, encoders) = presentation.layout(&kind);
+            let DeviceStateUpdate::EncoderTwist(held_dial, _) = presentation
+                .opendeck_update(&kind, DeviceStateUpdate::EncoderTwist(N1_HELD_DIAL, 1))
+            else {
+                panic!("held dial doesn't twist in {:?}", presentation);
+            };
+            // Registered after the held dial, see [crate::device::register]
+            let long_press = encoders as u8 + 1;
+
+            let mut pressed = vec![];
+
+            for face in 0..kind.face_button_count() as u8 {
+                let DeviceStateUpdate::EncoderDown(encoder) = press(presentation, face) else {
+                    panic!(
+                        "face button {} isn't an encoder in {:?}",
+                        face, presentation
+                    );
+                };
+
+                assert!((encoder as usize) < encoders);
+                assert_ne!(encoder, N1_DIAL);
+                assert_ne!(encoder, long_press);
+                assert_ne!(encoder, held_dial);
+                assert_eq!(presentation.grid_key(&kind, Some("Encoder"), encoder), None);
+
+                pressed.push(encoder);
+            }
+
+            pressed.sort();
+            pressed.dedup();
+            assert_eq!(pressed.len(), kind.face_button_count());
+        }
+    }
+
+    #[test]
+    fn release_goes_to_the_encoder_of_the_press() {
+        let (kind, presentation) = (Kind::N1, Presentation::Grid);
+        let key = kind.key_count() as u8;
+        let encoder = presentation.face_encoder(&kind, 0);
+
+        assert!(matches!(
+            route(&kind, presentation, &DeviceStateUpdate::ButtonUp(key)),
+            Some(DeviceStateUpdate::EncoderUp(released)) if released == encoder
+        ));
+    }
+
+    #[test]
+    fn display_keys_and_dials_are_no_face_buttons() {
+        let kind = Kind::N1;
+
+        for presentation in PRESENTATIONS {
+            for key in 0..kind.key_count() as u8 {
+                let update = DeviceStateUpdate::ButtonDown(key);
+                assert!(route(&kind, presentation, &update).is_none());
+            }
+
+            let update = DeviceStateUpdate::EncoderDown(N1_DIAL);
+            assert!(route(&kind, presentation, &update).is_none());
+        }
+    }
+}
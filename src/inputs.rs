@@ -1,121 +1,447 @@
+use std::time::{Duration, Instant};
+
 use mirajazz::{error::MirajazzError, types::DeviceInput};
-use std::sync::Mutex;
-
-/// N1 key count (6x3 = 18: 15 buttons + 3 top LCDs)
-const N1_KEY_COUNT: usize = 18;
-
-/// N1 encoder/dial input IDs
-/// Input 30: Left face button (above the dial)
-/// Input 31: Right face button (above the dial)  
-/// Input 35: Dial press (push down on the dial)
-/// Input 50: Dial rotation counter-clockwise (left)
-/// Input 51: Dial rotation clockwise (right)
-
-/// Track current encoder state [dial_pressed]
-static DIAL_PRESSED: Mutex<bool> = Mutex::new(false);
-
-/// Process raw input from N1 device (18 keys: 15 buttons + 3 LCDs, plus dial/face buttons)
-/// Device inputs 16-18 (top LCDs) map to OpenDeck keys 0-2
-/// Device inputs 1-15 (main grid) map to OpenDeck keys 3-17
-/// Device inputs 30, 31 (face buttons) are ignored (no display, no action)
-/// Device input 35 (dial press) maps to encoder 0
-/// Device inputs 50, 51 (dial rotation) map to encoder 0 twist
-pub fn process_input_n1(input: u8, state: u8) -> Result<DeviceInput, MirajazzError> {
-    log::info!("Processing N1 input: input={}, state={}", input, state);
-
-    // Handle face buttons (inputs 30, 31) - EXPERIMENTAL: currently ignored
-    if input == 30 || input == 31 {
-        log::info!("N1 face button pressed: input={}, ignoring (experimental)", input);
-        return Ok(DeviceInput::ButtonStateChange(vec![false; N1_KEY_COUNT]));
-    }
-
-    // Handle dial press (input 35)
-    if input == 35 {
-        let is_pressed = state != 0;
-        log::info!("N1 dial press: is_pressed={}", is_pressed);
-        
-        let mut dial_state = DIAL_PRESSED.lock().unwrap();
-        *dial_state = is_pressed;
-        
-        log::info!("→ Sending EncoderStateChange([{}])", is_pressed);
-        return Ok(DeviceInput::EncoderStateChange(vec![is_pressed]));
-    }
-
-    // Handle dial rotation
-    if input == 50 {
-        log::info!("N1 dial CCW rotation → EncoderTwist([-1])");
-        return Ok(DeviceInput::EncoderTwist(vec![-1]));
-    }
-    if input == 51 {
-        log::info!("N1 dial CW rotation → EncoderTwist([1])");
-        return Ok(DeviceInput::EncoderTwist(vec![1]));
-    }
-
-    // Handle main buttons (inputs 1-18)
-    match input {
-        1..=18 => read_button_press_n1(input, state),
-        _ => {
-            log::warn!("Unknown N1 input {}", input);
-            Err(MirajazzError::BadData)
+use opendeck_ajazz_n1::keymap::{self, N1_KEY_CODES, N1_KEY_COUNT};
+
+use crate::mappings::Kind;
+
+// N1 encoder/dial input IDs
+// Input 30: Left face button (above the dial)
+// Input 31: Right face button (above the dial)
+// Input 35: Dial press (push down on the dial)
+// Input 50: Dial rotation counter-clockwise (left)
+// Input 51: Dial rotation clockwise (right)
+
+/// Input codes of the N1 face buttons, left one first
+const N1_FACE_BUTTONS: [u8; 2] = [30, 31];
+
+/// Input code of the dial press
+pub const N1_DIAL_PRESS: u8 = 35;
+
+/// Input code of sync frames, reports saying that nothing is held
+pub const SYNC_FRAME: u8 = 0;
+
+/// Encoder of the dial itself
+pub const N1_DIAL: u8 = 0;
+
+/// Encoder twists of the held dial are reported on, right after the dial itself
+pub const N1_HELD_DIAL: u8 = 1;
+
+/// How a device kind reports its inputs, picked by [crate::mappings::Kind::input_processor]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum InputScheme {
+    /// Top LCDs, main grid, face buttons and the dial, see [InputProcessor::process_n1]
+    #[default]
+    N1,
+    /// Keys only, no LCD offset or encoders (AKP153 family and other v1/v3 grids)
+    Grid {
+        /// Key code of every key, indexed by OpenDeck key, like [N1_KEY_CODES]
+        key_codes: &'static [u8],
+    },
+}
+
+impl InputScheme {
+    /// Returns key code of every key, indexed by OpenDeck key
+    ///
+    /// Input reports carry these codes, image uploads address `code - 1`; both directions of the
+    /// mapping come from this one table.
+    pub fn key_codes(&self) -> &'static [u8] {
+        match self {
+            Self::N1 => &N1_KEY_CODES,
+            Self::Grid { key_codes } => key_codes,
         }
     }
 }
 
-fn read_button_states(states: &[u8], key_count: usize) -> Vec<bool> {
-    let mut bools = vec![];
-    for i in 0..key_count {
-        bools.push(states.get(i + 1).copied().unwrap_or(0) != 0);
-    }
-    bools
+/// Input processing state of one device
+///
+/// Every device has its own, so pressing the dial of one unit can't change what is tracked for
+/// another one connected at the same time
+#[derive(Debug, Default)]
+pub struct InputProcessor {
+    /// How the device reports inputs
+    scheme: InputScheme,
+    /// Whether the dial is held down
+    dial_pressed: bool,
+    /// When the dial was pressed last, kept after release as held back presses are sent then
+    dial_pressed_at: Option<Instant>,
+    /// Last sign of the dial being held on purpose: its press, or a twist while it's held
+    dial_active_at: Option<Instant>,
+    /// Whether twists of the held dial go to [N1_HELD_DIAL], see [Self::set_hold_rotate]
+    hold_rotate: bool,
+    /// Whether the dial was twisted since it got pressed, the press was only a modifier then
+    twisted_while_held: bool,
+    /// Input to process right after the last one, see [Self::take_pending]
+    pending: Option<DeviceInput>,
+    /// Whether the firmware reports releases, keys are only tracked then
+    reports_releases: bool,
+    /// Keys (and face buttons after them) held down as far as presses and releases tell
+    keys_held: Vec<bool>,
 }
 
-/// Converts opendeck key index to device key index for N1
-/// Maps 6×3 grid to device inputs.
-/// Both top LCDs (16-18) and main grid (1-15) have a +1 offset.
-pub fn opendeck_to_device(key: u8) -> u8 {
-    match key {
-        // Top row LCDs: send position-1 to compensate for +1 offset
-        0 => 15,  // Want 16, send 15 (15+1=16)
-        1 => 16,  // Want 17, send 16 (16+1=17)
-        2 => 17,  // Want 18, send 17 (17+1=18)
-        // Main grid (3-17): send (key-3) to compensate for +1 offset
-        3..=17 => key - 3,
-        _ => key,
+impl InputProcessor {
+    pub fn new(scheme: InputScheme) -> Self {
+        Self {
+            scheme,
+            ..Self::default()
+        }
     }
-}
 
-/// Converts N1 device key index to opendeck key index
-fn device_to_opendeck_n1(key: usize) -> usize {
-    match key {
-        // Top LCDs: direct mapping
-        16 => 0,
-        17 => 1,
-        18 => 2,
-        // Main grid (1-15): direct mapping to OpenDeck 3-17
-        1..=15 => key + 2,
-        _ => key.saturating_sub(1),
+    /// Turns the hold-and-rotate gesture on or off
+    ///
+    /// While on, twists of the held dial are reported on encoder [N1_HELD_DIAL] instead of the
+    /// dial, and the dial press is held back until release: it's sent (press and release at once)
+    /// only if the dial wasn't twisted meanwhile, so using it as a modifier never triggers the
+    /// press action. Needs firmware reporting releases, otherwise the dial never counts as held.
+    pub fn set_hold_rotate(&mut self, hold_rotate: bool) {
+        self.hold_rotate = hold_rotate;
+    }
+
+    /// Whether the dial is held down, as far as the reports tell
+    pub fn is_dial_held(&self) -> bool {
+        self.dial_pressed
+    }
+
+    /// Returns when the dial was pressed last, even if the press was only sent on release
+    pub fn dial_pressed_at(&self) -> Option<Instant> {
+        self.dial_pressed_at
+    }
+
+    /// Returns when the held dial counts as stuck, `timeout` after its press or its last twist
+    ///
+    /// Quick taps sometimes lose their release report, and OpenDeck would treat the dial as held
+    /// forever. `None` while the dial isn't held.
+    pub fn dial_release_deadline(&self, timeout: Duration) -> Option<Instant> {
+        self.dial_active_at
+            .filter(|_| self.dial_pressed)
+            .map(|at| at + timeout)
+    }
+
+    /// Tells whether the firmware reports releases, see [Self::read_button_press]
+    pub fn set_reports_releases(&mut self, reports_releases: bool) {
+        self.reports_releases = reports_releases;
+    }
+
+    /// Returns input that has to follow the one [Self::process] returned last, if any
+    pub fn take_pending(&mut self) -> Option<DeviceInput> {
+        self.pending.take()
+    }
+
+    /// Processes raw input of the device, as its [InputScheme] says
+    pub fn process(&mut self, input: u8, state: u8) -> Result<DeviceInput, MirajazzError> {
+        match self.scheme {
+            InputScheme::N1 => self.process_n1(input, state),
+            InputScheme::Grid { key_codes } => self.process_grid(key_codes, input, state),
+        }
+    }
+
+    /// Process raw input from N1 device (18 keys: 15 buttons + 3 LCDs, plus dial/face buttons)
+    /// Device inputs 16-18 (top LCDs) map to OpenDeck keys 0-2
+    /// Device inputs 1-15 (main grid) map to OpenDeck keys 3-17
+    /// Device inputs 30, 31 (face buttons) map to keys 18, 19, routed by [crate::face]
+    /// Device input 35 (dial press) maps to encoder 0
+    /// Device inputs 50, 51 (dial rotation) map to encoder 0 twist
+    fn process_n1(&mut self, input: u8, state: u8) -> Result<DeviceInput, MirajazzError> {
+        log::info!("Processing N1 input: input={}, state={}", input, state);
+
+        let button_count = N1_KEY_COUNT + N1_FACE_BUTTONS.len();
+
+        // Handle face buttons (inputs 30, 31), they follow the display keys
+        if N1_FACE_BUTTONS.contains(&input) {
+            log::info!("N1 face button: input={}, state={}", input, state);
+            return Ok(self.read_button_press(button_count, n1_button_index(input), state));
+        }
+
+        // Handle dial press (input 35)
+        if input == N1_DIAL_PRESS {
+            let is_pressed = state != 0;
+
+            // Firmwares reporting presses only send the same state every time
+            if is_pressed != self.dial_pressed {
+                log::info!("N1 dial press: is_pressed={}", is_pressed);
+
+                self.dial_active_at = is_pressed.then(Instant::now);
+
+                if is_pressed {
+                    self.dial_pressed_at = self.dial_active_at;
+                }
+            }
+
+            let was_pressed = std::mem::replace(&mut self.dial_pressed, is_pressed);
+
+            if self.hold_rotate {
+                return Ok(self.hold_dial(was_pressed, is_pressed));
+            }
+
+            log::info!("→ Sending EncoderStateChange([{}])", is_pressed);
+            return Ok(DeviceInput::EncoderStateChange(vec![is_pressed]));
+        }
+
+        // Handle dial rotation while held, on an encoder of its own
+        if (input == 50 || input == 51) && self.hold_rotate && self.dial_pressed {
+            let mut twist = vec![0; N1_HELD_DIAL as usize + 1];
+            twist[N1_HELD_DIAL as usize] = if input == 50 { -1 } else { 1 };
+
+            self.twisted_while_held = true;
+            self.dial_active_at = Some(Instant::now());
+
+            log::info!("N1 held dial rotation → EncoderTwist({:?})", twist);
+            return Ok(DeviceInput::EncoderTwist(twist));
+        }
+
+        // Twisting the held dial means it's held on purpose
+        if (input == 50 || input == 51) && self.dial_pressed {
+            self.dial_active_at = Some(Instant::now());
+        }
+
+        // Handle dial rotation
+        if input == 50 {
+            log::info!("N1 dial CCW rotation → EncoderTwist([-1])");
+            return Ok(DeviceInput::EncoderTwist(vec![-1]));
+        }
+        if input == 51 {
+            log::info!("N1 dial CW rotation → EncoderTwist([1])");
+            return Ok(DeviceInput::EncoderTwist(vec![1]));
+        }
+
+        // Handle main buttons (inputs 1-18) and sync frames
+        match input {
+            SYNC_FRAME | 1..=18 => {
+                Ok(self.read_button_press(button_count, n1_button_index(input), state))
+            }
+            _ => {
+                log::warn!("Unknown N1 input {}", input);
+                Err(MirajazzError::BadData)
+            }
+        }
+    }
+
+    /// Processes raw input of a device with keys only, codes are looked up in `key_codes`
+    fn process_grid(
+        &mut self,
+        key_codes: &[u8],
+        input: u8,
+        state: u8,
+    ) -> Result<DeviceInput, MirajazzError> {
+        log::info!("Processing grid input: input={}, state={}", input, state);
+
+        if input == SYNC_FRAME {
+            return Ok(self.read_button_press(key_codes.len(), None, state));
+        }
+
+        match keymap::code_to_key(key_codes, input) {
+            Some(key) => Ok(self.read_button_press(key_codes.len(), Some(key), state)),
+            None => {
+                log::warn!("Unknown grid input {}", input);
+                Err(MirajazzError::BadData)
+            }
+        }
+    }
+
+    /// Returns state of all `button_count` buttons after a report of the one at `index`
+    ///
+    /// Reports only name the key that changed. Sync frames (input 0, no index) come as if nothing
+    /// was held, also in the middle of a hold, so they report the keys held so far; only a release
+    /// report lets go of a key. Without release reports every report is a press of its key alone.
+    fn read_button_press(
+        &mut self,
+        button_count: usize,
+        index: Option<usize>,
+        state: u8,
+    ) -> DeviceInput {
+        if !self.reports_releases {
+            let mut states = vec![false; button_count];
+
+            if let Some(index) = index {
+                states[index] = state != 0;
+            }
+
+            return DeviceInput::ButtonStateChange(states);
+        }
+
+        self.keys_held.resize(button_count, false);
+
+        if let Some(index) = index {
+            self.keys_held[index] = state != 0;
+        }
+
+        DeviceInput::ButtonStateChange(self.keys_held.clone())
+    }
+
+    /// Holds the dial press back until release, drops it if the dial was twisted meanwhile
+    fn hold_dial(&mut self, was_pressed: bool, is_pressed: bool) -> DeviceInput {
+        match (was_pressed, is_pressed) {
+            (false, true) => {
+                self.twisted_while_held = false;
+                DeviceInput::NoData
+            }
+            (true, false) if !std::mem::take(&mut self.twisted_while_held) => {
+                log::info!("→ Sending held back dial press");
+
+                self.pending = Some(DeviceInput::EncoderStateChange(vec![false]));
+                DeviceInput::EncoderStateChange(vec![true])
+            }
+            (true, false) => {
+                log::info!("Dial press was used for twisting, not sending it");
+                DeviceInput::NoData
+            }
+            // Repeated state of firmwares sending presses more than once
+            _ => DeviceInput::NoData,
+        }
     }
 }
 
-fn read_button_press_n1(input: u8, state: u8) -> Result<DeviceInput, MirajazzError> {
-    let mut button_states = vec![0x01];
-    button_states.extend(vec![0u8; N1_KEY_COUNT + 1]);
+/// Converts OpenDeck key index to the key index images of the kind are uploaded to
+///
+/// See [InputScheme::key_codes], N1 puts its top LCDs first while grid kinds don't.
+pub fn opendeck_to_device(kind: &Kind, key: u8) -> u8 {
+    keymap::key_to_upload(kind.input_scheme().key_codes(), key)
+}
 
-    if input == 0 {
-        return Ok(DeviceInput::ButtonStateChange(read_button_states(
-            &button_states,
-            N1_KEY_COUNT,
-        )));
+/// Converts N1 key or face button input code to its index in button states, `None` for other codes
+fn n1_button_index(input: u8) -> Option<usize> {
+    keymap::code_to_key(&N1_KEY_CODES, input).or_else(|| {
+        N1_FACE_BUTTONS
+            .iter()
+            .position(|code| *code == input)
+            .map(|face| N1_KEY_COUNT + face)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn dial(processor: &mut InputProcessor, state: u8) -> Vec<bool> {
+        match processor.process(35, state).unwrap() {
+            DeviceInput::EncoderStateChange(states) => states,
+            _ => panic!("dial press wasn't an encoder state change"),
+        }
     }
 
-    let pressed_index: usize = device_to_opendeck_n1(input as usize);
+    #[test]
+    fn dial_state_is_kept_per_device() {
+        let mut first = InputProcessor::default();
+        let mut second = InputProcessor::default();
 
-    if pressed_index < N1_KEY_COUNT {
-        button_states[pressed_index + 1] = state;
+        // Interleaved like two units reporting at the same time
+        assert_eq!(dial(&mut first, 1), [true]);
+        assert!(!second.dial_pressed);
+
+        assert_eq!(dial(&mut second, 1), [true]);
+        assert_eq!(dial(&mut first, 0), [false]);
+        assert!(!first.dial_pressed);
+        assert!(second.dial_pressed);
+
+        assert_eq!(dial(&mut second, 0), [false]);
+        assert!(!second.dial_pressed);
+    }
+
+    #[test]
+    fn n1_uploads_put_the_top_lcds_after_the_grid() {
+        // Top LCDs are codes 16-18, the first grid key is code 1
+        for (key, upload) in [(0, 15), (2, 17), (3, 0), (17, 14)] {
+            assert_eq!(opendeck_to_device(&Kind::N1, key), upload);
+        }
     }
 
-    Ok(DeviceInput::ButtonStateChange(read_button_states(
-        &button_states,
-        N1_KEY_COUNT,
-    )))
+    #[test]
+    fn akp153_uploads_follow_the_mirajazz_example() {
+        // Upload indices of the AKP153R example's opendeck_to_device
+        let uploads = [12, 9, 6, 3, 0, 15, 13, 10, 7, 4, 1, 16, 14, 11, 8, 5, 2, 17];
+
+        for kind in [Kind::Akp153, Kind::Akp153E, Kind::Akp153R] {
+            for (key, upload) in uploads.into_iter().enumerate() {
+                assert_eq!(opendeck_to_device(&kind, key as u8), upload, "{:?}", kind);
+            }
+        }
+    }
+
+    #[test]
+    fn grid_kinds_report_keys_without_an_lcd_offset() {
+        let kind = Kind::Akp153E;
+        let mut processor = InputProcessor::new(kind.input_scheme());
+
+        // Code 13 is the top left key of the AKP153 grid, code 1 the fifth of the top row
+        for (code, key) in [(13, 0), (1, 4), (18, 17)] {
+            let DeviceInput::ButtonStateChange(states) = processor.process(code, 1).unwrap() else {
+                panic!("code {} isn't a key", code);
+            };
+
+            assert_eq!(states.len(), kind.key_count());
+            assert_eq!(states.iter().position(|held| *held), Some(key));
+        }
+
+        assert!(processor.process(N1_FACE_BUTTONS[0], 1).is_err());
+    }
+
+    #[test]
+    fn every_kind_reports_keys_where_their_images_go() {
+        for kind in Kind::ALL {
+            let mut processor = InputProcessor::new(kind.input_scheme());
+
+            for key in 0..kind.key_count() as u8 {
+                let code = opendeck_to_device(&kind, key) + 1;
+                let DeviceInput::ButtonStateChange(states) = processor.process(code, 1).unwrap()
+                else {
+                    panic!("{:?} code {} isn't a key", kind, code);
+                };
+
+                assert_eq!(
+                    states.iter().position(|held| *held),
+                    Some(key as usize),
+                    "{:?}",
+                    kind
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn every_kind_maps_keys_to_unique_uploads_and_back() {
+        for kind in Kind::ALL {
+            let codes = kind.input_scheme().key_codes();
+            let mut uploads = vec![];
+
+            assert_eq!(codes.len(), kind.key_count(), "{:?}", kind);
+            assert!(keymap::is_bijection(codes), "{:?}", kind);
+
+            for key in 0..kind.key_count() as u8 {
+                let upload = opendeck_to_device(&kind, key);
+
+                assert!(
+                    (upload as usize) < kind.key_count(),
+                    "{:?} key {}",
+                    kind,
+                    key
+                );
+                assert!(!uploads.contains(&upload), "{:?} key {}", kind, key);
+                assert_eq!(
+                    keymap::code_to_key(codes, upload + 1),
+                    Some(key as usize),
+                    "{:?}",
+                    kind
+                );
+
+                uploads.push(upload);
+            }
+        }
+    }
+
+    #[test]
+    fn every_kind_maps_input_codes_to_unique_keys() {
+        for kind in Kind::ALL {
+            let codes = kind.input_scheme().key_codes();
+            let mut keys = vec![];
+
+            for code in 1..=kind.key_count() as u8 {
+                let key = keymap::code_to_key(codes, code).expect("every code is a key");
+
+                assert!(!keys.contains(&key), "{:?} code {}", kind, code);
+                assert_eq!(codes[key], code);
+
+                keys.push(key);
+            }
+        }
+    }
 }
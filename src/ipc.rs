@@ -0,0 +1,1179 @@
+use std::path::PathBuf;
+
+use image::DynamicImage;
+use openaction::global_events::SetImageEvent;
+use tokio::{
+    io::{AsyncBufReadExt, AsyncWriteExt, BufReader},
+    net::{UnixListener, UnixStream},
+};
+use tokio_util::sync::CancellationToken;
+
+use crate::{
+    DEVICES, assets, cache,
+    compose::{self, Layer},
+    device::{
+        ImageData, apply_brightness, decode_oriented, handle_error, parse_image_data_url,
+        redraw_from_cache, register, set_device_image, set_device_images, set_device_strip_image,
+        target_brightness,
+    },
+    dnd::DndWindow,
+    keylock,
+    mappings::{ColorOrder, MappingProfile, N1_LCD_STRIP, Presentation},
+    mirror::{self, Mirror},
+    overlay::{self, Badge},
+    placeholder, protocol, reaction,
+    scale::{self, ResizeFilter, ScaleMode},
+    settings::{self, DevicePayload},
+    stats,
+    theme::{self, Theme},
+    tone::{Tint, Tone},
+    twist::{TwistCurve, TwistSteps},
+};
+
+/// Returns whether the control socket was turned on with `OPENDECK_N1_CONTROL_SOCKET=1`
+///
+/// Anyone who can reach the socket can drive the devices, so it's off unless asked for.
+pub fn enabled() -> bool {
+    std::env::var("OPENDECK_N1_CONTROL_SOCKET").is_ok_and(|value| value == "1")
+}
+
+/// Returns path of the control socket, `None` without `XDG_RUNTIME_DIR`
+///
+/// The runtime dir is private to the user. The temp dir is shared by every user of the machine,
+/// so there is no fallback to it.
+pub fn socket_path() -> Option<PathBuf> {
+    std::env::var_os("XDG_RUNTIME_DIR")
+        .filter(|dir| !dir.is_empty())
+        .map(|dir| PathBuf::from(dir).join("opendeck-ajazz-n1.sock"))
+}
+
+/// Listens for control connections until cancelled
+///
+/// Accepts one text command per line and answers with a single line starting with `ok` or `error:`
+pub async fn ipc_task(token: CancellationToken) {
+    let Some(path) = socket_path() else {
+        log::error!("XDG_RUNTIME_DIR is not set, control socket is not started");
+        return;
+    };
+
+    // Socket file is left behind if the plugin was killed
+    std::fs::remove_file(&path).ok();
+
+    let listener = match UnixListener::bind(&path) {
+        Ok(listener) => listener,
+        Err(e) => {
+            log::error!("Unable to bind control socket {}: {}", path.display(), e);
+            return;
+        }
+    };
+
+    log::info!("Control socket is listening on {}", path.display());
+
+    loop {
+        let stream = tokio::select! {
+            v = listener.accept() => v,
+            _ = token.cancelled() => break,
+        };
+
+        match stream {
+            Ok((stream, _)) => {
+                tokio::spawn(connection_task(stream, token.clone()));
+            }
+            Err(e) => log::error!("Failed to accept control connection: {}", e),
+        }
+    }
+
+    std::fs::remove_file(&path).ok();
+
+    log::info!("Control socket closed");
+}
+
+async fn connection_task(stream: UnixStream, token: CancellationToken) {
+    let (reader, mut writer) = stream.into_split();
+    let mut lines = BufReader::new(reader).lines();
+
+    loop {
+        let line = tokio::select! {
+            v = lines.next_line() => v,
+            _ = token.cancelled() => break,
+        };
+
+        let line = match line {
+            Ok(Some(line)) => line,
+            Ok(None) => break,
+            Err(e) => {
+                log::error!("Failed to read control command: {}", e);
+                break;
+            }
+        };
+
+        let line = line.trim();
+
+        if line.is_empty() {
+            continue;
+        }
+
+        let response = if line.starts_with('{') {
+            handle_request(line).await
+        } else {
+            match handle_command(line).await {
+                Ok(message) if message.is_empty() => "ok".to_string(),
+                Ok(message) => format!("ok {}", message),
+                Err(message) => format!("error: {}", message),
+            }
+        };
+
+        if writer
+            .write_all(format!("{}\n", response).as_bytes())
+            .await
+            .is_err()
+        {
+            break;
+        }
+    }
+}
+
+/// Decodes image sent as a data url
+fn decode_image(value: &str) -> Result<DynamicImage, String> {
+    let ImageData { body, format } = parse_image_data_url(value).map_err(|e| e.to_string())?;
+
+    decode_oriented(&body, format).map_err(|e| format!("invalid image: {}", e))
+}
+
+fn parse_position(value: Option<&str>) -> Result<u8, String> {
+    value
+        .ok_or("missing position")?
+        .parse::<u8>()
+        .map_err(|e| format!("invalid position: {}", e))
+}
+
+/// Parses comma separated list of positions and inclusive ranges, e.g. `0,2,6-8`
+fn parse_positions(value: Option<&str>) -> Result<Vec<u8>, String> {
+    let mut positions = vec![];
+
+    for part in value.ok_or("missing position")?.split(',') {
+        match part.split_once('-') {
+            Some((start, end)) => {
+                let (start, end) = (parse_position(Some(start))?, parse_position(Some(end))?);
+
+                if start > end {
+                    return Err(format!("invalid range: {}", part));
+                }
+
+                positions.extend(start..=end);
+            }
+            None => positions.push(parse_position(Some(part))?),
+        }
+    }
+
+    positions.sort_unstable();
+    positions.dedup();
+
+    Ok(positions)
+}
+
+/// Commands every build understands, see [commands]
+const COMMANDS: &[&str] = &[
+    "hello",
+    "set-image",
+    "set-strip",
+    "revert",
+    "set-badge",
+    "set-title",
+    "set-empty-image",
+    "set-profile",
+    "set-dnd",
+    "set-max-brightness",
+    "set-quirk",
+    "set-theme",
+    "set-dark-image",
+    "set-color-order",
+    "set-scale-mode",
+    "set-resize-filter",
+    "set-jpeg-quality",
+    "set-background",
+    "set-tone",
+    "set-night",
+    "set-asset-dir",
+    "set-mirror",
+    "set-group",
+    "set-twist-curve",
+    "set-twist-steps",
+    "set-hold-rotate",
+    "set-dial-long-press",
+    "set-dial-release",
+    "set-legacy-id",
+    "set-legacy-mode",
+    "set-input-map",
+    "lock",
+    "set-lock-chord",
+    "set-presentation",
+    "device-settings",
+    "refresh",
+    "stats",
+    "health",
+    "measure-latency",
+];
+
+/// Returns every command [handle_command] understands in this build, as listed by `hello`
+fn commands() -> Vec<&'static str> {
+    let mut commands = COMMANDS.to_vec();
+
+    if cfg!(feature = "video") {
+        commands.extend(["play-video", "stop-video"]);
+    }
+
+    if cfg!(feature = "repl") {
+        commands.extend(["devices", "dump-state", "send", "set-brightness"]);
+    }
+
+    commands
+}
+
+/// Answers a JSON request line with a JSON response line, see [protocol::Request]
+async fn handle_request(line: &str) -> String {
+    let response = match serde_json::from_str::<protocol::Request>(line) {
+        Ok(request) => {
+            let result = if request.command == "hello" {
+                protocol::hello(request.args.first().map(String::as_str), commands())
+                    .and_then(|hello| serde_json::to_value(hello).map_err(|e| e.to_string()))
+            } else {
+                // Same as the text line, so free text like titles may only be the last argument
+                handle_command(&format!("{} {}", request.command, request.args.join(" ")))
+                    .await
+                    .map(serde_json::Value::String)
+            };
+
+            protocol::Response::new(request.id, result)
+        }
+        Err(e) => protocol::Response::new(
+            serde_json::Value::Null,
+            Err(format!("invalid request: {}", e)),
+        ),
+    };
+
+    serde_json::to_string(&response).unwrap_or_else(|e| format!("error: {}", e))
+}
+
+async fn handle_command(line: &str) -> Result<String, String> {
+    log::debug!("Control command: {}", line);
+
+    let mut args = line.split_whitespace();
+    let command = args.next().unwrap_or_default();
+
+    match command {
+        // hello [version], highest protocol version the client speaks
+        "hello" => {
+            let hello = protocol::hello(args.next(), commands())?;
+
+            serde_json::to_string(&hello).map_err(|e| e.to_string())
+        }
+        // set-image <device> <positions> <data url|clear>, positions like 3, 3-5 or 0,2,6-8
+        "set-image" => {
+            let device = args.next().ok_or("missing device")?.to_string();
+            let positions = parse_positions(args.next())?;
+            let image = match args.next().ok_or("missing image")? {
+                "clear" => None,
+                image => Some(image.to_string()),
+            };
+
+            // Checked before the cache, it must not keep images of devices or keys that don't exist
+            let key_count = DEVICES
+                .read()
+                .await
+                .get(&device)
+                .map(|device| device.key_count() as u8)
+                .ok_or("unknown device")?;
+
+            if let Some(position) = positions.iter().find(|&&position| position >= key_count) {
+                return Err(format!(
+                    "invalid position: {}, the device has {} keys",
+                    position, key_count
+                ));
+            }
+
+            for position in &positions {
+                cache::record(&device, *position, image.clone()).await;
+            }
+
+            if !set_device_images(&device, &positions, image).await {
+                return Err("unknown device".to_string());
+            }
+
+            Ok(String::new())
+        }
+        // set-strip <device> <data url>, one wide image across the top LCDs
+        "set-strip" => {
+            let device = args.next().ok_or("missing device")?;
+            let image = decode_image(args.next().ok_or("missing image")?)?;
+
+            let kind = DEVICES.read().await.get(device).map(|device| device.kind());
+
+            if kind.is_some_and(|kind| !Presentation::Strip.supported_by(&kind)) {
+                return Err("device has no LCD strip".to_string());
+            }
+
+            if !set_device_strip_image(device, image).await {
+                return Err("unknown device".to_string());
+            }
+
+            Ok(String::new())
+        }
+        // revert <device> <position>
+        "revert" => {
+            let device = args.next().ok_or("missing device")?.to_string();
+            let position = parse_position(args.next())?;
+
+            let image = cache::revert(&device, position)
+                .await
+                .ok_or("no previous image for this key")?;
+
+            apply_image(device, position, image).await?;
+
+            Ok(String::new())
+        }
+        // set-badge <device> <position> <mute|recording|data url|off>
+        "set-badge" => {
+            let device = args.next().ok_or("missing device")?.to_string();
+            let position = parse_position(args.next())?;
+            let badge = match args.next().ok_or("missing badge")? {
+                "off" => None,
+                value => Some(match Badge::from_name(value) {
+                    Some(badge) => badge,
+                    None => Badge::Image(decode_image(value)?),
+                }),
+            };
+
+            overlay::set_badge(&device, position, badge).await;
+
+            // Only the badge is drawn again, OpenDeck doesn't need to resend the image
+            if !compose::invalidate(&device, &[position], Layer::Badge).await {
+                return Err("unknown device".to_string());
+            }
+
+            Ok(String::new())
+        }
+        // set-title <device> <position> <text|off>, drawn on the key while OpenDeck sends no image
+        "set-title" => {
+            let device = args.next().ok_or("missing device")?.to_string();
+            let position = parse_position(args.next())?;
+            let title = match args.collect::<Vec<_>>().join(" ") {
+                title if title.is_empty() => return Err("missing title".to_string()),
+                title if title == "off" => None,
+                title => Some(title),
+            };
+
+            placeholder::set_title(&device, position, title).await;
+
+            if !compose::invalidate(&device, &[position], Layer::Title).await {
+                return Err("unknown device".to_string());
+            }
+
+            Ok(String::new())
+        }
+        // set-empty-image <device> <position|all> <data url|off>, shown on cleared keys
+        "set-empty-image" => {
+            let device = args.next().ok_or("missing device")?.to_string();
+            let position = match args.next() {
+                Some("all") => None,
+                value => Some(parse_position(value)?),
+            };
+            let image = match args.next().ok_or("missing image")? {
+                "off" => None,
+                value => Some(decode_image(value)?),
+            };
+
+            let key_count = DEVICES
+                .read()
+                .await
+                .get(&device)
+                .map(|device| device.key_count() as u8)
+                .ok_or("unknown device")?;
+
+            placeholder::set_placeholder(&device, position, image).await;
+
+            // Keys that are empty right now pick up the change
+            let positions = match position {
+                Some(position) => vec![position],
+                None => (0..key_count).collect(),
+            };
+
+            for position in positions {
+                if cache::current(&device, position).await.is_none()
+                    && !compose::invalidate(&device, &[position], Layer::Base).await
+                {
+                    return Err("unknown device".to_string());
+                }
+            }
+
+            Ok(String::new())
+        }
+        // set-profile <device> <standard|mirrored>
+        "set-profile" => {
+            let device = args.next().ok_or("missing device")?.to_string();
+            let name = args.next().ok_or("missing profile")?;
+            let profile = MappingProfile::from_name(name)
+                .ok_or_else(|| format!("unknown profile: {}", name))?;
+
+            settings::store_profile(&device, profile).await;
+
+            // Images have to move to their new positions
+            openaction::device_plugin::rerender_images(device)
+                .await
+                .map_err(|e| e.to_string())?;
+
+            Ok(String::new())
+        }
+        // set-dnd <device> <HH:MM-HH:MM[,HH:MM-HH:MM...]|off>
+        "set-dnd" => {
+            let device = args.next().ok_or("missing device")?.to_string();
+            let value = args.next().ok_or("missing schedule")?;
+
+            let windows = if value == "off" {
+                vec![]
+            } else {
+                value
+                    .split(',')
+                    .map(|v| DndWindow::parse(v).ok_or_else(|| format!("invalid window: {}", v)))
+                    .collect::<Result<Vec<_>, _>>()?
+            };
+
+            settings::store_dnd(&device, windows).await;
+
+            Ok(String::new())
+        }
+        // set-max-brightness <device> <percent|default>
+        "set-max-brightness" => {
+            let device = args.next().ok_or("missing device")?.to_string();
+            let value = args.next().ok_or("missing limit")?;
+            let limit = match value {
+                "default" => None,
+                value => match value.parse::<u8>() {
+                    Ok(limit) if limit <= 100 => Some(limit),
+                    _ => return Err(format!("invalid limit: {}", value)),
+                },
+            };
+
+            settings::store_max_brightness(&device, limit).await;
+            refresh_device_brightness(&device).await?;
+
+            Ok(String::new())
+        }
+        // set-quirk <device> <max-brightness|brightness-delay> <value|default>
+        "set-quirk" => {
+            let device = args.next().ok_or("missing device")?.to_string();
+            let quirk = args.next().ok_or("missing quirk")?;
+            let value = args.next().ok_or("missing value")?;
+            let value = match value {
+                "default" => None,
+                value => Some(
+                    value
+                        .parse::<u64>()
+                        .map_err(|_| format!("invalid value: {}", value))?,
+                ),
+            };
+
+            let mut quirks = settings::quirks_for(&device).await;
+
+            match quirk {
+                "max-brightness" => {
+                    quirks.max_brightness = match value {
+                        Some(percent @ 1..=100) => Some(percent as u8),
+                        Some(percent) => return Err(format!("invalid brightness: {}", percent)),
+                        None => None,
+                    }
+                }
+                "brightness-delay" => quirks.brightness_delay_ms = value,
+                quirk => return Err(format!("unknown quirk: {}", quirk)),
+            }
+
+            settings::store_quirks(&device, quirks).await;
+            refresh_device_brightness(&device).await?;
+
+            Ok(String::new())
+        }
+        // set-theme <device> <light|dark|HH:MM-HH:MM>, a window schedules dark theme for that time
+        "set-theme" => {
+            let device = args.next().ok_or("missing device")?.to_string();
+            let value = args.next().ok_or("missing theme")?;
+
+            let (theme, dark_window) = match Theme::from_name(value) {
+                Some(theme) => (theme, None),
+                None => {
+                    let window = DndWindow::parse(value)
+                        .ok_or_else(|| format!("invalid theme: {}", value))?;
+
+                    (Theme::Light, Some(window))
+                }
+            };
+
+            // Theme task of the device picks the change up and redraws it
+            settings::store_theme(&device, theme, dark_window).await;
+
+            Ok(String::new())
+        }
+        // set-dark-image <device> <position> <data url|off>
+        "set-dark-image" => {
+            let device = args.next().ok_or("missing device")?.to_string();
+            let position = parse_position(args.next())?;
+            let image = match args.next().ok_or("missing image")? {
+                "off" => None,
+                value => Some(decode_image(value)?),
+            };
+
+            theme::set_dark_image(&device, position, image).await;
+
+            if theme::current(&device).await == Theme::Dark
+                && let Some(image) = cache::current(&device, position).await
+            {
+                apply_image(device, position, Some(image)).await?;
+            }
+
+            Ok(String::new())
+        }
+        // set-color-order <device> <rgb|bgr|auto>, auto uses the default of the device kind
+        "set-color-order" => {
+            let device = args.next().ok_or("missing device")?.to_string();
+            let value = args.next().ok_or("missing color order")?;
+            let color_order = match value {
+                "auto" => None,
+                value => Some(
+                    ColorOrder::from_name(value)
+                        .ok_or_else(|| format!("unknown color order: {}", value))?,
+                ),
+            };
+
+            settings::store_color_order(&device, color_order).await;
+            redraw_from_cache(&device).await;
+
+            Ok(String::new())
+        }
+        // set-scale-mode <device> <letterbox|fill|center|default>
+        "set-scale-mode" => {
+            let device = args.next().ok_or("missing device")?.to_string();
+            let value = args.next().ok_or("missing scale mode")?;
+            let scale_mode = match value {
+                "default" => None,
+                value => Some(
+                    ScaleMode::from_name(value)
+                        .ok_or_else(|| format!("unknown scale mode: {}", value))?,
+                ),
+            };
+
+            settings::store_scale_mode(&device, scale_mode).await;
+            redraw_from_cache(&device).await;
+
+            Ok(String::new())
+        }
+        // set-resize-filter <device> <nearest|triangle|catmullrom|lanczos3|default>
+        "set-resize-filter" => {
+            let device = args.next().ok_or("missing device")?.to_string();
+            let value = args.next().ok_or("missing filter")?;
+            let filter = match value {
+                "default" => None,
+                value => Some(
+                    ResizeFilter::from_name(value)
+                        .ok_or_else(|| format!("unknown filter: {}", value))?,
+                ),
+            };
+
+            settings::store_resize_filter(&device, filter).await;
+            redraw_from_cache(&device).await;
+
+            Ok(String::new())
+        }
+        // set-jpeg-quality <device> <0-100|default>
+        "set-jpeg-quality" => {
+            let device = args.next().ok_or("missing device")?.to_string();
+            let value = args.next().ok_or("missing quality")?;
+            let quality = match value {
+                "default" => None,
+                value => match value.parse::<u8>() {
+                    Ok(quality) if quality <= 100 => Some(quality),
+                    _ => return Err(format!("invalid quality: {}", value)),
+                },
+            };
+
+            settings::store_jpeg_quality(&device, quality).await;
+            // Keys would look the same before encoding, so they wouldn't be uploaded again
+            cache::forget_all_shown(&device).await;
+            redraw_from_cache(&device).await;
+
+            Ok(String::new())
+        }
+        // set-background <device> <#rrggbb|default>
+        "set-background" => {
+            let device = args.next().ok_or("missing device")?.to_string();
+            let value = args.next().ok_or("missing color")?;
+            let background = match value {
+                "default" => None,
+                value => Some(
+                    scale::parse_color(value).ok_or_else(|| format!("invalid color: {}", value))?,
+                ),
+            };
+
+            settings::store_background(&device, background).await;
+            redraw_from_cache(&device).await;
+
+            Ok(String::new())
+        }
+        // set-tone <device> <gamma,contrast,lift|off|default>
+        "set-tone" => {
+            let device = args.next().ok_or("missing device")?.to_string();
+            let tone = match args.next().ok_or("missing tone")? {
+                "default" => None,
+                "off" => Some(Tone::IDENTITY),
+                value => {
+                    Some(Tone::parse(value).ok_or_else(|| format!("invalid tone: {}", value))?)
+                }
+            };
+
+            settings::store_tone(&device, tone).await;
+            redraw_from_cache(&device).await;
+
+            Ok(String::new())
+        }
+        // set-night <device> <on|off|red,green,blue>, factors between 0 and 1
+        "set-night" => {
+            let device = args.next().ok_or("missing device")?.to_string();
+            let night = match args.next().ok_or("missing night mode")? {
+                "on" => Some(Tint::NIGHT),
+                "off" => None,
+                value => {
+                    Some(Tint::parse(value).ok_or_else(|| format!("invalid tint: {}", value))?)
+                }
+            };
+
+            settings::store_night(&device, night).await;
+            redraw_from_cache(&device).await;
+
+            Ok(String::new())
+        }
+        // set-asset-dir <device> <path|off>, shows the assets right away
+        "set-asset-dir" => {
+            let device = args.next().ok_or("missing device")?.to_string();
+            let dir = match args.next().ok_or("missing folder")? {
+                "off" => None,
+                dir => Some(dir.to_string()),
+            };
+
+            if let Some(dir) = &dir
+                && !std::path::Path::new(dir).is_dir()
+            {
+                return Err(format!("not a folder: {}", dir));
+            }
+
+            settings::store_asset_dir(&device, dir).await;
+
+            let count = assets::show(&device).await?;
+
+            Ok(format!("{} assets shown", count))
+        }
+        // set-mirror <device> <source>:<key>,<key>,...|off, LCD segments show keys of the source
+        "set-mirror" => {
+            let device = args.next().ok_or("missing device")?.to_string();
+            let mirror = match args.next().ok_or("missing mirror")? {
+                "off" => None,
+                value => Some(Mirror::parse(value).ok_or_else(|| {
+                    format!("invalid mirror, expected <source>:<key>,<key>,...: {}", value)
+                })?),
+            };
+
+            if mirror.as_ref().is_some_and(|mirror| mirror.source == device) {
+                return Err("device can't mirror itself".to_string());
+            }
+
+            let kind = DEVICES.read().await.get(&device).map(|device| device.kind());
+
+            if mirror.is_some() && kind.is_some_and(|kind| kind.lcd_strip().is_empty()) {
+                return Err("device has no LCD strip to mirror onto".to_string());
+            }
+
+            let mirrored = |mirror: &Option<Mirror>| mirror.as_ref().map_or(0, |m| m.keys.len());
+            let previous = mirrored(&settings::mirror_for(&device).await);
+            let current = mirrored(&mirror);
+
+            settings::store_mirror(&device, mirror).await;
+            mirror::refresh(&device).await;
+
+            // Segments that show the device's own keys again get their images from OpenDeck
+            if previous > current {
+                let segments: Vec<u8> = N1_LCD_STRIP[current..previous]
+                    .iter()
+                    .map(|(position, _)| *position)
+                    .collect();
+
+                for &segment in &segments {
+                    cache::record(&device, segment, None).await;
+                }
+
+                set_device_images(&device, &segments, None).await;
+
+                openaction::device_plugin::rerender_images(device)
+                    .await
+                    .map_err(|e| e.to_string())?;
+            }
+
+            Ok(String::new())
+        }
+        // set-group <device> <name|off>, grouped devices share brightness
+        "set-group" => {
+            let device = args.next().ok_or("missing device")?.to_string();
+            let group = match args.next().ok_or("missing group")? {
+                "off" => None,
+                name => Some(name.to_string()),
+            };
+
+            settings::store_group(&device, group).await;
+
+            Ok(String::new())
+        }
+        // set-twist-curve <device> <off|linear|quadratic|steps>
+        "set-twist-curve" => {
+            let device = args.next().ok_or("missing device")?.to_string();
+            let name = args.next().ok_or("missing curve")?;
+            let curve =
+                TwistCurve::from_name(name).ok_or_else(|| format!("unknown curve: {}", name))?;
+
+            settings::store_twist_curve(&device, curve).await;
+
+            Ok(String::new())
+        }
+        // set-twist-steps <device> <ms,ms,...> <max factor>|default
+        "set-twist-steps" => {
+            let device = args.next().ok_or("missing device")?.to_string();
+            let steps = match args.next().ok_or("missing thresholds")? {
+                "default" => TwistSteps::default(),
+                thresholds => {
+                    let max_factor = args.next().ok_or("missing max factor")?;
+
+                    TwistSteps::parse(thresholds, max_factor).ok_or_else(|| {
+                        format!("invalid steps: {} {}", thresholds, max_factor)
+                    })?
+                }
+            };
+
+            settings::store_twist_steps(&device, steps).await;
+
+            Ok(String::new())
+        }
+        // set-hold-rotate <device> <on|off>
+        "set-hold-rotate" => {
+            let device = args.next().ok_or("missing device")?.to_string();
+            let hold_rotate = match args.next().ok_or("missing state")? {
+                "on" => true,
+                "off" => false,
+                state => return Err(format!("unknown state: {}", state)),
+            };
+
+            settings::store_hold_rotate(&device, hold_rotate).await;
+
+            // OpenDeck only learns about the extra encoder by registering the device again
+            if DEVICES.read().await.contains_key(&device) {
+                present_again(device).await?;
+            }
+
+            Ok(String::new())
+        }
+        // set-dial-long-press <device> <ms|off>, how long the dial has to be held for a long press
+        "set-dial-long-press" => {
+            let device = args.next().ok_or("missing device")?.to_string();
+            let ms = match args.next().ok_or("missing threshold")? {
+                "off" => 0,
+                value => value
+                    .parse::<u64>()
+                    .map_err(|_| format!("invalid threshold: {}", value))?,
+            };
+
+            settings::store_dial_long_press(&device, ms).await;
+
+            // Long presses go to an encoder of their own, OpenDeck has to learn about it
+            if DEVICES.read().await.contains_key(&device) {
+                present_again(device).await?;
+            }
+
+            Ok(String::new())
+        }
+        // set-dial-release <device> <ms|off>, how long the dial may be held without twisting
+        "set-dial-release" => {
+            let device = args.next().ok_or("missing device")?.to_string();
+            let ms = match args.next().ok_or("missing timeout")? {
+                "off" => 0,
+                value => value
+                    .parse::<u64>()
+                    .map_err(|_| format!("invalid timeout: {}", value))?,
+            };
+
+            settings::store_dial_release(&device, ms).await;
+
+            Ok(String::new())
+        }
+        // set-legacy-id <legacy id> <current id|off>, used from the next connection
+        "set-legacy-id" => {
+            let legacy = args.next().ok_or("missing legacy id")?;
+            let current = match args.next().ok_or("missing device")? {
+                "off" => None,
+                current => Some(current.to_string()),
+            };
+
+            settings::store_legacy_id(legacy, current).await;
+
+            Ok(String::new())
+        }
+        // set-legacy-mode <register|migrate>, used from the next connection
+        "set-legacy-mode" => {
+            let migrate = match args.next().ok_or("missing mode")? {
+                "register" => false,
+                "migrate" => true,
+                mode => return Err(format!("unknown mode: {}", mode)),
+            };
+
+            settings::store_migrate_legacy_ids(migrate).await;
+
+            Ok(String::new())
+        }
+        // lock <device> <on|off|toggle>
+        "lock" => {
+            let device = args.next().ok_or("missing device")?.to_string();
+
+            let locked = match args.next().ok_or("missing state")? {
+                "on" => true,
+                "off" => false,
+                "toggle" => !keylock::is_locked(&device).await,
+                state => return Err(format!("unknown state: {}", state)),
+            };
+
+            keylock::set_locked(&device, locked).await;
+
+            Ok(String::new())
+        }
+        // set-lock-chord <device> <positions|off>, at least two keys so a single press can't lock
+        "set-lock-chord" => {
+            let device = args.next().ok_or("missing device")?.to_string();
+
+            let chord = match args.next() {
+                Some("off") => vec![],
+                value => {
+                    let chord = parse_positions(value)?;
+
+                    if chord.len() < 2 {
+                        return Err("chord needs at least two keys".to_string());
+                    }
+
+                    chord
+                }
+            };
+
+            settings::store_lock_chord(&device, chord).await;
+
+            Ok(String::new())
+        }
+        // set-presentation <device> <grid|strip>
+        "set-presentation" => {
+            let device = args.next().ok_or("missing device")?.to_string();
+            let name = args.next().ok_or("missing presentation")?;
+            let presentation = Presentation::from_name(name)
+                .ok_or_else(|| format!("unknown presentation: {}", name))?;
+
+            let kind = DEVICES
+                .read()
+                .await
+                .get(&device)
+                .map(|device| device.kind())
+                .ok_or("unknown device")?;
+
+            if !presentation.supported_by(&kind) {
+                return Err(format!("{} has no {} presentation", kind.human_name(), name));
+            }
+
+            settings::store_presentation(&device, presentation).await;
+            present_again(device).await?;
+
+            Ok(String::new())
+        }
+        // device-settings <device> <json>, device-scoped settings in the shape OpenDeck would send them
+        "device-settings" => {
+            let device = args.next().ok_or("missing device")?.to_string();
+            let json = args.collect::<Vec<_>>().join(" ");
+            let value = serde_json::from_str(&json).map_err(|e| format!("invalid json: {}", e))?;
+
+            apply_device_settings(&device, value).await?;
+
+            Ok(String::new())
+        }
+        // refresh <device> [opendeck], for stale or garbled keys
+        "refresh" => {
+            let device = args.next().ok_or("missing device")?.to_string();
+            let from_opendeck = match args.next() {
+                None => false,
+                Some("opendeck") => true,
+                Some(value) => return Err(format!("unknown source: {}", value)),
+            };
+
+            // What the keys show can't be trusted, so nothing may be skipped as already shown
+            cache::forget_all_shown(&device).await;
+
+            // Clearing first also fixes keys that should be empty, they get their placeholders back
+            apply_clear_all(device.clone()).await?;
+
+            if from_opendeck {
+                openaction::device_plugin::rerender_images(device)
+                    .await
+                    .map_err(|e| e.to_string())?;
+            } else {
+                redraw_from_cache(&device).await;
+            }
+
+            Ok(String::new())
+        }
+        // stats <device>
+        "stats" => {
+            let device = args.next().ok_or("missing device")?;
+
+            Ok(stats::get(device).await.to_string())
+        }
+        // health, plugin-wide state that isn't tied to a device
+        "health" => {
+            let devices = DEVICES.read().await.len();
+
+            Ok(format!(
+                "devices={} decoded_images: {}",
+                devices,
+                cache::decoded_stats().await
+            ))
+        }
+        // measure-latency <device> <position> [rounds]
+        "measure-latency" => {
+            let device = args.next().ok_or("missing device")?;
+            let position = args
+                .next()
+                .ok_or("missing position")?
+                .parse::<u8>()
+                .map_err(|e| format!("invalid position: {}", e))?;
+            let rounds = match args.next() {
+                Some(rounds) => rounds
+                    .parse::<u32>()
+                    .map_err(|e| format!("invalid rounds: {}", e))?,
+                None => reaction::DEFAULT_ROUNDS,
+            };
+
+            reaction::run(device, position, rounds).await
+        }
+        // play-video <device> <position>:<cols>x<rows> <fps> <file or url>
+        #[cfg(feature = "video")]
+        "play-video" => {
+            let device = args.next().ok_or("missing device")?;
+            let region = args.next().ok_or("missing region")?;
+            let region = crate::video::Region::parse(region)
+                .ok_or_else(|| format!("invalid region: {}", region))?;
+            let fps = args
+                .next()
+                .ok_or("missing fps")?
+                .parse::<u32>()
+                .map_err(|e| format!("invalid fps: {}", e))?;
+            let source = args.next().ok_or("missing source")?.to_string();
+
+            crate::video::play(device, region, fps, source).await?;
+
+            Ok(String::new())
+        }
+        // stop-video <device>
+        #[cfg(feature = "video")]
+        "stop-video" => {
+            let device = args.next().ok_or("missing device")?;
+
+            crate::video::stop(device).await?;
+
+            Ok(String::new())
+        }
+        #[cfg(feature = "repl")]
+        "devices" | "dump-state" | "send" | "set-brightness" => {
+            repl_command(command, args.collect()).await
+        }
+        _ => Err(format!("unknown command: {}", command)),
+    }
+}
+
+/// Debug commands for live support sessions, talking to devices directly
+#[cfg(feature = "repl")]
+async fn repl_command(command: &str, args: Vec<&str>) -> Result<String, String> {
+    use crate::TOKENS;
+
+    let devices = DEVICES.read().await;
+
+    if command == "devices" {
+        let list: Vec<String> = devices
+            .iter()
+            .map(|(id, device)| {
+                format!("{} ({})", id, device.kind().human_name())
+            })
+            .collect();
+
+        return Ok(list.join(", "));
+    }
+
+    let id = args.first().ok_or("missing device")?.to_string();
+    let device = devices.get(&id).ok_or("unknown device")?;
+
+    let result = match command {
+        // dump-state <device>
+        "dump-state" => {
+            let settings = settings::SETTINGS.read().await.devices.get(&id).cloned();
+            let cancelled = TOKENS.read().await.get(&id).map(|token| token.is_cancelled());
+
+            return Ok(format!(
+                "kind={:?} serial={} keys={} encoders={} cancelled={:?} settings={:?}",
+                device.kind(),
+                device.serial_number(),
+                device.key_count(),
+                device.encoder_count(),
+                cancelled,
+                settings,
+            ));
+        }
+        // send <device> <hex bytes>, first byte is the report id
+        "send" => {
+            let hex: String = args[1..].concat();
+
+            if hex.is_empty() || !hex.is_ascii() || !hex.len().is_multiple_of(2) {
+                return Err("expected even number of hex digits".to_string());
+            }
+
+            let mut payload = (0..hex.len())
+                .step_by(2)
+                .map(|i| u8::from_str_radix(&hex[i..i + 2], 16))
+                .collect::<Result<Vec<u8>, _>>()
+                .map_err(|e| format!("invalid hex: {}", e))?;
+
+            device.write_extended_data(&mut payload).await
+        }
+        // set-brightness <device> <percent>
+        "set-brightness" => {
+            let brightness = args
+                .get(1)
+                .ok_or("missing brightness")?
+                .parse::<u8>()
+                .map_err(|e| format!("invalid brightness: {}", e))?;
+
+            apply_brightness(&id, device.as_ref(), brightness).await
+        }
+        _ => return Err(format!("unknown command: {}", command)),
+    };
+
+    drop(devices);
+
+    match result {
+        Ok(_) => Ok(String::new()),
+        Err(err) => {
+            let message = err.to_string();
+            handle_error(&id, err).await;
+
+            Err(message)
+        }
+    }
+}
+
+/// Clears every key of the device through its image worker, placeholders included
+async fn apply_clear_all(device: String) -> Result<(), String> {
+    let event = SetImageEvent {
+        device,
+        controller: None,
+        position: None,
+        image: None,
+    };
+
+    if set_device_image(event).await {
+        Ok(())
+    } else {
+        Err("unknown device".to_string())
+    }
+}
+
+/// Registers the device with OpenDeck again in its stored presentation and lets OpenDeck resend images
+async fn present_again(device: String) -> Result<(), String> {
+    let kind = DEVICES
+        .read()
+        .await
+        .get(&device)
+        .map(|device| device.kind())
+        .ok_or("unknown device")?;
+
+    // OpenDeck only learns the new layout by registering the device again
+    openaction::device_plugin::unregister_device(device.clone())
+        .await
+        .map_err(|e| e.to_string())?;
+    register(&device, &kind).await.map_err(|e| e.to_string())?;
+
+    // Images of the old layout are stale, OpenDeck resends the new ones
+    cache::record_clear_all(&device).await;
+    apply_clear_all(device.clone()).await?;
+
+    openaction::device_plugin::rerender_images(device)
+        .await
+        .map_err(|e| e.to_string())
+}
+
+/// Stores a device-scoped settings payload and applies whatever it changed to the connected device
+///
+/// Settings are kept under the device id, which is derived from the serial number, so they
+/// follow the device across ports and reconnects
+pub async fn apply_device_settings(device: &str, value: serde_json::Value) -> Result<(), String> {
+    let payload: DevicePayload =
+        serde_json::from_value(value).map_err(|e| format!("invalid settings: {}", e))?;
+
+    log::info!("Applying device settings to {}: {:?}", device, payload);
+
+    let changes = settings::store_payload(device, payload).await;
+
+    if !DEVICES.read().await.contains_key(device) {
+        // Picked up on connect
+        return Ok(());
+    }
+
+    if changes.brightness {
+        refresh_device_brightness(device).await?;
+    }
+
+    // Registering again makes OpenDeck resend every image, which covers a new profile too
+    if changes.presentation {
+        present_again(device.to_string()).await
+    } else if changes.profile {
+        openaction::device_plugin::rerender_images(device.to_string())
+            .await
+            .map_err(|e| e.to_string())
+    } else {
+        Ok(())
+    }
+}
+
+/// Applies current target brightness to the device again, so a changed limit takes effect
+async fn refresh_device_brightness(id: &str) -> Result<(), String> {
+    let brightness = target_brightness(id).await;
+    let devices = DEVICES.read().await;
+    let device = devices.get(id).ok_or("unknown device")?;
+
+    if let Err(err) = apply_brightness(id, device.as_ref(), brightness).await {
+        let message = err.to_string();
+        drop(devices);
+        handle_error(&id.to_string(), err).await;
+
+        return Err(message);
+    }
+
+    Ok(())
+}
+
+async fn apply_image(device: String, position: u8, image: Option<String>) -> Result<(), String> {
+    let event = SetImageEvent {
+        device,
+        controller: None,
+        position: Some(position),
+        image,
+    };
+
+    if set_device_image(event).await {
+        Ok(())
+    } else {
+        Err("unknown device".to_string())
+    }
+}
@@ -0,0 +1,124 @@
+use std::{
+    collections::{HashMap, HashSet},
+    sync::LazyLock,
+};
+
+use mirajazz::state::DeviceStateUpdate;
+use tokio::sync::Mutex;
+
+use crate::{
+    DEVICES,
+    compose::{self, Layer},
+    settings,
+};
+
+/// Key or dial that OpenDeck was told is pressed
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+enum Pressed {
+    Key(u8),
+    Encoder(u8),
+}
+
+/// Input state of a device, as seen by the lock
+#[derive(Debug, Default)]
+struct LockState {
+    locked: bool,
+    /// Keys currently held down, for detecting the chord
+    held: HashSet<u8>,
+    /// Presses that were forwarded, their releases are forwarded even while locked
+    forwarded: HashSet<Pressed>,
+}
+
+/// Lock state per device, kept across reconnects
+static LOCKS: LazyLock<Mutex<HashMap<String, LockState>>> =
+    LazyLock::new(|| Mutex::new(HashMap::new()));
+
+/// Returns true if inputs of the device are not forwarded
+pub async fn is_locked(id: &str) -> bool {
+    LOCKS.lock().await.get(id).is_some_and(|state| state.locked)
+}
+
+/// Locks or unlocks the device and redraws its keys with or without the lock glyph
+pub async fn set_locked(id: &str, locked: bool) {
+    {
+        let mut locks = LOCKS.lock().await;
+        let state = locks.entry(id.to_string()).or_default();
+
+        if state.locked == locked {
+            return;
+        }
+
+        state.locked = locked;
+    }
+
+    log::info!("{} {}", if locked { "Locked" } else { "Unlocked" }, id);
+
+    let Some(key_count) = DEVICES
+        .read()
+        .await
+        .get(id)
+        .map(|device| device.key_count())
+    else {
+        return;
+    };
+
+    // Only the lock glyph changes, the key images underneath stay as they are
+    let positions: Vec<u8> = (0..key_count as u8).collect();
+    compose::invalidate(id, &positions, Layer::Overlay).await;
+}
+
+/// Looks at the update (in OpenDeck positions), returns true if it should be swallowed
+///
+/// Pressing all keys of the device's lock chord at once toggles the lock. Releases of presses
+/// OpenDeck already got are always let through, so no key stays stuck when locking.
+pub async fn filter_update(id: &str, update: &DeviceStateUpdate) -> bool {
+    let chord = settings::lock_chord_for(id).await;
+
+    let (swallow, toggled) = {
+        let mut locks = LOCKS.lock().await;
+        let state = locks.entry(id.to_string()).or_default();
+
+        let pressed = match *update {
+            DeviceStateUpdate::ButtonDown(key) => {
+                state.held.insert(key);
+                Some((Pressed::Key(key), true))
+            }
+            DeviceStateUpdate::ButtonUp(key) => {
+                state.held.remove(&key);
+                Some((Pressed::Key(key), false))
+            }
+            DeviceStateUpdate::EncoderDown(encoder) => Some((Pressed::Encoder(encoder), true)),
+            DeviceStateUpdate::EncoderUp(encoder) => Some((Pressed::Encoder(encoder), false)),
+            DeviceStateUpdate::EncoderTwist(..) => None,
+        };
+
+        let completes_chord = match update {
+            DeviceStateUpdate::ButtonDown(key) => {
+                chord.contains(key) && chord.iter().all(|key| state.held.contains(key))
+            }
+            _ => false,
+        };
+
+        let swallow = match pressed {
+            Some((pressed, true)) => {
+                let swallow = state.locked || completes_chord;
+
+                if !swallow {
+                    state.forwarded.insert(pressed);
+                }
+
+                swallow
+            }
+            Some((pressed, false)) => !state.forwarded.remove(&pressed),
+            None => state.locked,
+        };
+
+        (swallow, completes_chord.then_some(!state.locked))
+    };
+
+    if let Some(locked) = toggled {
+        set_locked(id, locked).await;
+    }
+
+    swallow
+}
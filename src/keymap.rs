@@ -0,0 +1,157 @@
+use mirajazz::{error::MirajazzError, types::DeviceInput};
+
+/// N1 key count (6x3 = 18: 15 buttons + 3 top LCDs)
+pub const N1_KEY_COUNT: usize = 18;
+
+/// Key code of every N1 key, indexed by OpenDeck key (grid position)
+///
+/// Input reports carry these codes as they are. Image uploads address `code - 1`, as mirajazz adds
+/// 1 to the key it writes to; both directions below are derived from this one table.
+///
+/// The top LCDs (OpenDeck 0-2) come first, followed by the main grid row by row.
+pub const N1_KEY_CODES: [u8; N1_KEY_COUNT] = [
+    16, 17, 18, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15,
+];
+
+// A code used twice would send presses of one key to another (say an LCD and a grid key), so the
+// build fails instead. Tables of other kinds get an assertion like this one.
+const _: () = assert!(
+    is_bijection(&N1_KEY_CODES),
+    "N1_KEY_CODES must use every code from 1 to the key count exactly once"
+);
+
+/// AKP153 family key count (3x6 = 18, every key has a display)
+pub const AKP153_KEY_COUNT: usize = 18;
+
+/// Key code of every key of the AKP153 family, indexed by OpenDeck key (grid position)
+///
+/// Same convention as [N1_KEY_CODES]. Derived from the AKP153R example of mirajazz 0.9, whose
+/// `opendeck_to_device` uploads OpenDeck key `k` to `AKP153_KEY_CODES[k] - 1` and whose
+/// `device_to_opendeck` is the inverse.
+pub const AKP153_KEY_CODES: [u8; AKP153_KEY_COUNT] = [
+    13, 10, 7, 4, 1, 16, 14, 11, 8, 5, 2, 17, 15, 12, 9, 6, 3, 18,
+];
+
+const _: () = assert!(
+    is_bijection(&AKP153_KEY_CODES),
+    "AKP153_KEY_CODES must use every code from 1 to the key count exactly once"
+);
+
+/// Returns true if every code from 1 to the table length appears exactly once
+pub const fn is_bijection(codes: &[u8]) -> bool {
+    let mut code = 1;
+
+    while code as usize <= codes.len() {
+        let (mut index, mut found) = (0, 0);
+
+        while index < codes.len() {
+            if codes[index] == code {
+                found += 1;
+            }
+
+            index += 1;
+        }
+
+        if found != 1 {
+            return false;
+        }
+
+        code += 1;
+    }
+
+    true
+}
+
+/// Returns OpenDeck key sending the input code, `None` if no key of the table sends it
+pub fn code_to_key(codes: &[u8], code: u8) -> Option<usize> {
+    codes.iter().position(|key_code| *key_code == code)
+}
+
+/// Returns key index images for the OpenDeck key are uploaded to
+pub fn key_to_upload(codes: &[u8], key: u8) -> u8 {
+    match codes.get(key as usize) {
+        Some(code) => code - 1,
+        // Not a key of the device, passed on for mirajazz to reject
+        None => key,
+    }
+}
+
+/// Returns OpenDeck key sending the N1 input code, codes of no key land past the keys
+///
+/// For the debug binaries, which index button states with it.
+pub fn device_to_opendeck_n1(code: usize) -> usize {
+    code_to_key(&N1_KEY_CODES, code as u8).unwrap_or(N1_KEY_COUNT)
+}
+
+/// Returns states of all N1 keys after a report of the key sending `input`
+///
+/// For the debug binaries: every report is taken as the only key held, and sync frames (input
+/// 0) as all keys released. The plugin keeps track of held keys in `inputs.rs` instead.
+pub fn read_button_press_n1(input: u8, state: u8) -> Result<DeviceInput, MirajazzError> {
+    let mut states = vec![false; N1_KEY_COUNT];
+
+    if let Some(state_of_key) = states.get_mut(device_to_opendeck_n1(input as usize)) {
+        *state_of_key = state != 0;
+    }
+
+    Ok(DeviceInput::ButtonStateChange(states))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bijection_needs_every_code_once() {
+        assert!(is_bijection(&[1, 2, 3]));
+        assert!(is_bijection(&[3, 1, 2]));
+        assert!(!is_bijection(&[1, 1, 3]));
+        assert!(!is_bijection(&[1, 2, 4]));
+        assert!(!is_bijection(&[0, 1, 2]));
+    }
+
+    #[test]
+    fn n1_keys_round_trip() {
+        for key in 0..N1_KEY_COUNT as u8 {
+            let code = N1_KEY_CODES[key as usize];
+            assert_eq!(key_to_upload(&N1_KEY_CODES, key), code - 1);
+            assert_eq!(code_to_key(&N1_KEY_CODES, code), Some(key as usize));
+        }
+    }
+
+    #[test]
+    fn n1_top_lcds_come_first() {
+        assert_eq!(key_to_upload(&N1_KEY_CODES, 0), 15);
+        assert_eq!(key_to_upload(&N1_KEY_CODES, 2), 17);
+        assert_eq!(key_to_upload(&N1_KEY_CODES, 3), 0);
+        assert_eq!(key_to_upload(&N1_KEY_CODES, 17), 14);
+    }
+
+    #[test]
+    fn n1_button_press_sets_its_key_alone() {
+        let Ok(DeviceInput::ButtonStateChange(states)) = read_button_press_n1(16, 1) else {
+            panic!("not a button state change");
+        };
+
+        assert_eq!(states.len(), N1_KEY_COUNT);
+        assert_eq!(states.iter().position(|pressed| *pressed), Some(0));
+
+        // Sync frames and codes of no key release everything
+        for input in [0, N1_KEY_COUNT as u8 + 1] {
+            assert!(matches!(
+                read_button_press_n1(input, 1),
+                Ok(DeviceInput::ButtonStateChange(states)) if !states.contains(&true)
+            ));
+        }
+    }
+
+    #[test]
+    fn codes_outside_the_table_map_to_no_key() {
+        assert_eq!(code_to_key(&N1_KEY_CODES, 0), None);
+        assert_eq!(code_to_key(&N1_KEY_CODES, N1_KEY_COUNT as u8 + 1), None);
+        assert_eq!(
+            key_to_upload(&N1_KEY_CODES, N1_KEY_COUNT as u8),
+            N1_KEY_COUNT as u8
+        );
+    }
+}
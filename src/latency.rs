@@ -0,0 +1,72 @@
+use std::{
+    hash::{BuildHasher, RandomState},
+    sync::LazyLock,
+    time::Duration,
+};
+
+/// Artificial delay for trying out how feedback feels on a slow link or a busy machine
+#[derive(Debug, Clone, Copy)]
+pub struct Injection {
+    base: Duration,
+    /// Up to this much is added on top of `base`, picked at random every time
+    jitter: Duration,
+}
+
+impl Injection {
+    /// Parses delay in `<ms>` or `<ms>:<jitter ms>` format
+    fn parse(value: &str) -> Option<Self> {
+        let (base, jitter) = value.split_once(':').unwrap_or((value, "0"));
+
+        Some(Self {
+            base: Duration::from_millis(base.trim().parse().ok()?),
+            jitter: Duration::from_millis(jitter.trim().parse().ok()?),
+        })
+    }
+
+    fn from_env(name: &str) -> Option<Self> {
+        let value = std::env::var(name).ok()?;
+
+        match Self::parse(&value) {
+            Some(injection) => {
+                log::warn!("{} is set, delaying by {:?}", name, injection);
+                Some(injection)
+            }
+            None => {
+                log::warn!("Invalid {} {}, not injecting latency", name, value);
+                None
+            }
+        }
+    }
+
+    async fn sleep(&self) {
+        let jitter = match self.jitter.as_millis() as u64 {
+            0 => 0,
+            // Doesn't have to be good randomness, just different every time
+            jitter => RandomState::new().hash_one(()) % (jitter + 1),
+        };
+
+        tokio::time::sleep(self.base + Duration::from_millis(jitter)).await;
+    }
+}
+
+/// Delay before every key image write, `OPENDECK_N1_UPLOAD_LATENCY` at startup
+static UPLOAD: LazyLock<Option<Injection>> =
+    LazyLock::new(|| Injection::from_env("OPENDECK_N1_UPLOAD_LATENCY"));
+
+/// Delay before every input is forwarded to OpenDeck, `OPENDECK_N1_INPUT_LATENCY` at startup
+static INPUT: LazyLock<Option<Injection>> =
+    LazyLock::new(|| Injection::from_env("OPENDECK_N1_INPUT_LATENCY"));
+
+/// Waits for the injected upload delay, returns right away unless configured
+pub async fn before_upload() {
+    if let Some(injection) = &*UPLOAD {
+        injection.sleep().await;
+    }
+}
+
+/// Waits for the injected input delay, returns right away unless configured
+pub async fn before_input() {
+    if let Some(injection) = &*INPUT {
+        injection.sleep().await;
+    }
+}
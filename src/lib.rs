@@ -0,0 +1,3 @@
+//! Parts of the plugin shared with the debug binaries in `src/bin`
+
+pub mod keymap;
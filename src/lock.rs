@@ -0,0 +1,89 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use futures_lite::StreamExt;
+use tokio_util::sync::CancellationToken;
+use zbus::{Connection, Proxy, zvariant::OwnedObjectPath};
+
+use crate::{
+    DEVICES,
+    device::{apply_brightness, handle_error, target_brightness},
+};
+
+static LOCKED: AtomicBool = AtomicBool::new(false);
+
+/// Returns true while the screen of the session is locked
+pub fn is_locked() -> bool {
+    LOCKED.load(Ordering::Acquire)
+}
+
+/// Follows session lock state from logind, blanking all devices while the screen is locked
+pub async fn lock_task(token: CancellationToken) {
+    tokio::select! {
+        result = watch_lock() => {
+            if let Err(e) = result {
+                log::error!("Unable to follow screen lock state: {}", e);
+            }
+        },
+        _ = token.cancelled() => {},
+    }
+
+    log::info!("Lock task finished");
+}
+
+async fn watch_lock() -> zbus::Result<()> {
+    let connection = Connection::system().await?;
+
+    let manager = Proxy::new(
+        &connection,
+        "org.freedesktop.login1",
+        "/org/freedesktop/login1",
+        "org.freedesktop.login1.Manager",
+    )
+    .await?;
+
+    // "auto" resolves to the session of the plugin process
+    let path: OwnedObjectPath = manager.call("GetSession", &("auto",)).await?;
+
+    log::info!("Following lock state of session {}", path.as_str());
+
+    let session = Proxy::new(
+        &connection,
+        "org.freedesktop.login1",
+        path,
+        "org.freedesktop.login1.Session",
+    )
+    .await?;
+
+    // Screen lockers report the lock through LockedHint, Lock/Unlock signals are only requests
+    let mut changes = session.receive_property_changed::<bool>("LockedHint").await;
+
+    set_locked(session.get_property("LockedHint").await?).await;
+
+    while let Some(change) = changes.next().await {
+        set_locked(change.get().await?).await;
+    }
+
+    Ok(())
+}
+
+async fn set_locked(locked: bool) {
+    if LOCKED.swap(locked, Ordering::AcqRel) == locked {
+        return;
+    }
+
+    log::info!("Screen {}", if locked { "locked" } else { "unlocked" });
+
+    let ids: Vec<String> = DEVICES.read().await.keys().cloned().collect();
+
+    for id in ids {
+        let brightness = target_brightness(&id).await;
+        let devices = DEVICES.read().await;
+
+        if let Some(device) = devices.get(&id)
+            && let Err(err) = apply_brightness(&id, device.as_ref(), brightness).await
+        {
+            drop(devices);
+            handle_error(&id, err).await;
+        }
+    }
+}
@@ -0,0 +1,130 @@
+use std::time::Duration;
+
+use mirajazz::state::DeviceStateUpdate;
+use tokio::time::Instant;
+
+use crate::{inputs::N1_DIAL, reader::InputUpdate};
+
+/// Where a dial press stands while long presses are detected
+#[derive(Debug, Default)]
+enum State {
+    #[default]
+    Idle,
+    /// Press is held back until it's released or held for the threshold
+    Held {
+        down: InputUpdate,
+        deadline: Instant,
+        threshold: Duration,
+        encoder: u8,
+    },
+    /// Threshold passed, the press went to the long press encoder and so does the release
+    Long { encoder: u8 },
+}
+
+/// Tells short dial presses from long ones, in the order updates are forwarded to OpenDeck
+///
+/// A press held for the threshold is sent on an encoder of its own instead of the dial, so both
+/// can have different actions. The dial press is held back meanwhile: a release before the
+/// threshold sends press and release of the dial, a twist sends the press right away and the
+/// press is an ordinary one (or the start of hold-and-rotate) from then on.
+#[derive(Debug, Default)]
+pub struct LongPress {
+    state: State,
+}
+
+impl LongPress {
+    /// Returns when the held dial press becomes a long one, `None` if no press is held back
+    pub fn deadline(&self) -> Option<Instant> {
+        match self.state {
+            State::Held { deadline, .. } => Some(deadline),
+            _ => None,
+        }
+    }
+
+    /// Takes the next update read from the device, returns the updates to forward now
+    ///
+    /// `threshold` is the hold time of a long press, `None` turns detection off for new presses.
+    /// `encoder` is the OpenDeck encoder long presses are sent on.
+    pub fn push(
+        &mut self,
+        update: InputUpdate,
+        threshold: Option<Duration>,
+        encoder: u8,
+    ) -> Vec<InputUpdate> {
+        match (&self.state, &update.update) {
+            (State::Idle, DeviceStateUpdate::EncoderDown(N1_DIAL)) => {
+                let Some(threshold) = threshold else {
+                    return vec![update];
+                };
+
+                self.state = State::Held {
+                    down: update,
+                    deadline: Instant::now() + threshold,
+                    threshold,
+                    encoder,
+                };
+
+                vec![]
+            }
+            (State::Held { .. }, DeviceStateUpdate::EncoderUp(N1_DIAL)) => {
+                let State::Held {
+                    down,
+                    threshold,
+                    encoder,
+                    ..
+                } = std::mem::take(&mut self.state)
+                else {
+                    unreachable!()
+                };
+
+                // Presses held back by hold-and-rotate come with their release, the hold time tells
+                if update.held.is_some_and(|held| held >= threshold) {
+                    log::info!("Dial was held for {:?}, sending a long press", update.held);
+
+                    return vec![
+                        DeviceStateUpdate::EncoderDown(encoder).into(),
+                        InputUpdate {
+                            update: DeviceStateUpdate::EncoderUp(encoder),
+                            held: update.held,
+                        },
+                    ];
+                }
+
+                vec![down, update]
+            }
+            (State::Held { .. }, DeviceStateUpdate::EncoderTwist(..)) => {
+                let State::Held { down, .. } = std::mem::take(&mut self.state) else {
+                    unreachable!()
+                };
+
+                log::debug!("Dial was twisted while held, not a long press");
+
+                vec![down, update]
+            }
+            (State::Long { encoder }, DeviceStateUpdate::EncoderUp(N1_DIAL)) => {
+                let update = InputUpdate {
+                    update: DeviceStateUpdate::EncoderUp(*encoder),
+                    held: update.held,
+                };
+
+                self.state = State::Idle;
+
+                vec![update]
+            }
+            _ => vec![update],
+        }
+    }
+
+    /// Turns the held back press into a long press, called once its [Self::deadline] passed
+    pub fn expire(&mut self) -> Vec<InputUpdate> {
+        let State::Held { encoder, .. } = self.state else {
+            return vec![];
+        };
+
+        log::info!("Dial is held past the long press threshold");
+
+        self.state = State::Long { encoder };
+
+        vec![DeviceStateUpdate::EncoderDown(encoder).into()]
+    }
+}
@@ -1,6 +1,9 @@
-use device::{handle_error, handle_set_image};
-use mirajazz::device::Device;
-use std::{collections::HashMap, sync::LazyLock};
+use device::{apply_brightness, handle_error, is_blanked, keepalive_task, set_device_image};
+use backend::DeckDevice;
+use std::{
+    collections::HashMap,
+    sync::{LazyLock, atomic::AtomicBool},
+};
 use tokio::sync::{Mutex, RwLock};
 use tokio_util::{sync::CancellationToken, task::TaskTracker};
 use watcher::watcher_task;
@@ -8,19 +11,63 @@ use watcher::watcher_task;
 #[cfg(not(target_os = "windows"))]
 use tokio::signal::unix::{SignalKind, signal};
 
+mod animation;
+mod assets;
+mod backend;
+mod cache;
+mod compose;
+mod delivery;
+mod descriptors;
 mod device;
+mod dnd;
+mod doctor;
+mod face;
 mod inputs;
+#[cfg(all(target_os = "linux", feature = "lock-blank"))]
+mod lock;
+mod longpress;
+#[cfg(unix)]
+mod ipc;
+mod keylock;
+mod latency;
 mod mappings;
+mod mirror;
+mod overlay;
+mod placeholder;
+#[cfg(unix)]
+mod protocol;
+mod reaction;
+mod reader;
+mod scale;
+mod schedule;
+#[cfg(all(target_os = "linux", feature = "session-guard"))]
+mod session;
+mod settings;
+mod stats;
+#[cfg(target_os = "linux")]
+mod systemd;
+mod test_pattern;
+mod text;
+mod theme;
+mod tone;
+mod twist;
+#[cfg(all(unix, feature = "video"))]
+mod video;
 mod watcher;
 
-pub static DEVICES: LazyLock<RwLock<HashMap<String, Device>>> =
+pub static DEVICES: LazyLock<RwLock<HashMap<String, Box<dyn DeckDevice>>>> =
     LazyLock::new(|| RwLock::new(HashMap::new()));
 pub static TOKENS: LazyLock<RwLock<HashMap<String, CancellationToken>>> =
     LazyLock::new(|| RwLock::new(HashMap::new()));
 pub static TRACKER: LazyLock<Mutex<TaskTracker>> = LazyLock::new(|| Mutex::new(TaskTracker::new()));
+/// Set once the plugin is exiting, tells device tasks apart from ones ended by a disconnect
+pub static SHUTTING_DOWN: AtomicBool = AtomicBool::new(false);
+/// Trackers of device tasks, one per device task run, pruned by the watcher once their tasks finish
+pub static DEVICE_TRACKERS: LazyLock<Mutex<Vec<(String, TaskTracker)>>> =
+    LazyLock::new(|| Mutex::new(Vec::new()));
 
 use openaction::global_events::{
-    GlobalEventHandler, SetBrightnessEvent, SetImageEvent,
+    DidReceiveGlobalSettingsEvent, GlobalEventHandler, SetBrightnessEvent, SetImageEvent,
 };
 use openaction::OpenActionResult;
 use openaction::async_trait;
@@ -30,6 +77,9 @@ struct GlobalEventHandlerImpl {}
 #[async_trait]
 impl GlobalEventHandler for GlobalEventHandlerImpl {
     async fn plugin_ready(&self) -> OpenActionResult<()> {
+        // Settings arrive asynchronously, watcher waits for them before touching devices
+        openaction::get_global_settings().await?;
+
         let tracker = TRACKER.lock().await.clone();
 
         let token = CancellationToken::new();
@@ -40,6 +90,50 @@ impl GlobalEventHandler for GlobalEventHandlerImpl {
             .await
             .insert("_watcher_task".to_string(), token);
 
+        let token = CancellationToken::new();
+        tracker.spawn(keepalive_task(token.clone()));
+
+        TOKENS
+            .write()
+            .await
+            .insert("_keepalive_task".to_string(), token);
+
+        #[cfg(all(target_os = "linux", feature = "lock-blank"))]
+        {
+            let token = CancellationToken::new();
+            tracker.spawn(lock::lock_task(token.clone()));
+
+            TOKENS.write().await.insert("_lock_task".to_string(), token);
+        }
+
+        #[cfg(all(target_os = "linux", feature = "session-guard"))]
+        {
+            let token = CancellationToken::new();
+            tracker.spawn(session::session_task(token.clone()));
+
+            TOKENS.write().await.insert("_session_task".to_string(), token);
+        }
+
+        #[cfg(unix)]
+        if ipc::enabled() {
+            let token = CancellationToken::new();
+            tracker.spawn(ipc::ipc_task(token.clone()));
+
+            TOKENS.write().await.insert("_ipc_task".to_string(), token);
+        }
+
+        #[cfg(target_os = "linux")]
+        {
+            if let Some(timeout) = systemd::watchdog_timeout() {
+                let token = CancellationToken::new();
+                tracker.spawn(systemd::watchdog_task(timeout, token.clone()));
+
+                TOKENS.write().await.insert("_watchdog_task".to_string(), token);
+            }
+
+            systemd::notify("READY=1");
+        }
+
         log::info!("Plugin initialized");
 
         Ok(())
@@ -51,23 +145,67 @@ impl GlobalEventHandler for GlobalEventHandlerImpl {
     ) -> OpenActionResult<()> {
         log::debug!("Asked to set image: {:#?}", event);
 
-        // Skip knob images
-        if event.controller.as_deref() == Some("Encoder") {
-            log::debug!("Looks like a knob, no need to set image");
+        let id = event.device.clone();
+
+        let Some(kind) = DEVICES.read().await.get(&id).map(|device| device.kind()) else {
+            log::error!("Received event for unknown device: {}", id);
+            return Ok(());
+        };
+
+        // Translate into the grid layout, used by everything past this point
+        let presentation = settings::presentation_for(&id, &kind).await;
+
+        let position = match event.position {
+            Some(position) => {
+                match presentation.grid_key(&kind, event.controller.as_deref(), position) {
+                    Some(position) => Some(position),
+                    None => {
+                        log::debug!("Knob or face button without a screen, no need to set image");
+                        return Ok(());
+                    }
+                }
+            }
+            // Clearing or filling is only meaningful for the whole device
+            None if event.controller.as_deref() == Some("Encoder") => return Ok(()),
+            None => None,
+        };
+
+        // Mirrored segments show keys of another device instead
+        if let Some(position) = position
+            && mirror::covers(&id, position).await
+        {
+            log::debug!("Key {} of {} is mirrored, ignoring its image", position, id);
             return Ok(());
         }
 
-        let id = event.device.clone();
+        let event = SetImageEvent {
+            controller: None,
+            position,
+            ..event
+        };
+
+        match (event.position, &event.image) {
+            (Some(position), _) => cache::record(&id, position, event.image.clone()).await,
+            // Image without a position goes to every key
+            (None, Some(_)) => {
+                for position in 0..kind.key_count() as u8 {
+                    cache::record(&id, position, event.image.clone()).await;
+                }
+            }
+            (None, None) => cache::record_clear_all(&id).await,
+        }
 
-        if let Some(device) = DEVICES.read().await.get(&event.device) {
-            handle_set_image(device, event)
-                .await
-                .map_err(async |err| handle_error(&id, err).await)
-                .ok();
-        } else {
-            log::error!("Received event for unknown device: {}", event.device);
+        if !set_device_image(event).await {
+            log::error!("Received event for unknown device: {}", id);
         }
 
+        // Clearing or filling the device wrote over its mirrored segments too
+        if position.is_none() {
+            mirror::refresh(&id).await;
+        }
+
+        mirror::refresh_targets(&id).await;
+
         Ok(())
     }
 
@@ -79,21 +217,70 @@ impl GlobalEventHandler for GlobalEventHandlerImpl {
 
         let id = event.device.clone();
 
-        if let Some(device) = DEVICES.read().await.get(&event.device) {
-            device
-                .set_brightness(event.brightness)
-                .await
-                .map_err(async |err| handle_error(&id, err).await)
-                .ok();
-        } else {
-            log::error!("Received event for unknown device: {}", event.device);
+        if !DEVICES.read().await.contains_key(&id) {
+            log::error!("Received event for unknown device: {}", id);
+            return Ok(());
+        }
+
+        // Grouped devices follow each other, OpenDeck only addresses the one it was changed for
+        let mut targets = vec![];
+
+        for member in settings::group_members(&id).await {
+            // Device already shows the persisted brightness since connect, avoid rewriting it
+            if settings::brightness_for(&member).await == event.brightness {
+                log::debug!("Brightness for {} is already {}, skipping", member, event.brightness);
+                continue;
+            }
+
+            // Stays blank while "do not disturb" or screen lock is active, stored brightness is restored then
+            if is_blanked(&member).await || !DEVICES.read().await.contains_key(&member) {
+                settings::store_brightness(&member, event.brightness).await;
+            } else {
+                targets.push(member);
+            }
+        }
+
+        // Whole group changes under one lock, so nothing else gets written in between
+        let mut written = vec![];
+        let mut failed = vec![];
+        let devices = DEVICES.read().await;
+
+        for member in targets {
+            if let Some(device) = devices.get(&member) {
+                match apply_brightness(&member, device.as_ref(), event.brightness).await {
+                    Ok(()) => written.push(member),
+                    Err(err) => failed.push((member, err)),
+                }
+            }
+        }
+
+        drop(devices);
+
+        // Persisted only once the device took it, so a failed write is retried on the next event
+        for member in written {
+            settings::store_brightness(&member, event.brightness).await;
+        }
+
+        for (member, err) in failed {
+            handle_error(&member, err).await;
         }
 
         Ok(())
     }
+
+    async fn did_receive_global_settings(
+        &self,
+        event: DidReceiveGlobalSettingsEvent,
+    ) -> OpenActionResult<()> {
+        settings::load(event.payload.settings).await;
+
+        Ok(())
+    }
 }
 
 async fn shutdown() {
+    SHUTTING_DOWN.store(true, std::sync::atomic::Ordering::Release);
+
     let tokens = TOKENS.write().await;
 
     for (_, token) in tokens.iter() {
@@ -119,6 +306,26 @@ async fn sigterm() -> Result<(), Box<dyn std::error::Error>> {
 
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let args: Vec<String> = std::env::args().collect();
+
+    // First-line triage for packagers and users, runs without OpenDeck
+    if args.get(1).is_some_and(|arg| arg == "doctor") {
+        let passed = doctor::run(&args[2..]).await;
+        std::process::exit(if passed { 0 } else { 1 });
+    }
+
+    // Key mapping check, shows OpenDeck indices on the keys and prints input codes
+    if args.get(1).is_some_and(|arg| arg == "test-pattern") {
+        let ok = test_pattern::run().await;
+        std::process::exit(if ok { 0 } else { 1 });
+    }
+
+    // Supported hardware for udev rule generators, the frontend and docs
+    if args.get(1).is_some_and(|arg| arg == "descriptors") {
+        descriptors::run();
+        return Ok(());
+    }
+
     simplelog::TermLogger::init(
         simplelog::LevelFilter::Info,
         simplelog::Config::default(),
@@ -132,7 +339,7 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     openaction::global_events::set_global_event_handler(&HANDLER);
 
     tokio::select! {
-        result = openaction::run(std::env::args().collect()) => {
+        result = openaction::run(args) => {
             if let Err(e) = result {
                 log::error!("OpenAction error: {}", e);
             }
@@ -142,6 +349,9 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
 
     log::info!("Shutting down");
 
+    #[cfg(target_os = "linux")]
+    systemd::notify("STOPPING=1");
+
     shutdown().await;
 
     let tracker = TRACKER.lock().await.clone();
@@ -151,6 +361,20 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     tracker.close();
     tracker.wait().await;
 
+    let device_trackers: Vec<TaskTracker> = DEVICE_TRACKERS
+        .lock()
+        .await
+        .drain(..)
+        .map(|(_, tracker)| tracker)
+        .collect();
+
+    for tracker in device_trackers {
+        tracker.close();
+        tracker.wait().await;
+    }
+
+    stats::log_summary().await;
+
     log::info!("Tasks are finished, exiting now");
 
     Ok(())
@@ -1,55 +1,276 @@
 use mirajazz::{
     device::DeviceQuery,
+    state::DeviceStateUpdate,
     types::{HidDeviceInfo, ImageFormat, ImageMirroring, ImageMode, ImageRotation},
 };
+use opendeck_ajazz_n1::keymap::{AKP153_KEY_CODES, AKP153_KEY_COUNT};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    inputs::{InputProcessor, InputScheme},
+    tone::Tone,
+};
 
 // Must be unique between all the plugins, 2 characters long and match `DeviceNamespace` field in `manifest.json`
 pub const DEVICE_NAMESPACE: &str = "N1";
 
+// OpenDeck misroutes device events if the manifest and the code disagree, so the build fails instead
+const _: () = {
+    assert!(DEVICE_NAMESPACE.len() == 2, "DEVICE_NAMESPACE must be 2 characters long");
+
+    let manifest = include_str!("../manifest.json");
+    assert!(
+        json_field_is(manifest, "DeviceNamespace", DEVICE_NAMESPACE),
+        "DeviceNamespace in manifest.json doesn't match DEVICE_NAMESPACE"
+    );
+
+    // Packaging renames the binary per platform, OpenDeck starts whatever these point to
+    assert!(
+        json_field_is(manifest, "CodePathLin", concat!(env!("CARGO_PKG_NAME"), "-linux"))
+            && json_field_is(manifest, "CodePathWin", concat!(env!("CARGO_PKG_NAME"), "-win.exe"))
+            && json_field_is(manifest, "CodePathMac", concat!(env!("CARGO_PKG_NAME"), "-macos")),
+        "CodePath fields in manifest.json don't match the package name"
+    );
+};
+
+/// Returns true if the JSON has `"<key>": "<value>"`, good enough for the flat manifest
+const fn json_field_is(json: &str, key: &str, value: &str) -> bool {
+    let (json, key, value) = (json.as_bytes(), key.as_bytes(), value.as_bytes());
+
+    let mut start = 0;
+
+    while start + key.len() + 2 <= json.len() {
+        if json[start] == b'"'
+            && bytes_at(json, start + 1, key)
+            && json[start + 1 + key.len()] == b'"'
+        {
+            let mut index = skip_whitespace(json, start + key.len() + 2);
+
+            if index >= json.len() || json[index] != b':' {
+                return false;
+            }
+
+            index = skip_whitespace(json, index + 1);
+
+            return index + value.len() + 2 <= json.len()
+                && json[index] == b'"'
+                && bytes_at(json, index + 1, value)
+                && json[index + 1 + value.len()] == b'"';
+        }
+
+        start += 1;
+    }
+
+    false
+}
+
+const fn bytes_at(haystack: &[u8], start: usize, needle: &[u8]) -> bool {
+    if start + needle.len() > haystack.len() {
+        return false;
+    }
+
+    let mut index = 0;
+
+    while index < needle.len() {
+        if haystack[start + index] != needle[index] {
+            return false;
+        }
+
+        index += 1;
+    }
+
+    true
+}
+
+const fn skip_whitespace(bytes: &[u8], mut index: usize) -> usize {
+    while index < bytes.len() && bytes[index].is_ascii_whitespace() {
+        index += 1;
+    }
+
+    index
+}
+
 pub const AJAZZ_VID: u16 = 0x0300;
+pub const MIRABOX_VID: u16 = 0x5548;
 pub const N1_PID: u16 = 0x3007;
+pub const AKP153_PID: u16 = 0x6674;
+pub const AKP153E_PID: u16 = 0x1010;
+pub const AKP153R_PID: u16 = 0x1020;
+
+/// HID usage page and usage of the interface carrying images and inputs, the same on every kind
+pub const N1_USAGE_PAGE: u16 = 65440;
+pub const N1_USAGE_ID: u16 = 1;
 
-pub const N1_QUERY: DeviceQuery = DeviceQuery::new(65440, 1, AJAZZ_VID, N1_PID);
+pub const N1_QUERY: DeviceQuery = DeviceQuery::new(N1_USAGE_PAGE, N1_USAGE_ID, AJAZZ_VID, N1_PID);
+pub const AKP153_QUERY: DeviceQuery =
+    DeviceQuery::new(N1_USAGE_PAGE, N1_USAGE_ID, MIRABOX_VID, AKP153_PID);
+pub const AKP153E_QUERY: DeviceQuery =
+    DeviceQuery::new(N1_USAGE_PAGE, N1_USAGE_ID, AJAZZ_VID, AKP153E_PID);
+pub const AKP153R_QUERY: DeviceQuery =
+    DeviceQuery::new(N1_USAGE_PAGE, N1_USAGE_ID, AJAZZ_VID, AKP153R_PID);
 
-pub const QUERIES: [DeviceQuery; 1] = [N1_QUERY];
+pub const QUERIES: [DeviceQuery; 4] = [N1_QUERY, AKP153_QUERY, AKP153E_QUERY, AKP153R_QUERY];
 
 /// Returns correct image format for device kind and key
-pub fn get_image_format_for_key(_kind: &Kind, key: u8) -> ImageFormat {
-    // N1 uses different format: no rotation, no mirroring
-    // With 6×3 layout:
-    // Keys 0, 1, 2 are top LCD screens (64×64)
-    // Keys 3-17 are main buttons (96×96)
-    let size = if key <= 2 { (64, 64) } else { (96, 96) };
+pub fn get_image_format_for_key(kind: &Kind, key: u8) -> ImageFormat {
+    let display = kind.display_format();
+
     ImageFormat {
         mode: ImageMode::JPEG,
-        size,
-        rotation: ImageRotation::Rot0,
-        mirror: ImageMirroring::None,
+        size: display.size_of(key),
+        rotation: display.rotation,
+        mirror: display.mirror,
+    }
+}
+
+/// Image size of a range of keys
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct KeySize {
+    /// First and last OpenDeck position the size applies to
+    pub keys: (u8, u8),
+    /// Size of the image sent to the key
+    pub size: (usize, usize),
+    /// Margin on every side the panel doesn't show, content is fitted inside and the margin padded
+    pub padding: usize,
+}
+
+impl KeySize {
+    /// Returns size the key content is fitted to, the image size without the padding
+    pub fn content(&self) -> (usize, usize) {
+        (
+            self.size.0.saturating_sub(self.padding * 2).max(1),
+            self.size.1.saturating_sub(self.padding * 2).max(1),
+        )
+    }
+}
+
+/// How images have to be turned and sized to appear upright on the keys of a model
+#[derive(Debug, Clone, Copy)]
+pub struct DisplayFormat {
+    pub rotation: ImageRotation,
+    pub mirror: ImageMirroring,
+    /// Image sizes by key range, the first range containing a key wins
+    pub sizes: &'static [KeySize],
+}
+
+impl DisplayFormat {
+    /// Returns image size of the key, in OpenDeck position
+    ///
+    /// Keys missing from the table get the size of the last range
+    pub fn key_size(&self, key: u8) -> &'static KeySize {
+        self.sizes
+            .iter()
+            .find(|size| (size.keys.0..=size.keys.1).contains(&key))
+            .or(self.sizes.last())
+            .expect("display format has no key sizes")
+    }
+
+    pub fn size_of(&self, key: u8) -> (usize, usize) {
+        self.key_size(key).size
     }
 }
 
+/// Keys 0-2 are the top LCD screens, 3-17 the main buttons
+///
+/// Sizes are what the firmware accepts, `discover_key_sizes` checks them on a unit. Images that
+/// look cropped on the panel call for padding, not for a size the firmware doesn't expect.
+const N1_KEY_SIZES: [KeySize; 2] = [
+    KeySize {
+        keys: (0, 2),
+        size: (64, 64),
+        padding: 0,
+    },
+    KeySize {
+        keys: (3, 17),
+        size: (96, 96),
+        padding: 0,
+    },
+];
+
+/// Every key of the AKP153 family, the size the AKP153R example of mirajazz 0.9 sends
+const AKP153_KEY_SIZES: [KeySize; 1] = [KeySize {
+    keys: (0, 17),
+    size: (85, 85),
+    padding: 0,
+}];
+
+/// Top LCDs of the N1 as segments of one strip: OpenDeck position and left edge in the strip image
+pub const N1_LCD_STRIP: [(u8, u32); 3] = [(0, 0), (1, 64), (2, 128)];
+
+/// Supported device models
+///
+/// The AKP153 family (AKP153, AKP153E and AKP153R) are the same 3×6 grid under different ids,
+/// protocol version 1 per mirajazz, with image format and key order of its AKP153R example.
+/// Rebrands like the Soomfon and Mars Gaming units get a kind of their own once their panel
+/// and key order are known, `test_detect` finds them meanwhile.
 #[derive(Debug, Clone)]
 pub enum Kind {
     N1,
+    Akp153,
+    Akp153E,
+    Akp153R,
 }
 
 impl Kind {
+    /// Every supported kind
+    pub const ALL: [Kind; 4] = [Kind::N1, Kind::Akp153, Kind::Akp153E, Kind::Akp153R];
+
+    /// Returns (VID, PID, HID usage page, HID usage) the kind is discovered by
+    pub fn hid_ids(&self) -> (u16, u16, u16, u16) {
+        match self {
+            Kind::N1 => (AJAZZ_VID, N1_PID, N1_USAGE_PAGE, N1_USAGE_ID),
+            Kind::Akp153 => (MIRABOX_VID, AKP153_PID, N1_USAGE_PAGE, N1_USAGE_ID),
+            Kind::Akp153E => (AJAZZ_VID, AKP153E_PID, N1_USAGE_PAGE, N1_USAGE_ID),
+            Kind::Akp153R => (AJAZZ_VID, AKP153R_PID, N1_USAGE_PAGE, N1_USAGE_ID),
+        }
+    }
+
     /// Matches devices VID+PID pairs to correct kinds
     pub fn from_vid_pid(vid: u16, pid: u16) -> Option<Self> {
-        if vid == AJAZZ_VID && pid == N1_PID {
-            Some(Kind::N1)
-        } else {
-            None
+        Self::ALL.into_iter().find(|kind| {
+            let (kind_vid, kind_pid, _, _) = kind.hid_ids();
+            (kind_vid, kind_pid) == (vid, pid)
+        })
+    }
+
+    /// Returns how devices of this kind report inputs and number their keys
+    pub fn input_scheme(&self) -> InputScheme {
+        match self {
+            Kind::N1 => InputScheme::N1,
+            Kind::Akp153 | Kind::Akp153E | Kind::Akp153R => InputScheme::Grid {
+                key_codes: &AKP153_KEY_CODES,
+            },
         }
     }
 
+    /// Returns input processor of a device of this kind, with state of its own
+    pub fn input_processor(&self) -> InputProcessor {
+        InputProcessor::new(self.input_scheme())
+    }
+
     /// Returns protocol version for device
     pub fn protocol_version(&self) -> usize {
-        3 // N1 uses protocol v3
+        match self {
+            Kind::N1 => 3, // N1 uses protocol v3
+            Kind::Akp153 | Kind::Akp153E | Kind::Akp153R => 1,
+        }
+    }
+
+    /// Returns mode set on connect, `None` for kinds that are controlled without one
+    pub fn software_mode(&self) -> Option<u8> {
+        match self {
+            // N1 requires software mode to be set for control
+            Kind::N1 => Some(3),
+            Kind::Akp153 | Kind::Akp153E | Kind::Akp153R => None,
+        }
     }
 
     /// Returns (rows, cols) layout for this device type
     pub fn layout(&self) -> (usize, usize) {
+        if let Kind::Akp153 | Kind::Akp153E | Kind::Akp153R = self {
+            return (3, 6);
+        }
+
         // N1: 6 rows × 3 cols = 18 keys
         // Arranged to match physical layout:
         // Row 0: [LCD_16] [LCD_17] [LCD_18]  <- 3 top LCDs (inputs 16, 17, 18)
@@ -58,35 +279,405 @@ impl Kind {
         // Row 3: [KEY_7]  [KEY_8]  [KEY_9]   <- Main row 2 (inputs 7, 8, 9)
         // Row 4: [KEY_10] [KEY_11] [KEY_12]  <- Main row 3 (inputs 10, 11, 12)
         // Row 5: [KEY_13] [KEY_14] [KEY_15]  <- Main row 4 (inputs 13, 14, 15)
-        // Note: The 2 top normal buttons (inputs 30, 31) are NOT shown in GUI
-        // (They work but have no display, so we hide them to avoid confusion)
+        // Note: The 2 top normal buttons (inputs 30, 31) have no display and get no key in the
+        // grid, they press encoders of their own, see [crate::face]
         (6, 3)
     }
 
     /// Returns number of display keys for this device
     pub fn key_count(&self) -> usize {
+        if let Kind::Akp153 | Kind::Akp153E | Kind::Akp153R = self {
+            return AKP153_KEY_COUNT;
+        }
+
         // N1 has 18 display keys total (15 main + 3 top LCDs)
         // Note: 2 normal buttons (inputs 30, 31) are NOT counted as they have no display
         18
     }
 
+    /// Returns number of buttons without a display, see [crate::face]
+    ///
+    /// Inputs report them right after the display keys, at `key_count()` and up
+    pub fn face_button_count(&self) -> usize {
+        match self {
+            // Inputs 30 and 31, above the dial
+            Kind::N1 => 2,
+            Kind::Akp153 | Kind::Akp153E | Kind::Akp153R => 0,
+        }
+    }
+
     /// Returns number of encoders (dials/knobs) for this device
     /// N1 has 1 encoder (the dial)
     pub fn encoder_count(&self) -> usize {
-        1
+        match self {
+            Kind::N1 => 1,
+            Kind::Akp153 | Kind::Akp153E | Kind::Akp153R => 0,
+        }
+    }
+
+    /// Returns OpenDeck positions of the LCD segments forming one strip and their left edges
+    ///
+    /// Empty for kinds without a strip, they have no [Presentation::Strip]
+    pub fn lcd_strip(&self) -> &'static [(u8, u32)] {
+        match self {
+            Kind::N1 => &N1_LCD_STRIP,
+            Kind::Akp153 | Kind::Akp153E | Kind::Akp153R => &[],
+        }
+    }
+
+    /// Returns orientation and key sizes of the displays
+    ///
+    /// Each model has its own entry so a rebrand with a differently mounted panel can be
+    /// corrected without touching the others
+    pub fn display_format(&self) -> DisplayFormat {
+        match self {
+            // LCDs and buttons are both mounted upright
+            Kind::N1 => DisplayFormat {
+                rotation: ImageRotation::Rot0,
+                mirror: ImageMirroring::None,
+                sizes: &N1_KEY_SIZES,
+            },
+            Kind::Akp153 | Kind::Akp153E | Kind::Akp153R => DisplayFormat {
+                rotation: ImageRotation::Rot90,
+                mirror: ImageMirroring::Both,
+                sizes: &AKP153_KEY_SIZES,
+            },
+        }
+    }
+
+    /// Returns key (grid position) showing images OpenDeck sends for a physical dial, `None` if it has no screen
+    ///
+    /// The key keeps receiving its own images as well, whichever came last is shown
+    pub fn dial_screen(&self, encoder: u8) -> Option<u8> {
+        match (self, encoder) {
+            // Dial feedback goes to the rightmost top LCD
+            (Kind::N1, 0) => Some(N1_LCD_STRIP[2].0),
+            (Kind::N1, _) => None,
+            (Kind::Akp153 | Kind::Akp153E | Kind::Akp153R, _) => None,
+        }
+    }
+
+    /// Returns tone curve for devices without their own, tuned to the panels of the kind
+    pub fn default_tone(&self) -> Tone {
+        match self {
+            // Dark icons turn muddy on the N1 LCDs, lift midtones a little
+            Kind::N1 => Tone {
+                gamma: 0.9,
+                contrast: 1.05,
+                lift: 0.0,
+            },
+            // Nobody tuned these panels yet
+            Kind::Akp153 | Kind::Akp153E | Kind::Akp153R => Tone::IDENTITY,
+        }
+    }
+
+    /// Returns workarounds the firmware of the kind needs, see [Quirks]
+    pub fn quirks(&self) -> Quirks {
+        match self {
+            // Genuine N1 firmware has none so far, clones get theirs per device
+            Kind::N1 => Quirks::default(),
+            Kind::Akp153 | Kind::Akp153E | Kind::Akp153R => Quirks::default(),
+        }
+    }
+
+    /// Returns channel order the device expects in uploaded images
+    pub fn color_order(&self) -> ColorOrder {
+        ColorOrder::Rgb
     }
 
     /// Returns human-readable device name
     pub fn human_name(&self) -> String {
-        "Ajazz N1".to_string()
+        match self {
+            Kind::N1 => "Ajazz N1",
+            Kind::Akp153 => "Ajazz AKP153",
+            Kind::Akp153E => "Ajazz AKP153E",
+            Kind::Akp153R => "Ajazz AKP153R",
+        }
+        .to_string()
+    }
+}
+
+/// Workarounds for firmware bugs, known per kind and added per device in settings
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Quirks {
+    /// Highest brightness (percent) the device is set to, for panels that burn out or firmwares
+    /// that drop the connection when driven harder
+    pub max_brightness: Option<u8>,
+    /// Milliseconds to leave the device alone after changing brightness
+    pub brightness_delay_ms: Option<u64>,
+}
+
+impl Quirks {
+    /// Takes every quirk that isn't set from `fallback`
+    pub fn or(self, fallback: Quirks) -> Quirks {
+        Quirks {
+            max_brightness: self.max_brightness.or(fallback.max_brightness),
+            brightness_delay_ms: self.brightness_delay_ms.or(fallback.brightness_delay_ms),
+        }
+    }
+}
+
+/// Channel order of images as the device renders them
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ColorOrder {
+    #[default]
+    Rgb,
+    /// Red and blue are swapped, icons look blue-tinted unless corrected
+    Bgr,
+}
+
+impl ColorOrder {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "rgb" => Some(Self::Rgb),
+            "bgr" => Some(Self::Bgr),
+            _ => None,
+        }
+    }
+}
+
+/// Key mapping profile, selectable per device
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum MappingProfile {
+    /// Keys are laid out as on the physical device
+    #[default]
+    Standard,
+    /// Columns are reversed, so the device can be used with the dial on the other side
+    Mirrored,
+}
+
+impl MappingProfile {
+    /// Parses profile name as used in settings
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "standard" => Some(Self::Standard),
+            "mirrored" => Some(Self::Mirrored),
+            _ => None,
+        }
+    }
+
+    /// Translates OpenDeck key position to the physical one
+    ///
+    /// Every profile is its own inverse, so the same call translates physical position back.
+    /// Positions past the grid (face buttons) are passed through, as they have no column
+    pub fn apply(&self, kind: &Kind, key: u8) -> u8 {
+        let (rows, cols) = kind.layout();
+
+        if key as usize >= rows * cols {
+            return key;
+        }
+
+        match self {
+            Self::Standard => key,
+            Self::Mirrored => {
+                let cols = cols as u8;
+
+                let (row, col) = (key / cols, key % cols);
+
+                row * cols + (cols - 1 - col)
+            }
+        }
+    }
+}
+
+/// How the device is presented to OpenDeck, selectable per device
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Presentation {
+    /// Top LCDs are the first row of keys, see [Kind::layout]
+    #[default]
+    Grid,
+    /// Top LCDs become screens of encoders 1 to 3, leaving 5 rows of keys
+    ///
+    /// The dial stays encoder 0 in both presentations, so its actions keep working
+    Strip,
+}
+
+impl Presentation {
+    /// Parses presentation name as used in settings
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "grid" => Some(Self::Grid),
+            "strip" => Some(Self::Strip),
+            _ => None,
+        }
+    }
+
+    /// Returns true if devices of the kind can be presented this way, only strips make a [Self::Strip]
+    pub fn supported_by(&self, kind: &Kind) -> bool {
+        match self {
+            Self::Grid => true,
+            Self::Strip => !kind.lcd_strip().is_empty(),
+        }
+    }
+
+    /// Returns (rows, cols, encoders) the device is registered with
+    ///
+    /// The grid only holds keys with a display. Face buttons get an encoder each after the ones
+    /// of the presentation instead, see [crate::face]
+    pub fn layout(&self, kind: &Kind) -> (usize, usize, usize) {
+        let (rows, cols) = kind.layout();
+        let (dials, faces) = (kind.encoder_count(), kind.face_button_count());
+
+        match self {
+            Self::Grid => (rows, cols, dials + faces),
+            Self::Strip => (rows - 1, cols, dials + cols + faces),
+        }
+    }
+
+    /// Returns OpenDeck encoder of the face button, after the dials (and LCDs) of the presentation
+    pub fn face_encoder(&self, kind: &Kind, face: u8) -> u8 {
+        let (_, cols) = kind.layout();
+        let dials = kind.encoder_count() as u8;
+
+        match self {
+            Self::Grid => dials + face,
+            Self::Strip => dials + cols as u8 + face,
+        }
     }
 
+    /// Translates OpenDeck key or encoder into a key of the grid layout, `None` if it has no screen
+    pub fn grid_key(&self, kind: &Kind, controller: Option<&str>, position: u8) -> Option<u8> {
+        let (_, cols) = kind.layout();
+        let (cols, dials) = (cols as u8, kind.encoder_count() as u8);
+        let keys = kind.key_count() as u8;
 
+        // Encoders of the face buttons come after every one with a screen
+        match (self, controller) {
+            (Self::Grid, Some("Encoder")) => kind.dial_screen(position),
+            (Self::Grid, _) => (position < keys).then_some(position),
+            (Self::Strip, Some("Encoder")) if position < dials => kind.dial_screen(position),
+            (Self::Strip, Some("Encoder")) => (position < dials + cols).then(|| position - dials),
+            (Self::Strip, _) => Some(position + cols).filter(|&key| key < keys),
+        }
+    }
+
+    /// Translates update of the grid layout into the one OpenDeck expects
+    ///
+    /// Twists of the held dial (the encoder right after the dials) go to the encoder after every
+    /// other one of the presentation. Encoder presses are OpenDeck encoders already, face buttons
+    /// press theirs ([Self::face_encoder]) and long presses go after the held dial.
+    pub fn opendeck_update(&self, kind: &Kind, update: DeviceStateUpdate) -> DeviceStateUpdate {
+        let (_, cols) = kind.layout();
+        let (cols, dials) = (cols as u8, kind.encoder_count() as u8);
+        let (_, _, encoders) = self.layout(kind);
+
+        match (self, update) {
+            (_, DeviceStateUpdate::EncoderTwist(encoder, delta)) if encoder == dials => {
+                DeviceStateUpdate::EncoderTwist(encoders as u8, delta)
+            }
+            (Self::Grid, update) => update,
+            (Self::Strip, DeviceStateUpdate::ButtonDown(key)) if key < cols => {
+                DeviceStateUpdate::EncoderDown(key + dials)
+            }
+            (Self::Strip, DeviceStateUpdate::ButtonUp(key)) if key < cols => {
+                DeviceStateUpdate::EncoderUp(key + dials)
+            }
+            (Self::Strip, DeviceStateUpdate::ButtonDown(key)) => {
+                DeviceStateUpdate::ButtonDown(key - cols)
+            }
+            (Self::Strip, DeviceStateUpdate::ButtonUp(key)) => {
+                DeviceStateUpdate::ButtonUp(key - cols)
+            }
+            (Self::Strip, update) => update,
+        }
+    }
+}
+
+/// How the plugin reaches the device, decides which backend gets used
+#[derive(Debug, Clone)]
+pub enum Transport {
+    Hid(HidDeviceInfo),
+    /// Virtual device without hardware, see [crate::backend::MockDevice]
+    Mock,
 }
 
 #[derive(Debug, Clone)]
 pub struct CandidateDevice {
     pub id: String,
-    pub dev: HidDeviceInfo,
+    pub transport: Transport,
     pub kind: Kind,
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn mirrored_profile_reverses_columns() {
+        let kind = Kind::N1;
+        let (rows, cols) = kind.layout();
+
+        for key in 0..(rows * cols) as u8 {
+            let mirrored = MappingProfile::Mirrored.apply(&kind, key);
+
+            assert_eq!(mirrored / cols as u8, key / cols as u8);
+            assert_eq!(mirrored % cols as u8, cols as u8 - 1 - key % cols as u8);
+            assert_eq!(MappingProfile::Mirrored.apply(&kind, mirrored), key);
+        }
+    }
+
+    #[test]
+    fn profiles_pass_through_keys_past_the_grid() {
+        let kind = Kind::N1;
+        let (rows, cols) = kind.layout();
+
+        for key in (rows * cols) as u8..=u8::MAX {
+            assert_eq!(MappingProfile::Standard.apply(&kind, key), key);
+            assert_eq!(MappingProfile::Mirrored.apply(&kind, key), key);
+        }
+    }
+
+    #[test]
+    fn kinds_are_found_by_their_own_ids() {
+        for kind in Kind::ALL {
+            let (vid, pid, _, _) = kind.hid_ids();
+            let found = Kind::from_vid_pid(vid, pid).expect("kind is found");
+
+            assert_eq!(found.hid_ids(), kind.hid_ids());
+        }
+
+        assert!(Kind::from_vid_pid(AJAZZ_VID, 0).is_none());
+    }
+
+    #[test]
+    fn only_kinds_with_a_strip_present_it() {
+        for kind in Kind::ALL {
+            assert!(Presentation::Grid.supported_by(&kind));
+            assert_eq!(
+                Presentation::Strip.supported_by(&kind),
+                !kind.lcd_strip().is_empty()
+            );
+        }
+
+        assert!(Presentation::Strip.supported_by(&Kind::N1));
+        assert!(!Presentation::Strip.supported_by(&Kind::Akp153E));
+    }
+
+    #[test]
+    fn face_buttons_are_registered_after_everything_with_a_screen() {
+        let kind = Kind::N1;
+        let faces = kind.face_button_count() as u8;
+
+        for presentation in [Presentation::Grid, Presentation::Strip] {
+            let (rows, cols, encoders) = presentation.layout(&kind);
+            let keys = (0..(rows * cols) as u8)
+                .filter(|&key| presentation.grid_key(&kind, None, key).is_some())
+                .count() as u8;
+
+            // The grid isn't resized for them, every key of it has a display
+            assert_eq!(rows * cols, keys as usize);
+
+            // Dials and LCD encoders come first, the face buttons take the last ones
+            for face in 0..faces {
+                let encoder = presentation.face_encoder(&kind, face);
+
+                assert_eq!(encoder as usize, encoders - faces as usize + face as usize);
+                assert_eq!(presentation.grid_key(&kind, Some("Encoder"), encoder), None);
+            }
+        }
+    }
+}
@@ -0,0 +1,123 @@
+use mirajazz::state::DeviceStateUpdate;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    DEVICES, cache,
+    device::set_device_images,
+    mappings::{Kind, N1_LCD_STRIP},
+    settings,
+};
+
+/// LCD segments of a device showing keys of another connected device
+///
+/// Segment images follow the source keys, and presses of a segment are forwarded as presses of
+/// the source key. OpenDeck's own images for the segments are ignored while mirrored.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Mirror {
+    /// Device the keys belong to
+    pub source: String,
+    /// Key of the source (OpenDeck position) shown on each segment, left to right
+    pub keys: Vec<u8>,
+}
+
+impl Mirror {
+    /// Parses `<source>:<key>,<key>,...`, at most one key per LCD segment
+    pub fn parse(value: &str) -> Option<Self> {
+        let (source, keys) = value.rsplit_once(':')?;
+
+        let keys = keys
+            .split(',')
+            .map(|key| key.parse().ok())
+            .collect::<Option<Vec<u8>>>()?;
+
+        if source.is_empty() || keys.is_empty() || keys.len() > N1_LCD_STRIP.len() {
+            return None;
+        }
+
+        Some(Self {
+            source: source.to_string(),
+            keys,
+        })
+    }
+
+    /// Returns OpenDeck positions of the mirrored segments together with their source keys
+    fn segments(&self) -> impl Iterator<Item = (u8, u8)> + '_ {
+        N1_LCD_STRIP
+            .iter()
+            .map(|(position, _)| *position)
+            .zip(self.keys.iter().copied())
+    }
+}
+
+/// Returns true if the key (OpenDeck position) of the device shows a key of another device
+pub async fn covers(id: &str, position: u8) -> bool {
+    settings::mirror_for(id)
+        .await
+        .is_some_and(|mirror| mirror.segments().any(|(segment, _)| segment == position))
+}
+
+/// Shows current images of the source keys on the mirrored segments of the device
+///
+/// Only segments whose image differs from the source key are written
+pub async fn refresh(id: &str) {
+    let Some(mirror) = settings::mirror_for(id).await else {
+        return;
+    };
+
+    for (segment, key) in mirror.segments() {
+        let image = cache::current(&mirror.source, key).await;
+
+        if cache::current(id, segment).await == image {
+            continue;
+        }
+
+        // Recorded for the segment like any other image, so redraws and reconnects show it too
+        cache::record(id, segment, image.clone()).await;
+        set_device_images(id, &[segment], image).await;
+    }
+}
+
+/// Refreshes every device mirroring keys of the source, after the source got new images
+pub async fn refresh_targets(source: &str) {
+    for target in settings::mirror_targets(source).await {
+        refresh(&target).await;
+    }
+}
+
+/// Where an update of a device goes
+pub enum Route {
+    /// Not a mirrored segment, the device's own key
+    Own(DeviceStateUpdate),
+    /// Press or release of the source key, in OpenDeck positions of the source
+    Source(String, Kind, DeviceStateUpdate),
+    /// Segment mirrors a device that isn't connected
+    Nowhere,
+}
+
+/// Turns press or release of a mirrored segment into one of the source key
+pub async fn route(id: &str, update: DeviceStateUpdate) -> Route {
+    let Some(mirror) = settings::mirror_for(id).await else {
+        return Route::Own(update);
+    };
+
+    let (key, source_update): (u8, fn(u8) -> DeviceStateUpdate) = match update {
+        DeviceStateUpdate::ButtonDown(key) => (key, DeviceStateUpdate::ButtonDown),
+        DeviceStateUpdate::ButtonUp(key) => (key, DeviceStateUpdate::ButtonUp),
+        _ => return Route::Own(update),
+    };
+
+    let Some((_, source_key)) = mirror.segments().find(|(segment, _)| *segment == key) else {
+        return Route::Own(update);
+    };
+
+    let kind = DEVICES
+        .read()
+        .await
+        .get(&mirror.source)
+        .map(|device| device.kind());
+
+    match kind {
+        Some(kind) => Route::Source(mirror.source, kind, source_update(source_key)),
+        None => Route::Nowhere,
+    }
+}
@@ -0,0 +1,155 @@
+use std::{collections::HashMap, sync::LazyLock};
+
+use image::{DynamicImage, Rgb, RgbImage, imageops};
+use tokio::sync::Mutex;
+
+/// Badge is drawn in the top right corner, taking this fraction of key width
+const BADGE_FRACTION: u32 = 3;
+
+const RED: Rgb<u8> = Rgb([220, 30, 30]);
+const WHITE: Rgb<u8> = Rgb([255, 255, 255]);
+
+/// Small overlay drawn on top of the key image
+#[derive(Debug, Clone)]
+pub enum Badge {
+    /// Red circle with a slash
+    Mute,
+    /// Red dot
+    Recording,
+    /// Custom badge image
+    Image(DynamicImage),
+}
+
+impl Badge {
+    /// Parses name of a built-in badge
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "mute" => Some(Self::Mute),
+            "recording" => Some(Self::Recording),
+            _ => None,
+        }
+    }
+
+    fn draw(&self, image: &mut RgbImage) {
+        let size = image.width() / BADGE_FRACTION;
+        let (left, top) = (image.width() - size, 0);
+
+        match self {
+            Self::Image(badge) => {
+                let badge = badge
+                    .resize_exact(size, size, imageops::FilterType::Triangle)
+                    .to_rgb8();
+
+                imageops::replace(image, &badge, left as i64, top as i64);
+            }
+            Self::Mute | Self::Recording => {
+                let radius = size as f32 / 2.0;
+                let (cx, cy) = (left as f32 + radius, top as f32 + radius);
+
+                for y in top..top + size {
+                    for x in left..image.width() {
+                        let (dx, dy) = (x as f32 + 0.5 - cx, y as f32 + 0.5 - cy);
+                        let distance = (dx * dx + dy * dy).sqrt();
+
+                        if distance > radius {
+                            continue;
+                        }
+
+                        let pixel = match self {
+                            // Ring with a diagonal slash
+                            Self::Mute if distance > radius * 0.75 => RED,
+                            Self::Mute if (dx - dy).abs() < radius * 0.25 => RED,
+                            Self::Mute => WHITE,
+                            _ => RED,
+                        };
+
+                        image.put_pixel(x, y, pixel);
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Badges per device, keyed by device id and OpenDeck position
+///
+/// Kept apart from the key images, so badges can be toggled without OpenDeck resending them
+static BADGES: LazyLock<Mutex<HashMap<(String, u8), Badge>>> =
+    LazyLock::new(|| Mutex::new(HashMap::new()));
+
+/// Sets or removes (with `None`) the badge of the key
+pub async fn set_badge(id: &str, position: u8, badge: Option<Badge>) {
+    let mut badges = BADGES.lock().await;
+
+    match badge {
+        Some(badge) => badges.insert((id.to_string(), position), badge),
+        None => badges.remove(&(id.to_string(), position)),
+    };
+}
+
+/// Returns badge of the key, if there is one
+pub async fn badge(id: &str, position: u8) -> Option<Badge> {
+    BADGES.lock().await.get(&(id.to_string(), position)).cloned()
+}
+
+/// Draws the badge on top of the image
+pub fn apply(badge: Option<&Badge>, image: DynamicImage) -> DynamicImage {
+    let Some(badge) = badge else {
+        return image;
+    };
+
+    let mut image = image.to_rgb8();
+    badge.draw(&mut image);
+
+    DynamicImage::ImageRgb8(image)
+}
+
+/// Draws a red frame around the key, shown when a press of the key didn't reach OpenDeck
+pub fn apply_error_border(image: DynamicImage) -> DynamicImage {
+    let mut image = image.to_rgb8();
+    let (width, height) = image.dimensions();
+    let thickness = (width / 16).max(2);
+
+    for (x, y, pixel) in image.enumerate_pixels_mut() {
+        if x < thickness || y < thickness || x >= width - thickness || y >= height - thickness {
+            *pixel = RED;
+        }
+    }
+
+    DynamicImage::ImageRgb8(image)
+}
+
+/// Turns the whole key white, the flash of a display latency measurement
+pub fn apply_flash(image: DynamicImage) -> DynamicImage {
+    let (width, height) = (image.width(), image.height());
+
+    DynamicImage::ImageRgb8(RgbImage::from_pixel(width, height, WHITE))
+}
+
+/// Draws a padlock in the top left corner, shown on every key while the device is locked
+pub fn apply_lock(image: DynamicImage) -> DynamicImage {
+    let mut image = image.to_rgb8();
+
+    let size = image.width() / BADGE_FRACTION;
+    let unit = size as f32 / 8.0;
+
+    for y in 0..size {
+        for x in 0..size {
+            let (fx, fy) = (x as f32 + 0.5, y as f32 + 0.5);
+
+            // Body in the lower half, shackle as a ring above it
+            let body = (unit..size as f32 - unit).contains(&fx)
+                && (unit * 4.0..size as f32 - unit).contains(&fy);
+
+            let (dx, dy) = (fx - size as f32 / 2.0, fy - unit * 4.0);
+            let distance = (dx * dx + dy * dy).sqrt();
+            let shackle = dy < 0.0 && distance < unit * 2.5 && distance > unit * 1.5;
+
+            if body || shackle {
+                image.put_pixel(x, y, WHITE);
+            }
+        }
+    }
+
+    DynamicImage::ImageRgb8(image)
+}
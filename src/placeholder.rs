@@ -0,0 +1,104 @@
+use std::{collections::HashMap, sync::LazyLock};
+
+use image::{DynamicImage, Rgb, RgbImage};
+use tokio::sync::Mutex;
+
+use crate::text;
+
+/// Size the splash is drawn at, upload fits it to every key format
+const SPLASH_SIZE: u32 = 96;
+
+/// Device id and OpenDeck position, `None` position holds the default for keys without their own
+type PlaceholderKey = (String, Option<u8>);
+
+/// Images shown on cleared keys instead of black
+static PLACEHOLDERS: LazyLock<Mutex<HashMap<PlaceholderKey, DynamicImage>>> =
+    LazyLock::new(|| Mutex::new(HashMap::new()));
+
+/// Titles drawn on cleared keys, by device id and OpenDeck position
+static TITLES: LazyLock<Mutex<HashMap<(String, u8), String>>> =
+    LazyLock::new(|| Mutex::new(HashMap::new()));
+
+/// Background of title images
+const TITLE_BACKGROUND: Rgb<u8> = Rgb([24, 24, 24]);
+
+/// Sets or removes (with `None`) the title of the key
+///
+/// OpenAction doesn't tell device plugins the titles of actions, so they have to be set from
+/// outside, e.g. by a script that knows the profile
+pub async fn set_title(id: &str, position: u8, title: Option<String>) {
+    let mut titles = TITLES.lock().await;
+
+    match title {
+        Some(title) => {
+            titles.insert((id.to_string(), position), title);
+        }
+        None => {
+            titles.remove(&(id.to_string(), position));
+        }
+    }
+}
+
+/// Sets or removes (with `None`) the placeholder of the key, or the device default
+pub async fn set_placeholder(id: &str, position: Option<u8>, image: Option<DynamicImage>) {
+    let mut placeholders = PLACEHOLDERS.lock().await;
+
+    match image {
+        Some(image) => {
+            placeholders.insert((id.to_string(), position), image);
+        }
+        None => {
+            placeholders.remove(&(id.to_string(), position));
+        }
+    }
+}
+
+/// Returns title of the key, if it has one
+///
+/// Titles are a layer of their own, drawn over placeholders, see [crate::compose]
+pub async fn title(id: &str, position: u8) -> Option<String> {
+    TITLES
+        .lock()
+        .await
+        .get(&(id.to_string(), position))
+        .cloned()
+}
+
+/// Returns image to show when the key gets cleared, if there is one
+pub async fn get(id: &str, position: u8) -> Option<DynamicImage> {
+    let placeholders = PLACEHOLDERS.lock().await;
+
+    placeholders
+        .get(&(id.to_string(), Some(position)))
+        .or_else(|| placeholders.get(&(id.to_string(), None)))
+        .cloned()
+}
+
+/// Draws the title in white on a dark background, wrapped to fit the key
+pub fn render_title(title: &str, size: (usize, usize)) -> DynamicImage {
+    let mut image = RgbImage::from_pixel(size.0 as u32, size.1 as u32, TITLE_BACKGROUND);
+
+    text::draw_wrapped(&mut image, title, Rgb([255, 255, 255]));
+
+    DynamicImage::ImageRgb8(image)
+}
+
+/// Returns image shown on keys right after connecting, until OpenDeck sends their content
+///
+/// A small green dot on black, so the device visibly works without looking like a real icon
+pub fn splash() -> DynamicImage {
+    let center = SPLASH_SIZE as f32 / 2.0;
+    let radius = SPLASH_SIZE as f32 / 10.0;
+
+    let image = RgbImage::from_fn(SPLASH_SIZE, SPLASH_SIZE, |x, y| {
+        let (dx, dy) = (x as f32 + 0.5 - center, y as f32 + 0.5 - center);
+
+        if dx * dx + dy * dy <= radius * radius {
+            Rgb([40, 200, 90])
+        } else {
+            Rgb([0, 0, 0])
+        }
+    });
+
+    DynamicImage::ImageRgb8(image)
+}
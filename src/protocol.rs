@@ -0,0 +1,108 @@
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+/// Newest control protocol version the plugin speaks
+///
+/// 1 is the plain text protocol (one command per line, `ok ...` or `error: ...`), 2 adds JSON
+/// requests and the `hello` exchange. Bumped whenever an existing command changes its meaning;
+/// new commands and features are only added to the [Hello] lists.
+pub const VERSION: u32 = 2;
+
+/// Oldest control protocol version the plugin still answers
+pub const MIN_VERSION: u32 = 1;
+
+/// Cargo features the plugin was built with, as listed by `hello`
+const FEATURES: &[(&str, bool)] = &[
+    ("repl", cfg!(feature = "repl")),
+    ("video", cfg!(feature = "video")),
+    ("lock-blank", cfg!(feature = "lock-blank")),
+    ("session-guard", cfg!(feature = "session-guard")),
+];
+
+/// Control request sent as one line of JSON, e.g.
+/// `{"id":1,"command":"set-image","args":["N1-123","3","clear"]}`
+#[derive(Debug, Deserialize)]
+pub struct Request {
+    /// Echoed in the response so clients can match them up, any JSON value
+    #[serde(default)]
+    pub id: Value,
+    pub command: String,
+    /// Arguments of the text command, in order
+    #[serde(default)]
+    pub args: Vec<String>,
+}
+
+/// Answer to a [Request], `result` on success and `error` otherwise
+#[derive(Debug, Serialize)]
+pub struct Response {
+    pub id: Value,
+    pub ok: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub result: Option<Value>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+impl Response {
+    pub fn new(id: Value, result: Result<Value, String>) -> Self {
+        match result {
+            Ok(result) => Self {
+                id,
+                ok: true,
+                result: Some(result),
+                error: None,
+            },
+            Err(error) => Self {
+                id,
+                ok: false,
+                result: None,
+                error: Some(error),
+            },
+        }
+    }
+}
+
+/// Answer to `hello`, what this plugin build understands
+#[derive(Debug, Serialize)]
+pub struct Hello {
+    /// Version both sides speak, the lower of the client's and [VERSION]
+    pub protocol: u32,
+    pub min_protocol: u32,
+    pub max_protocol: u32,
+    pub plugin: &'static str,
+    pub commands: Vec<&'static str>,
+    pub features: Vec<&'static str>,
+}
+
+/// Negotiates the protocol version with a client speaking up to `version` (1 when it doesn't say)
+///
+/// Clients should check `commands` and `features` rather than the plugin version, those lists
+/// only ever grow.
+pub fn hello(version: Option<&str>, commands: Vec<&'static str>) -> Result<Hello, String> {
+    let version = match version {
+        Some(value) => value
+            .parse::<u32>()
+            .map_err(|_| format!("invalid version: {}", value))?,
+        None => MIN_VERSION,
+    };
+
+    if version < MIN_VERSION {
+        return Err(format!(
+            "unsupported protocol version {}, plugin speaks {} to {}",
+            version, MIN_VERSION, VERSION
+        ));
+    }
+
+    Ok(Hello {
+        protocol: version.min(VERSION),
+        min_protocol: MIN_VERSION,
+        max_protocol: VERSION,
+        plugin: env!("CARGO_PKG_VERSION"),
+        commands,
+        features: FEATURES
+            .iter()
+            .filter(|(_, enabled)| *enabled)
+            .map(|(name, _)| *name)
+            .collect(),
+    })
+}
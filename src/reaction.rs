@@ -0,0 +1,187 @@
+use std::{
+    collections::HashMap,
+    hash::{BuildHasher, RandomState},
+    sync::LazyLock,
+    time::{Duration, Instant},
+};
+
+use mirajazz::state::DeviceStateUpdate;
+use tokio::sync::{Mutex, oneshot};
+
+use crate::{
+    DEVICES,
+    compose::{self, Layer},
+    stats,
+};
+
+/// Rounds run when the command doesn't ask for a number
+pub const DEFAULT_ROUNDS: u32 = 5;
+
+/// Most rounds a single measurement runs
+const MAX_ROUNDS: u32 = 20;
+
+/// Shortest wait before a flash, a random amount up to [WAIT_JITTER] is added so taps can't follow a rhythm
+const MIN_WAIT: Duration = Duration::from_secs(1);
+const WAIT_JITTER: Duration = Duration::from_secs(2);
+
+/// Longest wait for a tap after the flash before the round counts as missed
+const TAP_TIMEOUT: Duration = Duration::from_secs(3);
+
+/// Running measurement of a device
+struct Measurement {
+    /// Key (OpenDeck position) that flashes and has to be tapped
+    position: u8,
+    /// Whether the key currently shows the flash
+    lit: bool,
+    /// Receives the time of the tap, set while a flash waits for one
+    tap: Option<oneshot::Sender<Instant>>,
+}
+
+/// Measurements by device id, only one per device at a time
+static RUNNING: LazyLock<Mutex<HashMap<String, Measurement>>> =
+    LazyLock::new(|| Mutex::new(HashMap::new()));
+
+/// Returns true while the key is flashed for a measurement
+pub async fn is_lit(id: &str, position: u8) -> bool {
+    RUNNING
+        .lock()
+        .await
+        .get(id)
+        .is_some_and(|measurement| measurement.lit && measurement.position == position)
+}
+
+/// Looks at the update (in OpenDeck positions), returns true if it should be swallowed
+///
+/// Presses and releases of the measured key never reach OpenDeck, a press while the flash is
+/// shown ends the round
+pub async fn filter_update(id: &str, update: &DeviceStateUpdate) -> bool {
+    let mut running = RUNNING.lock().await;
+
+    let Some(measurement) = running.get_mut(id) else {
+        return false;
+    };
+
+    match *update {
+        DeviceStateUpdate::ButtonDown(key) if key == measurement.position => {
+            if let Some(tap) = measurement.tap.take() {
+                tap.send(Instant::now()).ok();
+            }
+
+            true
+        }
+        DeviceStateUpdate::ButtonUp(key) => key == measurement.position,
+        _ => false,
+    }
+}
+
+/// Flashes the key `rounds` times and times how long it takes until it's tapped after each flash
+///
+/// The time covers queueing and writing the image, the panel and the user's reaction, so only
+/// runs of the same person are comparable, e.g. with the device on different USB ports or hubs.
+/// Every tap is recorded as `display_latency` in the stats of the device.
+pub async fn run(id: &str, position: u8, rounds: u32) -> Result<String, String> {
+    if rounds == 0 || rounds > MAX_ROUNDS {
+        return Err(format!("rounds must be between 1 and {}", MAX_ROUNDS));
+    }
+
+    let kind = DEVICES
+        .read()
+        .await
+        .get(id)
+        .map(|device| device.kind())
+        .ok_or("unknown device")?;
+
+    if position as usize >= kind.key_count() {
+        return Err(format!("no key at position {}", position));
+    }
+
+    {
+        let mut running = RUNNING.lock().await;
+
+        if running.contains_key(id) {
+            return Err("a measurement is already running".to_string());
+        }
+
+        running.insert(
+            id.to_string(),
+            Measurement {
+                position,
+                lit: false,
+                tap: None,
+            },
+        );
+    }
+
+    log::info!(
+        "Measuring display latency of {} on key {}, {} rounds",
+        id,
+        position,
+        rounds
+    );
+
+    let mut taps = vec![];
+
+    for _ in 0..rounds {
+        if !DEVICES.read().await.contains_key(id) {
+            break;
+        }
+
+        match round(id, position).await {
+            Some(elapsed) => {
+                stats::record_display_latency(id, elapsed).await;
+                taps.push(elapsed);
+            }
+            None => log::info!("No tap on key {} of {} in time", position, id),
+        }
+    }
+
+    RUNNING.lock().await.remove(id);
+
+    if taps.is_empty() {
+        return Err("no taps".to_string());
+    }
+
+    let mean = taps.iter().sum::<Duration>() / taps.len() as u32;
+    let (min, max) = (taps.iter().min().unwrap(), taps.iter().max().unwrap());
+
+    Ok(format!(
+        "{}/{} taps, mean {}ms, min {}ms, max {}ms",
+        taps.len(),
+        rounds,
+        mean.as_millis(),
+        min.as_millis(),
+        max.as_millis()
+    ))
+}
+
+/// Flashes the key once, returns time from queueing the flash until the tap
+async fn round(id: &str, position: u8) -> Option<Duration> {
+    // Doesn't have to be good randomness, just different every time
+    let jitter = RandomState::new().hash_one(()) % (WAIT_JITTER.as_millis() as u64 + 1);
+    tokio::time::sleep(MIN_WAIT + Duration::from_millis(jitter)).await;
+
+    let (tx, rx) = oneshot::channel();
+
+    if let Some(measurement) = RUNNING.lock().await.get_mut(id) {
+        measurement.lit = true;
+        measurement.tap = Some(tx);
+    }
+
+    let started = Instant::now();
+    let shown = compose::invalidate(id, &[position], Layer::Overlay).await;
+
+    let tapped = if shown {
+        tokio::time::timeout(TAP_TIMEOUT, rx).await.ok()
+    } else {
+        None
+    };
+
+    if let Some(measurement) = RUNNING.lock().await.get_mut(id) {
+        measurement.lit = false;
+        measurement.tap = None;
+    }
+
+    compose::invalidate(id, &[position], Layer::Overlay).await;
+
+    tapped?.ok().map(|tapped| tapped - started)
+}
@@ -0,0 +1,741 @@
+use std::{
+    collections::{HashMap, VecDeque},
+    iter::zip,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+use mirajazz::{error::MirajazzError, state::DeviceStateUpdate, types::DeviceInput};
+use tokio::sync::Notify;
+
+use crate::{
+    backend::{DeckDevice, ReportReader},
+    inputs::{InputProcessor, N1_DIAL, N1_DIAL_PRESS, SYNC_FRAME},
+    stats,
+};
+
+/// Identical reports arriving within this window are treated as duplicates
+const DUPLICATE_WINDOW: Duration = Duration::from_millis(3);
+
+/// Report prefix of input events
+const ACK: [u8; 3] = [65, 67, 75];
+
+/// Offset of the first event slot (input code, state) in a report
+const FIRST_SLOT: usize = 9;
+
+/// Most event slots looked at in one report, firmwares seen so far pack at most two
+const MAX_SLOTS: usize = 4;
+
+/// Returns (input, state) of every event packed into the report
+///
+/// Slots follow each other two bytes apart, the first one with input 0 ends the list. Reports
+/// too short for even the first slot have none.
+fn event_slots(data: &[u8]) -> Vec<(u8, u8)> {
+    data.get(FIRST_SLOT..)
+        .unwrap_or_default()
+        .chunks_exact(2)
+        .take(MAX_SLOTS)
+        .map(|slot| (slot[0], slot[1]))
+        .enumerate()
+        .take_while(|(index, (input, _))| *index == 0 || *input != 0)
+        .map(|(_, slot)| slot)
+        .collect()
+}
+
+/// State update read from the device
+#[derive(Debug, Clone)]
+pub struct InputUpdate {
+    pub update: DeviceStateUpdate,
+    /// How long the key or dial was held, set on releases
+    pub held: Option<Duration>,
+}
+
+impl From<DeviceStateUpdate> for InputUpdate {
+    fn from(update: DeviceStateUpdate) -> Self {
+        Self { update, held: None }
+    }
+}
+
+/// What happened to an update given to [InputQueue::push]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Pushed {
+    Queued,
+    /// Twist was added to the twist of the same dial waiting at the end of the queue
+    Merged,
+    /// A twist had to be dropped to stay within capacity
+    DroppedTwist,
+}
+
+/// Updates read from the device, waiting to be forwarded to OpenDeck
+///
+/// Bounded, so a stalled OpenDeck connection can't pile up events. Twists only carry relative
+/// movement, so a twist queued right behind one of the same dial is merged into it, and the
+/// oldest ones are dropped when the queue is full. Presses and releases wait for room
+/// instead: losing a release would leave the key stuck in OpenDeck.
+pub struct InputQueue {
+    updates: Mutex<VecDeque<InputUpdate>>,
+    capacity: usize,
+    pushed: Notify,
+    popped: Notify,
+}
+
+impl InputQueue {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            updates: Mutex::new(VecDeque::with_capacity(capacity)),
+            capacity,
+            pushed: Notify::new(),
+            popped: Notify::new(),
+        }
+    }
+
+    /// Queues the update, merging or dropping twists while OpenDeck is behind
+    pub async fn push(&self, update: InputUpdate) -> Pushed {
+        let is_twist = |update: &InputUpdate| {
+            matches!(update.update, DeviceStateUpdate::EncoderTwist(..))
+        };
+
+        loop {
+            {
+                let mut updates = self.updates.lock().unwrap();
+
+                // Only the last queued update is looked at, merging past a press would reorder them
+                if let DeviceStateUpdate::EncoderTwist(encoder, delta) = update.update
+                    && let Some(last) = updates.back_mut()
+                    && let DeviceStateUpdate::EncoderTwist(last_encoder, last_delta) =
+                        &mut last.update
+                    && *last_encoder == encoder
+                    && let Some(sum) = last_delta.checked_add(delta)
+                {
+                    // Turning back and forth can cancel out completely
+                    if sum == 0 {
+                        updates.pop_back();
+                    } else {
+                        *last_delta = sum;
+                    }
+
+                    return Pushed::Merged;
+                }
+
+                if updates.len() < self.capacity {
+                    updates.push_back(update);
+                    self.pushed.notify_one();
+                    return Pushed::Queued;
+                }
+
+                if let Some(index) = updates.iter().position(is_twist) {
+                    updates.remove(index);
+                    updates.push_back(update);
+                    self.pushed.notify_one();
+                    return Pushed::DroppedTwist;
+                }
+
+                // Full of presses and releases, newest twist is the one to go
+                if is_twist(&update) {
+                    return Pushed::DroppedTwist;
+                }
+            }
+
+            self.popped.notified().await;
+        }
+    }
+
+    /// Waits for the oldest queued update, nothing is lost if the wait is dropped
+    pub async fn pop(&self) -> InputUpdate {
+        loop {
+            if let Some(update) = self.updates.lock().unwrap().pop_front() {
+                self.popped.notify_one();
+                return update;
+            }
+
+            self.pushed.notified().await;
+        }
+    }
+}
+
+/// Reads raw input reports from the device and turns them into state updates
+///
+/// Does the same job as [mirajazz::state::DeviceStateReader::read], but gives us control over report parsing
+pub struct InputReader {
+    id: String,
+    reader: Box<dyn ReportReader>,
+    /// Turns input codes into device inputs, with state of this device only
+    processor: InputProcessor,
+    buttons: Vec<bool>,
+    encoders: Vec<bool>,
+    /// When currently held keys and encoders were pressed
+    buttons_pressed_at: Vec<Option<Instant>>,
+    encoders_pressed_at: Vec<Option<Instant>>,
+    dedupe: bool,
+    /// Last state reported for every input and when, see [Self::is_duplicate]
+    last_reports: HashMap<u8, (u8, Instant)>,
+}
+
+impl InputReader {
+    pub fn new(
+        id: &str,
+        device: &dyn DeckDevice,
+        mut processor: InputProcessor,
+        dedupe: bool,
+    ) -> Self {
+        let reader = device.report_reader();
+        processor.set_reports_releases(reader.supports_both_states());
+
+        // Face buttons are reported as keys after the display keys
+        let buttons = device.key_count() + device.kind().face_button_count();
+
+        Self {
+            id: id.to_string(),
+            reader,
+            processor,
+            buttons: vec![false; buttons],
+            encoders: vec![false; device.encoder_count()],
+            buttons_pressed_at: vec![None; buttons],
+            encoders_pressed_at: vec![None; device.encoder_count()],
+            dedupe,
+            last_reports: HashMap::new(),
+        }
+    }
+
+    /// Turns the hold-and-rotate gesture on or off, stays off without release reports
+    pub fn set_hold_rotate(&mut self, hold_rotate: bool) {
+        self.processor
+            .set_hold_rotate(hold_rotate && self.reader.supports_both_states());
+    }
+
+    /// Returns when the held dial counts as stuck, see [InputProcessor::dial_release_deadline]
+    ///
+    /// `None` without release reports, the dial never counts as held then.
+    pub fn dial_release_deadline(&self, timeout: Duration) -> Option<Instant> {
+        if !self.reader.supports_both_states() {
+            return None;
+        }
+
+        self.processor.dial_release_deadline(timeout)
+    }
+
+    /// Releases the held dial as if its release report arrived, returns the resulting updates
+    ///
+    /// Used when the release report got lost. Goes through the processor like a real release, so
+    /// its state, the hold duration and the hold-and-rotate gesture all end the same way.
+    pub fn release_dial(&mut self) -> Vec<InputUpdate> {
+        if !self.processor.is_dial_held() {
+            return vec![];
+        }
+
+        let mut updates = vec![];
+
+        let input = self
+            .processor
+            .process(N1_DIAL_PRESS, 0)
+            .unwrap_or(DeviceInput::NoData);
+        let pending = self.processor.take_pending();
+
+        for input in [Some(input), pending].into_iter().flatten() {
+            for update in self.input_to_updates(input) {
+                updates.push(self.with_hold_duration(update));
+            }
+        }
+
+        updates
+    }
+
+    /// Waits for the next report and returns updates of every event packed into it
+    pub async fn read(&mut self) -> Result<Vec<InputUpdate>, MirajazzError> {
+        let data = self.reader.read_report().await?;
+
+        // Devices with very old firmware (protocol version 0) don't prefix reports with ACK
+        if !data.starts_with(&ACK) && self.reader.protocol_version() > 0 {
+            return Ok(vec![]);
+        }
+
+        let mut updates = vec![];
+
+        for (index, (input, state)) in event_slots(&data).into_iter().enumerate() {
+            let state = if self.reader.supports_both_states() {
+                state
+            } else {
+                0x1u8
+            };
+
+            // Nothing is held according to the device, a dial that still is lost its release.
+            // Keys are reconciled by the processor, they stay held until released.
+            if index == 0 && input == SYNC_FRAME && self.processor.is_dial_held() {
+                log::warn!(
+                    "Sync frame from {} while the dial is held, releasing it",
+                    self.id
+                );
+                stats::count_synthetic_release(&self.id).await;
+
+                updates.extend(self.release_dial());
+            }
+
+            if self.is_duplicate(input, state) {
+                log::debug!("Dropping duplicate report input={} state={}", input, state);
+                stats::count_duplicate_report(&self.id).await;
+
+                continue;
+            }
+
+            let input = match self.processor.process(input, state) {
+                Ok(input) => input,
+                Err(e) if index == 0 => return Err(e),
+                // Firmwares without more slots may leave anything in those bytes
+                Err(_) => {
+                    log::debug!("Ignoring unknown input {} in slot {}", input, index);
+                    break;
+                }
+            };
+
+            let pending = self.processor.take_pending();
+
+            for input in [Some(input), pending].into_iter().flatten() {
+                for update in self.input_to_updates(input) {
+                    updates.push(self.with_hold_duration(update));
+                }
+            }
+        }
+
+        Ok(updates)
+    }
+
+    /// Remembers when keys got pressed and attaches hold duration to releases
+    fn with_hold_duration(&mut self, update: DeviceStateUpdate) -> InputUpdate {
+        let now = Instant::now();
+
+        let held = match update {
+            DeviceStateUpdate::ButtonDown(key) => {
+                if let Some(at) = self.buttons_pressed_at.get_mut(key as usize) {
+                    *at = Some(now);
+                }
+                None
+            }
+            DeviceStateUpdate::EncoderDown(encoder) => {
+                // Dial presses held back by hold-and-rotate count from the actual press
+                let pressed_at = match encoder {
+                    N1_DIAL => self.processor.dial_pressed_at().unwrap_or(now),
+                    _ => now,
+                };
+
+                if let Some(at) = self.encoders_pressed_at.get_mut(encoder as usize) {
+                    *at = Some(pressed_at);
+                }
+                None
+            }
+            DeviceStateUpdate::ButtonUp(key) => self
+                .buttons_pressed_at
+                .get_mut(key as usize)
+                .and_then(Option::take)
+                .map(|at| now - at),
+            DeviceStateUpdate::EncoderUp(encoder) => self
+                .encoders_pressed_at
+                .get_mut(encoder as usize)
+                .and_then(Option::take)
+                .map(|at| now - at),
+            DeviceStateUpdate::EncoderTwist(..) => None,
+        };
+
+        InputUpdate { update, held }
+    }
+
+    /// Some units send every report twice, in quick succession
+    ///
+    /// Compared with the previous report of the same input, so a duplicate is caught even with
+    /// reports of other keys in between. A changed state never is one: press, release and press
+    /// again all get through, however quick.
+    fn is_duplicate(&mut self, input: u8, state: u8) -> bool {
+        let now = Instant::now();
+        let previous = self.last_reports.insert(input, (state, now));
+
+        if !self.dedupe {
+            return false;
+        }
+
+        matches!(previous, Some((s, at)) if s == state && now - at < DUPLICATE_WINDOW)
+    }
+
+    fn input_to_updates(&mut self, input: DeviceInput) -> Vec<DeviceStateUpdate> {
+        let both_states = self.reader.supports_both_states();
+        let mut updates = vec![];
+
+        match input {
+            DeviceInput::ButtonStateChange(buttons) => {
+                for (index, (their, mine)) in zip(buttons.iter(), self.buttons.iter()).enumerate() {
+                    if !both_states {
+                        if *their {
+                            updates.push(DeviceStateUpdate::ButtonDown(index as u8));
+                            updates.push(DeviceStateUpdate::ButtonUp(index as u8));
+                        }
+                    } else if their != mine {
+                        if *their {
+                            updates.push(DeviceStateUpdate::ButtonDown(index as u8));
+                        } else {
+                            updates.push(DeviceStateUpdate::ButtonUp(index as u8));
+                        }
+                    }
+                }
+
+                self.buttons = buttons;
+            }
+            DeviceInput::EncoderStateChange(encoders) => {
+                for (index, (their, mine)) in zip(encoders.iter(), self.encoders.iter()).enumerate() {
+                    if !both_states {
+                        if *their {
+                            updates.push(DeviceStateUpdate::EncoderDown(index as u8));
+                            updates.push(DeviceStateUpdate::EncoderUp(index as u8));
+                        }
+                    } else if their != mine {
+                        if *their {
+                            updates.push(DeviceStateUpdate::EncoderDown(index as u8));
+                        } else {
+                            updates.push(DeviceStateUpdate::EncoderUp(index as u8));
+                        }
+                    }
+                }
+
+                self.encoders = encoders;
+            }
+            DeviceInput::EncoderTwist(twist) => {
+                for (index, change) in twist.iter().enumerate() {
+                    if *change != 0 {
+                        updates.push(DeviceStateUpdate::EncoderTwist(index as u8, *change));
+                    }
+                }
+            }
+            DeviceInput::NoData => {}
+        }
+
+        updates
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Input report with the slots packed after the header, zero padded like the firmware sends it
+    fn report(slots: &[(u8, u8)]) -> Vec<u8> {
+        let mut data = ACK.to_vec();
+        data.resize(FIRST_SLOT, 0);
+
+        for (input, state) in slots {
+            data.extend([*input, *state]);
+        }
+
+        data.resize(64, 0);
+        data
+    }
+
+    #[test]
+    fn every_packed_slot_is_read_up_to_the_first_empty_one() {
+        assert_eq!(event_slots(&report(&[(4, 1)])), [(4, 1)]);
+        assert_eq!(event_slots(&report(&[(4, 1), (5, 0)])), [(4, 1), (5, 0)]);
+        assert_eq!(event_slots(&report(&[(4, 1), (0, 0), (5, 1)])), [(4, 1)]);
+    }
+
+    #[test]
+    fn slots_past_the_limit_are_ignored() {
+        let slots: Vec<(u8, u8)> = (1..=MAX_SLOTS as u8 + 2).map(|input| (input, 1)).collect();
+
+        assert_eq!(event_slots(&report(&slots)), slots[..MAX_SLOTS]);
+    }
+
+    #[test]
+    fn first_slot_is_kept_even_when_empty() {
+        // Sync frames report input 0
+        assert_eq!(event_slots(&report(&[])), [(0, 0)]);
+        assert_eq!(event_slots(&report(&[])[..FIRST_SLOT]), []);
+        assert_eq!(event_slots(&report(&[(7, 1)])[..FIRST_SLOT + 2]), [(7, 1)]);
+    }
+
+    fn twist(delta: i8) -> InputUpdate {
+        DeviceStateUpdate::EncoderTwist(0, delta).into()
+    }
+
+    async fn drain(queue: &InputQueue, count: usize) -> Vec<DeviceStateUpdate> {
+        let mut updates = vec![];
+
+        for _ in 0..count {
+            updates.push(queue.pop().await.update);
+        }
+
+        updates
+    }
+
+    #[tokio::test]
+    async fn full_queue_drops_the_oldest_twist() {
+        let queue = InputQueue::new(3);
+
+        assert_eq!(queue.push(twist(1)).await, Pushed::Queued);
+        assert_eq!(queue.push(DeviceStateUpdate::ButtonDown(4).into()).await, Pushed::Queued);
+        assert_eq!(queue.push(twist(2)).await, Pushed::Queued);
+        assert_eq!(queue.push(DeviceStateUpdate::ButtonUp(4).into()).await, Pushed::DroppedTwist);
+
+        assert_eq!(
+            format!("{:?}", drain(&queue, 3).await),
+            format!(
+                "{:?}",
+                [
+                    DeviceStateUpdate::ButtonDown(4),
+                    DeviceStateUpdate::EncoderTwist(0, 2),
+                    DeviceStateUpdate::ButtonUp(4),
+                ]
+            )
+        );
+    }
+
+    #[tokio::test]
+    async fn twist_is_dropped_when_only_presses_are_queued() {
+        let queue = InputQueue::new(2);
+
+        queue.push(DeviceStateUpdate::ButtonDown(1).into()).await;
+        queue.push(DeviceStateUpdate::ButtonDown(2).into()).await;
+
+        assert_eq!(queue.push(twist(1)).await, Pushed::DroppedTwist);
+        assert_eq!(
+            format!("{:?}", drain(&queue, 2).await),
+            format!(
+                "{:?}",
+                [DeviceStateUpdate::ButtonDown(1), DeviceStateUpdate::ButtonDown(2)]
+            )
+        );
+    }
+
+    #[tokio::test]
+    async fn twists_behind_a_twist_of_the_same_dial_are_merged() {
+        let queue = InputQueue::new(4);
+
+        assert_eq!(queue.push(twist(1)).await, Pushed::Queued);
+        assert_eq!(queue.push(twist(2)).await, Pushed::Merged);
+        assert_eq!(queue.push(DeviceStateUpdate::ButtonDown(1).into()).await, Pushed::Queued);
+        assert_eq!(queue.push(twist(1)).await, Pushed::Queued);
+        assert_eq!(queue.push(twist(-1)).await, Pushed::Merged);
+
+        assert_eq!(
+            format!("{:?}", drain(&queue, 2).await),
+            format!(
+                "{:?}",
+                [DeviceStateUpdate::EncoderTwist(0, 3), DeviceStateUpdate::ButtonDown(1)]
+            )
+        );
+    }
+
+    #[tokio::test]
+    async fn press_waits_for_room_instead_of_being_dropped() {
+        let queue = std::sync::Arc::new(InputQueue::new(1));
+
+        queue.push(DeviceStateUpdate::ButtonDown(1).into()).await;
+
+        let pushing = tokio::spawn({
+            let queue = queue.clone();
+            async move { queue.push(DeviceStateUpdate::ButtonUp(1).into()).await }
+        });
+
+        tokio::time::sleep(Duration::from_millis(20)).await;
+        assert!(!pushing.is_finished());
+
+        queue.pop().await;
+
+        assert_eq!(pushing.await.unwrap(), Pushed::Queued);
+        assert!(matches!(
+            queue.pop().await.update,
+            DeviceStateUpdate::ButtonUp(1)
+        ));
+    }
+
+    /// Hands out the given reports one after another, from a device reporting presses and releases
+    struct ScriptedReports(Mutex<VecDeque<Vec<u8>>>);
+
+    #[async_trait::async_trait]
+    impl ReportReader for ScriptedReports {
+        async fn read_report(&self) -> Result<Vec<u8>, MirajazzError> {
+            Ok(self.0.lock().unwrap().pop_front().expect("no report left"))
+        }
+
+        fn protocol_version(&self) -> usize {
+            3
+        }
+
+        fn supports_both_states(&self) -> bool {
+            true
+        }
+    }
+
+    /// Reader of an N1 getting the reports, each given as its slots
+    fn reader(id: &str, dedupe: bool, reports: &[&[(u8, u8)]]) -> InputReader {
+        let kind = crate::mappings::Kind::N1;
+        let buttons = kind.key_count() + kind.face_button_count();
+        let reports = reports.iter().map(|slots| report(slots)).collect();
+
+        let mut processor = InputProcessor::default();
+        processor.set_reports_releases(true);
+
+        InputReader {
+            id: id.to_string(),
+            reader: Box::new(ScriptedReports(Mutex::new(reports))),
+            processor,
+            buttons: vec![false; buttons],
+            encoders: vec![false; kind.encoder_count()],
+            buttons_pressed_at: vec![None; buttons],
+            encoders_pressed_at: vec![None; kind.encoder_count()],
+            dedupe,
+            last_reports: HashMap::new(),
+        }
+    }
+
+    /// Reads `count` reports and returns all their updates, formatted to compare them
+    async fn read(reader: &mut InputReader, count: usize) -> String {
+        let mut updates = vec![];
+
+        for _ in 0..count {
+            updates.extend(reader.read().await.unwrap().into_iter().map(|u| u.update));
+        }
+
+        format!("{:?}", updates)
+    }
+
+    // Input 16 is the first top LCD (key 0), 51 a clockwise twist of the dial
+    const KEY: u8 = 16;
+    const TWIST: u8 = 51;
+
+    #[tokio::test]
+    async fn repeated_twist_is_dropped() {
+        let id = "N1-dedupe-twist";
+        let mut reader = reader(id, true, &[&[(TWIST, 0)], &[(TWIST, 0)]]);
+
+        assert_eq!(
+            read(&mut reader, 2).await,
+            format!("{:?}", [DeviceStateUpdate::EncoderTwist(0, 1)])
+        );
+        assert_eq!(stats::get(id).await.duplicate_reports, 1);
+    }
+
+    #[tokio::test]
+    async fn duplicate_is_caught_with_other_inputs_in_between() {
+        let mut reader = reader(
+            "N1-dedupe-between",
+            true,
+            &[&[(TWIST, 0), (KEY, 1)], &[(TWIST, 0), (KEY, 0)]],
+        );
+
+        assert_eq!(
+            read(&mut reader, 2).await,
+            format!(
+                "{:?}",
+                [
+                    DeviceStateUpdate::EncoderTwist(0, 1),
+                    DeviceStateUpdate::ButtonDown(0),
+                    DeviceStateUpdate::ButtonUp(0),
+                ]
+            )
+        );
+    }
+
+    #[tokio::test]
+    async fn quick_press_release_press_all_get_through() {
+        let mut reader = reader(
+            "N1-dedupe-changed",
+            true,
+            &[&[(KEY, 1), (KEY, 0), (KEY, 1)]],
+        );
+
+        assert_eq!(
+            read(&mut reader, 1).await,
+            format!(
+                "{:?}",
+                [
+                    DeviceStateUpdate::ButtonDown(0),
+                    DeviceStateUpdate::ButtonUp(0),
+                    DeviceStateUpdate::ButtonDown(0),
+                ]
+            )
+        );
+    }
+
+    #[tokio::test]
+    async fn duplicates_pass_with_dedupe_off() {
+        let mut reader = reader("N1-dedupe-off", false, &[&[(TWIST, 0), (TWIST, 0)]]);
+
+        assert_eq!(
+            read(&mut reader, 1).await,
+            format!(
+                "{:?}",
+                [
+                    DeviceStateUpdate::EncoderTwist(0, 1),
+                    DeviceStateUpdate::EncoderTwist(0, 1),
+                ]
+            )
+        );
+    }
+
+    // Input 17 is the second top LCD (key 1)
+    const OTHER_KEY: u8 = 17;
+
+    #[tokio::test]
+    async fn key_stays_held_across_sync_frames() {
+        let mut reader = reader("N1-sync-held", true, &[&[(KEY, 1)], &[], &[], &[(KEY, 0)]]);
+
+        assert_eq!(
+            read(&mut reader, 4).await,
+            format!(
+                "{:?}",
+                [
+                    DeviceStateUpdate::ButtonDown(0),
+                    DeviceStateUpdate::ButtonUp(0),
+                ]
+            )
+        );
+    }
+
+    #[tokio::test]
+    async fn pressing_another_key_keeps_the_first_held() {
+        let mut reader = reader(
+            "N1-sync-two-keys",
+            true,
+            &[
+                &[(KEY, 1)],
+                &[(OTHER_KEY, 1)],
+                &[],
+                &[(KEY, 0)],
+                &[(OTHER_KEY, 0)],
+            ],
+        );
+
+        assert_eq!(
+            read(&mut reader, 5).await,
+            format!(
+                "{:?}",
+                [
+                    DeviceStateUpdate::ButtonDown(0),
+                    DeviceStateUpdate::ButtonDown(1),
+                    DeviceStateUpdate::ButtonUp(0),
+                    DeviceStateUpdate::ButtonUp(1),
+                ]
+            )
+        );
+    }
+
+    #[tokio::test]
+    async fn sync_frame_releases_a_held_dial_but_not_keys() {
+        let id = "N1-sync-dial";
+        let mut reader = reader(
+            id,
+            true,
+            &[&[(KEY, 1)], &[(N1_DIAL_PRESS, 1)], &[], &[(KEY, 0)]],
+        );
+
+        assert_eq!(
+            read(&mut reader, 4).await,
+            format!(
+                "{:?}",
+                [
+                    DeviceStateUpdate::ButtonDown(0),
+                    DeviceStateUpdate::EncoderDown(0),
+                    DeviceStateUpdate::EncoderUp(0),
+                    DeviceStateUpdate::ButtonUp(0),
+                ]
+            )
+        );
+        assert_eq!(stats::get(id).await.synthetic_releases, 1);
+    }
+}
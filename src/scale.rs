@@ -0,0 +1,356 @@
+use std::sync::LazyLock;
+
+use image::{DynamicImage, GenericImageView, Rgb, RgbImage, imageops};
+use serde::{Deserialize, Serialize};
+
+/// How images that don't match the key aspect are fitted
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ScaleMode {
+    /// Whole image is shown, padded with the background color
+    #[default]
+    Letterbox,
+    /// Key is covered, overflow is cropped evenly from both sides
+    Fill,
+    /// Images that fit are shown at their own size, padded with the background color
+    ///
+    /// Keeps small icons (e.g. 72×72) sharp instead of blowing them up, larger ones are shrunk
+    /// like with [ScaleMode::Letterbox]
+    Center,
+}
+
+impl ScaleMode {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "letterbox" => Some(Self::Letterbox),
+            "fill" => Some(Self::Fill),
+            "center" => Some(Self::Center),
+            _ => None,
+        }
+    }
+}
+
+/// Filter used whenever a key image is resized
+///
+/// Nearest and Triangle blur or break up small text on the 64×64 LCDs, CatmullRom keeps it
+/// readable at little cost, Lanczos3 is a bit sharper and slower
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ResizeFilter {
+    Nearest,
+    Triangle,
+    #[default]
+    CatmullRom,
+    Lanczos3,
+}
+
+impl ResizeFilter {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "nearest" => Some(Self::Nearest),
+            "triangle" => Some(Self::Triangle),
+            "catmullrom" => Some(Self::CatmullRom),
+            "lanczos3" => Some(Self::Lanczos3),
+            _ => None,
+        }
+    }
+
+    fn filter_type(self) -> imageops::FilterType {
+        match self {
+            Self::Nearest => imageops::FilterType::Nearest,
+            Self::Triangle => imageops::FilterType::Triangle,
+            Self::CatmullRom => imageops::FilterType::CatmullRom,
+            Self::Lanczos3 => imageops::FilterType::Lanczos3,
+        }
+    }
+}
+
+/// Filter for devices without their own setting, `OPENDECK_N1_RESIZE_FILTER` at startup
+pub static DEFAULT_FILTER: LazyLock<ResizeFilter> = LazyLock::new(|| {
+    let Ok(value) = std::env::var("OPENDECK_N1_RESIZE_FILTER") else {
+        return ResizeFilter::default();
+    };
+
+    ResizeFilter::from_name(&value).unwrap_or_else(|| {
+        log::warn!(
+            "Unknown OPENDECK_N1_RESIZE_FILTER {}, using catmullrom",
+            value
+        );
+        ResizeFilter::default()
+    })
+});
+
+/// Mode for devices without their own setting, `OPENDECK_N1_SCALE_MODE` at startup
+pub static DEFAULT_MODE: LazyLock<ScaleMode> = LazyLock::new(|| {
+    let Ok(value) = std::env::var("OPENDECK_N1_SCALE_MODE") else {
+        return ScaleMode::default();
+    };
+
+    ScaleMode::from_name(&value).unwrap_or_else(|| {
+        log::warn!("Unknown OPENDECK_N1_SCALE_MODE {}, using letterbox", value);
+        ScaleMode::default()
+    })
+});
+
+/// Longest side of an image that is decoded at all, anything bigger is refused
+pub const MAX_SOURCE_SIZE: u32 = 4096;
+
+/// Images up to this many pixels smaller than the key on a side are padded instead of resized
+///
+/// Resizing 95×96 to 96×96 blurs the whole image for one pixel of difference
+const PAD_TOLERANCE: u32 = 2;
+
+/// Background used for devices without their own setting
+pub const DEFAULT_BACKGROUND: Rgb<u8> = Rgb([0, 0, 0]);
+
+/// Parses color in `#rrggbb` or `rrggbb` format
+pub fn parse_color(value: &str) -> Option<[u8; 3]> {
+    let value = value.strip_prefix('#').unwrap_or(value);
+
+    if value.len() != 6 || !value.is_ascii() {
+        return None;
+    }
+
+    let channel = |index: usize| u8::from_str_radix(&value[index..index + 2], 16).ok();
+
+    Some([channel(0)?, channel(2)?, channel(4)?])
+}
+
+/// Composites image with alpha over the background, the JPEG sent to the device has no alpha
+pub fn flatten(image: DynamicImage, background: Rgb<u8>) -> DynamicImage {
+    if !image.color().has_alpha() {
+        return image;
+    }
+
+    let image = image.to_rgba8();
+    let mut canvas = RgbImage::from_pixel(image.width(), image.height(), background);
+
+    for (pixel, source) in canvas.pixels_mut().zip(image.pixels()) {
+        let alpha = source[3] as u16;
+
+        for channel in 0..3 {
+            pixel[channel] = ((source[channel] as u16 * alpha
+                + pixel[channel] as u16 * (255 - alpha)
+                + 127)
+                / 255) as u8;
+        }
+    }
+
+    DynamicImage::ImageRgb8(canvas)
+}
+
+/// Brings image to exactly `size` using the mode, letterbox bars use the background
+///
+/// The only place key images get resized, so the filter applies the same way to every key size
+/// and kind. The upload has nothing left to scale.
+pub fn fit(
+    image: DynamicImage,
+    size: (usize, usize),
+    mode: ScaleMode,
+    background: Rgb<u8>,
+    filter: ResizeFilter,
+) -> DynamicImage {
+    let (target_width, target_height) = (size.0 as u32, size.1 as u32);
+    let (width, height) = image.dimensions();
+    let filter = filter.filter_type();
+
+    if (width, height) == (target_width, target_height) {
+        return image;
+    }
+
+    let fits = width <= target_width && height <= target_height;
+
+    if fits
+        && (mode == ScaleMode::Center
+            || (target_width - width <= PAD_TOLERANCE && target_height - height <= PAD_TOLERANCE))
+    {
+        return exact(image, size, background);
+    }
+
+    if width * target_height == height * target_width {
+        return image.resize_exact(target_width, target_height, filter);
+    }
+
+    match mode {
+        ScaleMode::Letterbox | ScaleMode::Center => {
+            letterbox(image, target_width, target_height, background, filter)
+        }
+        ScaleMode::Fill => image.resize_to_fill(target_width, target_height, filter),
+    }
+}
+
+/// Puts image centered on a canvas of exactly `size` filled with the background, never resizing
+///
+/// Sides larger than the canvas are cropped evenly. Firmwares garble or offset images that
+/// aren't exactly the size they declared, so this runs last before encoding for the device.
+pub fn exact(image: DynamicImage, size: (usize, usize), background: Rgb<u8>) -> DynamicImage {
+    let (target_width, target_height) = (size.0 as u32, size.1 as u32);
+    let (width, height) = image.dimensions();
+
+    if (width, height) == (target_width, target_height) {
+        return image;
+    }
+
+    // Negative offsets crop, overlay only draws what lands on the canvas
+    let (x, y) = (
+        (target_width as i64 - width as i64) / 2,
+        (target_height as i64 - height as i64) / 2,
+    );
+
+    let mut canvas = RgbImage::from_pixel(target_width, target_height, background);
+    imageops::overlay(&mut canvas, &image.to_rgb8(), x, y);
+
+    DynamicImage::ImageRgb8(canvas)
+}
+
+/// Pads image that was fitted to the content size with the background on every side
+pub fn pad(image: DynamicImage, padding: usize, background: Rgb<u8>) -> DynamicImage {
+    if padding == 0 {
+        return image;
+    }
+
+    let (width, height) = image.dimensions();
+    let padding = padding as u32;
+
+    let mut canvas = RgbImage::from_pixel(width + padding * 2, height + padding * 2, background);
+    imageops::overlay(
+        &mut canvas,
+        &image.to_rgb8(),
+        padding as i64,
+        padding as i64,
+    );
+
+    DynamicImage::ImageRgb8(canvas)
+}
+
+/// Fits image inside the size keeping its aspect ratio, centering it on the background
+fn letterbox(
+    image: DynamicImage,
+    target_width: u32,
+    target_height: u32,
+    background: Rgb<u8>,
+    filter: imageops::FilterType,
+) -> DynamicImage {
+    let scaled = image.resize(target_width, target_height, filter);
+
+    let mut canvas = RgbImage::from_pixel(target_width, target_height, background);
+    imageops::overlay(
+        &mut canvas,
+        &scaled.to_rgb8(),
+        ((target_width - scaled.width()) / 2) as i64,
+        ((target_height - scaled.height()) / 2) as i64,
+    );
+
+    DynamicImage::ImageRgb8(canvas)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const RED: Rgb<u8> = Rgb([255, 0, 0]);
+
+    fn image(width: u32, height: u32) -> DynamicImage {
+        DynamicImage::ImageRgb8(RgbImage::from_pixel(width, height, Rgb([255, 255, 255])))
+    }
+
+    #[test]
+    fn exact_pads_and_crops_to_the_size() {
+        let cases = [
+            ((96, 96), (96, 96)),
+            ((95, 96), (96, 96)),
+            ((72, 72), (96, 96)),
+            ((100, 90), (96, 96)),
+            ((64, 64), (60, 60)),
+            ((1, 1), (60, 64)),
+        ];
+
+        for ((width, height), size) in cases {
+            let exact = exact(image(width, height), size, RED);
+
+            assert_eq!(
+                exact.dimensions(),
+                (size.0 as u32, size.1 as u32),
+                "{}x{}",
+                width,
+                height
+            );
+        }
+    }
+
+    #[test]
+    fn exact_centers_without_resizing() {
+        let exact = exact(image(72, 72), (96, 96), RED).to_rgb8();
+
+        assert_eq!(*exact.get_pixel(11, 48), RED);
+        assert_eq!(*exact.get_pixel(12, 48), Rgb([255, 255, 255]));
+        assert_eq!(*exact.get_pixel(83, 48), Rgb([255, 255, 255]));
+        assert_eq!(*exact.get_pixel(84, 48), RED);
+    }
+
+    #[test]
+    fn fit_gives_the_size_in_every_mode() {
+        let modes = [ScaleMode::Letterbox, ScaleMode::Fill, ScaleMode::Center];
+        let sources = [(96, 96), (95, 96), (72, 72), (200, 100), (100, 200), (500, 500)];
+
+        for mode in modes {
+            for (width, height) in sources {
+                let fitted = fit(
+                    image(width, height),
+                    (96, 96),
+                    mode,
+                    RED,
+                    ResizeFilter::default(),
+                );
+
+                assert_eq!(
+                    fitted.dimensions(),
+                    (96, 96),
+                    "{}x{} {:?}",
+                    width,
+                    height,
+                    mode
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn fit_pads_small_differences_instead_of_resizing() {
+        // One pixel short, padded with one background column instead of stretched
+        let fitted = fit(
+            image(95, 96),
+            (96, 96),
+            ScaleMode::Letterbox,
+            RED,
+            ResizeFilter::default(),
+        )
+        .to_rgb8();
+
+        assert_eq!(*fitted.get_pixel(0, 0), Rgb([255, 255, 255]));
+        assert_eq!(*fitted.get_pixel(95, 0), RED);
+    }
+
+    #[test]
+    fn center_keeps_small_icons_at_their_size() {
+        let centered = fit(
+            image(72, 72),
+            (96, 96),
+            ScaleMode::Center,
+            RED,
+            ResizeFilter::default(),
+        )
+        .to_rgb8();
+        let letterboxed = fit(
+            image(72, 72),
+            (96, 96),
+            ScaleMode::Letterbox,
+            RED,
+            ResizeFilter::default(),
+        )
+        .to_rgb8();
+
+        assert_eq!(*centered.get_pixel(0, 0), RED);
+        assert_eq!(*letterboxed.get_pixel(0, 0), Rgb([255, 255, 255]));
+    }
+}
@@ -0,0 +1,101 @@
+use std::{sync::LazyLock, time::Instant};
+
+use tokio::sync::{Mutex, MutexGuard};
+
+use crate::stats;
+
+/// Jobs a device writes per turn while other devices are connected
+const DEFAULT_SLICE: usize = 6;
+
+/// Jobs a device writes per turn, `OPENDECK_N1_UPLOAD_SLICE` at startup, 0 turns scheduling off
+///
+/// Decks on one hub share its bandwidth. A full refresh of one deck used to write all its keys in
+/// one go, delaying the input reports of the others until it was done.
+pub static SLICE: LazyLock<usize> = LazyLock::new(|| {
+    let Ok(value) = std::env::var("OPENDECK_N1_UPLOAD_SLICE") else {
+        return DEFAULT_SLICE;
+    };
+
+    value.parse().unwrap_or_else(|_| {
+        log::warn!(
+            "Invalid OPENDECK_N1_UPLOAD_SLICE {}, using {}",
+            value,
+            DEFAULT_SLICE
+        );
+        DEFAULT_SLICE
+    })
+});
+
+/// Held by the device currently writing images, tokio hands it out in the order it was asked for
+static TURN: Mutex<()> = Mutex::const_new(());
+
+/// Returns most jobs the device may write in one turn, `limit` when it doesn't have to share
+pub fn slice(devices: usize, limit: usize) -> usize {
+    if *SLICE == 0 || devices < 2 {
+        return limit;
+    }
+
+    SLICE.min(limit)
+}
+
+/// Waits until the device may write images, devices that asked earlier go first
+///
+/// Every device gets a turn of at most one [slice] after another, so refreshes of several devices
+/// interleave instead of one starving the others. The wait is recorded as `upload_wait` in the
+/// stats of the device. `None` without waiting while scheduling is off.
+pub async fn turn(id: &str) -> Option<MutexGuard<'static, ()>> {
+    if *SLICE == 0 {
+        return None;
+    }
+
+    let started = Instant::now();
+    let turn = TURN.lock().await;
+
+    stats::record_upload_wait(id, started.elapsed()).await;
+
+    Some(turn)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{sync::Arc, time::Duration};
+
+    use super::*;
+
+    #[test]
+    fn lone_device_keeps_its_batch_limit() {
+        assert_eq!(slice(0, 32), 32);
+        assert_eq!(slice(1, 32), 32);
+        assert_eq!(slice(2, 32), DEFAULT_SLICE);
+        assert_eq!(slice(5, 4), 4);
+    }
+
+    #[tokio::test]
+    async fn turns_are_handed_out_in_request_order() {
+        let order = Arc::new(Mutex::new(vec![]));
+        let first = turn("N1-turn-first").await;
+
+        let mut waiting = vec![];
+
+        for id in ["N1-turn-second", "N1-turn-third"] {
+            let order = order.clone();
+
+            waiting.push(tokio::spawn(async move {
+                let _turn = turn(id).await;
+                order.lock().await.push(id);
+            }));
+
+            // Lets the task queue up before the next one asks
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+
+        drop(first);
+
+        for task in waiting {
+            task.await.unwrap();
+        }
+
+        assert_eq!(*order.lock().await, ["N1-turn-second", "N1-turn-third"]);
+        assert_eq!(stats::get("N1-turn-second").await.upload_wait.count(), 1);
+    }
+}
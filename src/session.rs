@@ -0,0 +1,87 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use futures_lite::StreamExt;
+use tokio_util::sync::CancellationToken;
+use zbus::{Connection, Proxy, zvariant::OwnedObjectPath};
+
+use crate::{DEVICES, watcher};
+
+/// Sessions are assumed active until logind says otherwise, so a missing logind changes nothing
+static ACTIVE: AtomicBool = AtomicBool::new(true);
+
+/// Returns true while the session of the plugin is the active one on its seat
+pub fn is_active() -> bool {
+    ACTIVE.load(Ordering::Acquire)
+}
+
+/// Follows whether the session is in the foreground, releasing devices to the other session
+/// on fast user switching and claiming them back when switched to
+pub async fn session_task(token: CancellationToken) {
+    tokio::select! {
+        result = watch_session() => {
+            if let Err(e) = result {
+                log::error!("Unable to follow session state: {}", e);
+            }
+        },
+        _ = token.cancelled() => {},
+    }
+
+    log::info!("Session task finished");
+}
+
+async fn watch_session() -> zbus::Result<()> {
+    let connection = Connection::system().await?;
+
+    let manager = Proxy::new(
+        &connection,
+        "org.freedesktop.login1",
+        "/org/freedesktop/login1",
+        "org.freedesktop.login1.Manager",
+    )
+    .await?;
+
+    // "auto" resolves to the session of the plugin process
+    let path: OwnedObjectPath = manager.call("GetSession", &("auto",)).await?;
+
+    log::info!("Following activity of session {}", path.as_str());
+
+    let session = Proxy::new(
+        &connection,
+        "org.freedesktop.login1",
+        path,
+        "org.freedesktop.login1.Session",
+    )
+    .await?;
+
+    let mut changes = session.receive_property_changed::<bool>("Active").await;
+
+    set_active(session.get_property("Active").await?).await;
+
+    while let Some(change) = changes.next().await {
+        set_active(change.get().await?).await;
+    }
+
+    Ok(())
+}
+
+async fn set_active(active: bool) {
+    if ACTIVE.swap(active, Ordering::AcqRel) == active {
+        return;
+    }
+
+    if active {
+        log::info!("Session became active, claiming devices");
+
+        if let Err(e) = watcher::claim_devices().await {
+            log::error!("Failed to claim devices: {}", e);
+        }
+    } else {
+        log::info!("Session became inactive, releasing devices");
+
+        let ids: Vec<String> = DEVICES.read().await.keys().cloned().collect();
+
+        for id in ids {
+            watcher::release_device(&id).await;
+        }
+    }
+}
@@ -0,0 +1,930 @@
+use std::{
+    collections::HashMap,
+    sync::{
+        LazyLock,
+        atomic::{AtomicBool, Ordering},
+    },
+    time::Duration,
+};
+
+use image::Rgb;
+use serde::{Deserialize, Serialize};
+use tokio::sync::{Notify, RwLock};
+
+use crate::{
+    dnd::DndWindow,
+    mappings::{ColorOrder, Kind, MappingProfile, Presentation, Quirks},
+    mirror::Mirror,
+    scale::{self, ResizeFilter, ScaleMode},
+    theme::Theme,
+    tone::{Tint, Tone},
+    twist::{TwistCurve, TwistSteps},
+};
+
+/// Plugin settings, persisted through OpenDeck's global settings store
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Settings {
+    /// Per-device settings, keyed by device id
+    pub devices: HashMap<String, DeviceSettings>,
+    /// Device ids of older plugin versions, mapped to the current id of the same device
+    pub legacy_ids: HashMap<String, String>,
+    /// Move settings stored under a legacy id to the current id, instead of using the legacy id
+    pub migrate_legacy_ids: bool,
+}
+
+/// Settings that are remembered for a specific device
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct DeviceSettings {
+    /// Last brightness that was applied to the device
+    pub brightness: Option<u8>,
+    /// Brightness (percent) requests are capped at, the `max_brightness` quirk applies either way
+    pub max_brightness: Option<u8>,
+    /// Workarounds found for this device, on top of the ones of its kind
+    pub quirks: Quirks,
+    /// Key mapping profile
+    pub profile: MappingProfile,
+    /// Drop reports that are exact repeats of the previous one (some units send everything twice)
+    pub dedupe_reports: bool,
+    /// "Do not disturb" windows
+    pub dnd: Vec<DndWindow>,
+    /// Icon set shown when there is no dark window
+    pub theme: Theme,
+    /// Local time window during which the dark icon set is shown, overrides `theme`
+    pub dark_window: Option<DndWindow>,
+    /// Overrides channel order of the device kind, for debugging new clones
+    pub color_order: Option<ColorOrder>,
+    /// How non-square images are fitted to keys, `OPENDECK_N1_SCALE_MODE` or letterbox when unset
+    pub scale_mode: Option<ScaleMode>,
+    /// How the device is laid out in OpenDeck
+    pub presentation: Presentation,
+    /// Color transparent parts of images and letterbox bars are filled with, black when unset
+    pub background: Option<[u8; 3]>,
+    /// Devices sharing a group name are kept at the same brightness
+    pub group: Option<String>,
+    /// How fast dial spins are scaled into larger deltas
+    pub twist_curve: TwistCurve,
+    /// Thresholds of the `steps` twist curve
+    pub twist_steps: TwistSteps,
+    /// Twisting the held dial turns an encoder of its own, the press alone is sent on release
+    pub hold_rotate: bool,
+    /// Milliseconds the dial has to be held for a long press, 0 turns long presses off
+    pub dial_long_press_ms: u64,
+    /// Milliseconds the dial may be held without twisting before its release is sent anyway, 0 never
+    pub dial_release_ms: u64,
+    /// Keys (OpenDeck positions) that lock or unlock the device when pressed together, none disables
+    pub lock_chord: Vec<u8>,
+    /// Tone curve of key images, default of the device kind when unset
+    pub tone: Option<Tone>,
+    /// Night mode tint applied after the tone curve, off when unset
+    pub night: Option<Tint>,
+    /// Folder of pre-encoded key JPEGs (`0.jpg`, `1.jpg`, ...) shown on keys OpenDeck sent nothing for
+    pub asset_dir: Option<String>,
+    /// LCD segments showing keys of another device
+    pub mirror: Option<Mirror>,
+    /// Filter key images are resized with, startup default when unset
+    pub resize_filter: Option<ResizeFilter>,
+    /// Quality (0-100) of JPEGs sent to the device, [DEFAULT_JPEG_QUALITY] when unset
+    pub jpeg_quality: Option<u8>,
+}
+
+impl Default for DeviceSettings {
+    fn default() -> Self {
+        Self {
+            brightness: None,
+            max_brightness: None,
+            quirks: Quirks::default(),
+            profile: MappingProfile::default(),
+            dedupe_reports: true,
+            dnd: vec![],
+            theme: Theme::default(),
+            dark_window: None,
+            color_order: None,
+            scale_mode: None,
+            presentation: Presentation::default(),
+            background: None,
+            group: None,
+            twist_curve: TwistCurve::default(),
+            twist_steps: TwistSteps::default(),
+            hold_rotate: false,
+            dial_long_press_ms: 0,
+            dial_release_ms: DEFAULT_DIAL_RELEASE_MS,
+            lock_chord: vec![],
+            tone: None,
+            night: None,
+            asset_dir: None,
+            mirror: None,
+            resize_filter: None,
+            jpeg_quality: None,
+        }
+    }
+}
+
+/// Device-scoped settings as OpenDeck (or the control socket, until OpenDeck sends them) hands them over
+///
+/// Every field is optional, missing ones leave the stored setting alone and unknown ones are ignored
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct DevicePayload {
+    /// Key mapping profile
+    pub orientation: Option<MappingProfile>,
+    /// Brightness used until one was set for the device
+    pub brightness: Option<u8>,
+    /// How the LCDs and the dial are presented as encoders
+    pub encoders: Option<Presentation>,
+}
+
+/// Settings a [DevicePayload] actually changed, each needs its own follow-up on the device
+#[derive(Debug, Clone, Copy, Default)]
+pub struct PayloadChanges {
+    pub profile: bool,
+    pub brightness: bool,
+    pub presentation: bool,
+}
+
+/// Brightness used when nothing was persisted for the device yet
+pub const DEFAULT_BRIGHTNESS: u8 = 50;
+
+/// JPEG quality of devices that have none set, the one mirajazz encodes with
+pub const DEFAULT_JPEG_QUALITY: u8 = 90;
+
+/// Time a dial is held without twisting before it counts as having lost its release report
+pub const DEFAULT_DIAL_RELEASE_MS: u64 = 1500;
+
+pub static SETTINGS: LazyLock<RwLock<Settings>> = LazyLock::new(|| RwLock::new(Settings::default()));
+
+static LOADED: AtomicBool = AtomicBool::new(false);
+static LOADED_NOTIFY: Notify = Notify::const_new();
+static CHANGED: Notify = Notify::const_new();
+
+/// Replaces current settings with the ones received from OpenDeck
+pub async fn load(value: serde_json::Value) {
+    let settings = match serde_json::from_value::<Settings>(value) {
+        Ok(settings) => settings,
+        Err(e) => {
+            log::error!("Failed to parse global settings, using defaults: {}", e);
+            Settings::default()
+        }
+    };
+
+    log::debug!("Loaded settings: {:#?}", settings);
+
+    *SETTINGS.write().await = settings;
+
+    LOADED.store(true, Ordering::Release);
+    LOADED_NOTIFY.notify_waiters();
+    CHANGED.notify_waiters();
+}
+
+/// Resolves next time settings are loaded or changed
+pub async fn changed() {
+    CHANGED.notified().await
+}
+
+/// Waits until settings are received from OpenDeck, giving up after `timeout`
+pub async fn wait_loaded(timeout: Duration) {
+    let notified = LOADED_NOTIFY.notified();
+
+    if LOADED.load(Ordering::Acquire) {
+        return;
+    }
+
+    if tokio::time::timeout(timeout, notified).await.is_err() {
+        log::warn!("Global settings were not received in time, using defaults");
+    }
+}
+
+/// Sends current settings to OpenDeck for storage
+pub async fn persist() {
+    CHANGED.notify_waiters();
+
+    let settings = SETTINGS.read().await.clone();
+
+    if let Err(e) = openaction::set_global_settings(settings).await {
+        log::error!("Failed to persist settings: {}", e);
+    }
+}
+
+/// Returns id the device is used under, given its current id and the ids it had before
+///
+/// OpenDeck binds profiles to device ids, so a changed id scheme would leave them behind. While
+/// settings are only stored under a legacy id (one of `legacy` or listed in `legacy_ids`), the
+/// device keeps using that id and its profiles keep working. With `migrate_legacy_ids` the settings
+/// move to the current id instead, once. Either way the pair is recorded in `legacy_ids`.
+pub async fn resolve_device_id(current: &str, legacy: &[String]) -> String {
+    let mut settings = SETTINGS.write().await;
+
+    if settings.devices.contains_key(current) {
+        return current.to_string();
+    }
+
+    let mut candidates = legacy.to_vec();
+    candidates.extend(
+        settings
+            .legacy_ids
+            .iter()
+            .filter(|(_, id)| *id == current)
+            .map(|(legacy, _)| legacy.clone()),
+    );
+
+    let Some(legacy) = candidates
+        .into_iter()
+        .find(|legacy| settings.devices.contains_key(legacy))
+    else {
+        return current.to_string();
+    };
+
+    let recorded = settings
+        .legacy_ids
+        .insert(legacy.clone(), current.to_string());
+
+    if !settings.migrate_legacy_ids {
+        drop(settings);
+
+        if recorded.as_deref() != Some(current) {
+            log::info!(
+                "Settings of {} are stored under its legacy id {}, using that id",
+                current,
+                legacy
+            );
+            persist().await;
+        }
+
+        return legacy;
+    }
+
+    if let Some(device) = settings.devices.remove(&legacy) {
+        settings.devices.insert(current.to_string(), device);
+    }
+
+    drop(settings);
+
+    log::info!(
+        "Migrated settings of {} from its legacy id {}",
+        current,
+        legacy
+    );
+    persist().await;
+
+    current.to_string()
+}
+
+/// Maps a legacy device id to the current id of the device, or forgets it with `None`
+pub async fn store_legacy_id(legacy: &str, current: Option<String>) {
+    let mut settings = SETTINGS.write().await;
+
+    match current {
+        Some(current) => settings.legacy_ids.insert(legacy.to_string(), current),
+        None => settings.legacy_ids.remove(legacy),
+    };
+
+    drop(settings);
+
+    persist().await;
+}
+
+/// Chooses between using legacy ids (false) and moving their settings to current ids (true)
+pub async fn store_migrate_legacy_ids(migrate: bool) {
+    SETTINGS.write().await.migrate_legacy_ids = migrate;
+
+    persist().await;
+}
+
+/// Returns brightness that should be shown on the device
+pub async fn brightness_for(id: &str) -> u8 {
+    SETTINGS
+        .read()
+        .await
+        .devices
+        .get(id)
+        .and_then(|device| device.brightness)
+        .unwrap_or(DEFAULT_BRIGHTNESS)
+}
+
+/// Remembers brightness for the device, returning false if it was already stored
+pub async fn store_brightness(id: &str, brightness: u8) -> bool {
+    let mut settings = SETTINGS.write().await;
+    let device = settings.devices.entry(id.to_string()).or_default();
+
+    if device.brightness == Some(brightness) {
+        return false;
+    }
+
+    device.brightness = Some(brightness);
+    drop(settings);
+
+    persist().await;
+
+    true
+}
+
+/// Returns brightness limit set for the device, `None` if it has none
+pub async fn max_brightness_for(id: &str) -> Option<u8> {
+    SETTINGS
+        .read()
+        .await
+        .devices
+        .get(id)
+        .and_then(|device| device.max_brightness)
+}
+
+/// Changes brightness limit of the device (`None` removes it) and persists it
+pub async fn store_max_brightness(id: &str, max_brightness: Option<u8>) {
+    SETTINGS
+        .write()
+        .await
+        .devices
+        .entry(id.to_string())
+        .or_default()
+        .max_brightness = max_brightness;
+
+    persist().await;
+}
+
+/// Returns workarounds stored for the device itself, without those of its kind
+pub async fn quirks_for(id: &str) -> Quirks {
+    SETTINGS
+        .read()
+        .await
+        .devices
+        .get(id)
+        .map(|device| device.quirks)
+        .unwrap_or_default()
+}
+
+/// Changes workarounds of the device and persists them
+pub async fn store_quirks(id: &str, quirks: Quirks) {
+    SETTINGS
+        .write()
+        .await
+        .devices
+        .entry(id.to_string())
+        .or_default()
+        .quirks = quirks;
+
+    persist().await;
+}
+
+/// Stores settings of a device-scoped payload at once and persists them if anything changed
+///
+/// The payload's brightness only applies to devices that have none stored yet, a brightness set
+/// through OpenDeck's slider always wins over it
+pub async fn store_payload(id: &str, payload: DevicePayload) -> PayloadChanges {
+    let mut changes = PayloadChanges::default();
+
+    let mut settings = SETTINGS.write().await;
+    let device = settings.devices.entry(id.to_string()).or_default();
+
+    if let Some(profile) = payload.orientation
+        && device.profile != profile
+    {
+        device.profile = profile;
+        changes.profile = true;
+    }
+
+    if let Some(brightness) = payload.brightness
+        && device.brightness.is_none()
+    {
+        device.brightness = Some(brightness.min(100));
+        changes.brightness = true;
+    }
+
+    if let Some(presentation) = payload.encoders
+        && device.presentation != presentation
+    {
+        device.presentation = presentation;
+        changes.presentation = true;
+    }
+
+    drop(settings);
+
+    if changes.profile || changes.brightness || changes.presentation {
+        persist().await;
+    }
+
+    changes
+}
+
+/// Returns key mapping profile of the device
+pub async fn profile_for(id: &str) -> MappingProfile {
+    SETTINGS
+        .read()
+        .await
+        .devices
+        .get(id)
+        .map(|device| device.profile)
+        .unwrap_or_default()
+}
+
+/// Changes key mapping profile of the device and persists it
+pub async fn store_profile(id: &str, profile: MappingProfile) {
+    SETTINGS
+        .write()
+        .await
+        .devices
+        .entry(id.to_string())
+        .or_default()
+        .profile = profile;
+
+    persist().await;
+}
+
+/// Returns whether duplicate reports should be dropped for the device
+pub async fn dedupe_reports_for(id: &str) -> bool {
+    SETTINGS
+        .read()
+        .await
+        .devices
+        .get(id)
+        .map(|device| device.dedupe_reports)
+        .unwrap_or(true)
+}
+
+/// Returns "do not disturb" windows of the device
+pub async fn dnd_for(id: &str) -> Vec<DndWindow> {
+    SETTINGS
+        .read()
+        .await
+        .devices
+        .get(id)
+        .map(|device| device.dnd.clone())
+        .unwrap_or_default()
+}
+
+/// Changes "do not disturb" windows of the device and persists them
+pub async fn store_dnd(id: &str, dnd: Vec<DndWindow>) {
+    SETTINGS
+        .write()
+        .await
+        .devices
+        .entry(id.to_string())
+        .or_default()
+        .dnd = dnd;
+
+    persist().await;
+}
+
+/// Returns theme of the device and the window during which dark theme is scheduled
+pub async fn theme_for(id: &str) -> (Theme, Option<DndWindow>) {
+    SETTINGS
+        .read()
+        .await
+        .devices
+        .get(id)
+        .map(|device| (device.theme, device.dark_window.clone()))
+        .unwrap_or_default()
+}
+
+/// Changes theme of the device, either fixed or scheduled, and persists it
+pub async fn store_theme(id: &str, theme: Theme, dark_window: Option<DndWindow>) {
+    let mut settings = SETTINGS.write().await;
+    let device = settings.devices.entry(id.to_string()).or_default();
+
+    device.theme = theme;
+    device.dark_window = dark_window;
+    drop(settings);
+
+    persist().await;
+}
+
+/// Returns channel order override of the device
+pub async fn color_order_for(id: &str) -> Option<ColorOrder> {
+    SETTINGS
+        .read()
+        .await
+        .devices
+        .get(id)
+        .and_then(|device| device.color_order)
+}
+
+/// Changes channel order override of the device (`None` uses the kind default) and persists it
+pub async fn store_color_order(id: &str, color_order: Option<ColorOrder>) {
+    SETTINGS
+        .write()
+        .await
+        .devices
+        .entry(id.to_string())
+        .or_default()
+        .color_order = color_order;
+
+    persist().await;
+}
+
+/// Returns how non-square images are fitted on the device
+pub async fn scale_mode_for(id: &str) -> ScaleMode {
+    SETTINGS
+        .read()
+        .await
+        .devices
+        .get(id)
+        .and_then(|device| device.scale_mode)
+        .unwrap_or(*scale::DEFAULT_MODE)
+}
+
+/// Changes scale mode of the device (`None` uses the default) and persists it
+pub async fn store_scale_mode(id: &str, scale_mode: Option<ScaleMode>) {
+    SETTINGS
+        .write()
+        .await
+        .devices
+        .entry(id.to_string())
+        .or_default()
+        .scale_mode = scale_mode;
+
+    persist().await;
+}
+
+/// Returns filter key images of the device are resized with
+pub async fn resize_filter_for(id: &str) -> ResizeFilter {
+    SETTINGS
+        .read()
+        .await
+        .devices
+        .get(id)
+        .and_then(|device| device.resize_filter)
+        .unwrap_or(*scale::DEFAULT_FILTER)
+}
+
+/// Changes resize filter of the device (`None` uses the default) and persists it
+pub async fn store_resize_filter(id: &str, resize_filter: Option<ResizeFilter>) {
+    SETTINGS
+        .write()
+        .await
+        .devices
+        .entry(id.to_string())
+        .or_default()
+        .resize_filter = resize_filter;
+
+    persist().await;
+}
+
+/// Returns quality of JPEGs sent to the device
+///
+/// Gradients on the small LCDs show compression artifacts, trading bandwidth for looks is up to the user
+pub async fn jpeg_quality_for(id: &str) -> u8 {
+    SETTINGS
+        .read()
+        .await
+        .devices
+        .get(id)
+        .and_then(|device| device.jpeg_quality)
+        .unwrap_or(DEFAULT_JPEG_QUALITY)
+}
+
+/// Changes JPEG quality of the device (`None` uses the default) and persists it
+pub async fn store_jpeg_quality(id: &str, quality: Option<u8>) {
+    SETTINGS
+        .write()
+        .await
+        .devices
+        .entry(id.to_string())
+        .or_default()
+        .jpeg_quality = quality;
+
+    persist().await;
+}
+
+/// Returns how the device is presented to OpenDeck
+///
+/// A stored presentation the kind can't be shown in falls back to the default one
+pub async fn presentation_for(id: &str, kind: &Kind) -> Presentation {
+    SETTINGS
+        .read()
+        .await
+        .devices
+        .get(id)
+        .map(|device| device.presentation)
+        .filter(|presentation| presentation.supported_by(kind))
+        .unwrap_or_default()
+}
+
+/// Changes presentation of the device and persists it, the device has to be registered again
+pub async fn store_presentation(id: &str, presentation: Presentation) {
+    SETTINGS
+        .write()
+        .await
+        .devices
+        .entry(id.to_string())
+        .or_default()
+        .presentation = presentation;
+
+    persist().await;
+}
+
+/// Returns color images are composited onto for the device
+pub async fn background_for(id: &str) -> Rgb<u8> {
+    SETTINGS
+        .read()
+        .await
+        .devices
+        .get(id)
+        .and_then(|device| device.background)
+        .map(Rgb)
+        .unwrap_or(scale::DEFAULT_BACKGROUND)
+}
+
+/// Changes background color of the device (`None` uses black) and persists it
+pub async fn store_background(id: &str, background: Option<[u8; 3]>) {
+    SETTINGS
+        .write()
+        .await
+        .devices
+        .entry(id.to_string())
+        .or_default()
+        .background = background;
+
+    persist().await;
+}
+
+/// Returns the device together with every other device of its group, sorted by id
+pub async fn group_members(id: &str) -> Vec<String> {
+    let settings = SETTINGS.read().await;
+
+    let Some(group) = settings
+        .devices
+        .get(id)
+        .and_then(|device| device.group.as_ref())
+    else {
+        return vec![id.to_string()];
+    };
+
+    let mut members: Vec<String> = settings
+        .devices
+        .iter()
+        .filter(|(_, device)| device.group.as_ref() == Some(group))
+        .map(|(member, _)| member.clone())
+        .collect();
+
+    members.sort();
+
+    members
+}
+
+/// Changes group of the device (`None` leaves its group) and persists it
+pub async fn store_group(id: &str, group: Option<String>) {
+    SETTINGS
+        .write()
+        .await
+        .devices
+        .entry(id.to_string())
+        .or_default()
+        .group = group;
+
+    persist().await;
+}
+
+/// Returns how dial spins of the device are scaled
+pub async fn twist_curve_for(id: &str) -> TwistCurve {
+    SETTINGS
+        .read()
+        .await
+        .devices
+        .get(id)
+        .map(|device| device.twist_curve)
+        .unwrap_or_default()
+}
+
+/// Changes twist curve of the device and persists it
+pub async fn store_twist_curve(id: &str, twist_curve: TwistCurve) {
+    SETTINGS
+        .write()
+        .await
+        .devices
+        .entry(id.to_string())
+        .or_default()
+        .twist_curve = twist_curve;
+
+    persist().await;
+}
+
+/// Returns thresholds of the `steps` twist curve of the device
+pub async fn twist_steps_for(id: &str) -> TwistSteps {
+    SETTINGS
+        .read()
+        .await
+        .devices
+        .get(id)
+        .map(|device| device.twist_steps.clone())
+        .unwrap_or_default()
+}
+
+/// Changes thresholds of the `steps` twist curve of the device and persists them
+pub async fn store_twist_steps(id: &str, twist_steps: TwistSteps) {
+    SETTINGS
+        .write()
+        .await
+        .devices
+        .entry(id.to_string())
+        .or_default()
+        .twist_steps = twist_steps;
+
+    persist().await;
+}
+
+/// Returns whether twists of the held dial go to an encoder of their own on the device
+pub async fn hold_rotate_for(id: &str) -> bool {
+    SETTINGS
+        .read()
+        .await
+        .devices
+        .get(id)
+        .is_some_and(|device| device.hold_rotate)
+}
+
+/// Turns the hold-and-rotate encoder of the device on or off and persists it
+pub async fn store_hold_rotate(id: &str, hold_rotate: bool) {
+    SETTINGS
+        .write()
+        .await
+        .devices
+        .entry(id.to_string())
+        .or_default()
+        .hold_rotate = hold_rotate;
+
+    persist().await;
+}
+
+/// Returns how long the dial has to be held for a long press, `None` if long presses are off
+pub async fn dial_long_press_for(id: &str) -> Option<Duration> {
+    let ms = SETTINGS
+        .read()
+        .await
+        .devices
+        .get(id)
+        .map_or(0, |device| device.dial_long_press_ms);
+
+    (ms > 0).then(|| Duration::from_millis(ms))
+}
+
+/// Sets how long the dial of the device has to be held for a long press and persists it, 0 is off
+pub async fn store_dial_long_press(id: &str, ms: u64) {
+    SETTINGS
+        .write()
+        .await
+        .devices
+        .entry(id.to_string())
+        .or_default()
+        .dial_long_press_ms = ms;
+
+    persist().await;
+}
+
+/// Returns how long the dial may be held without twisting before it's released, `None` if forever
+pub async fn dial_release_timeout_for(id: &str) -> Option<Duration> {
+    let ms = SETTINGS
+        .read()
+        .await
+        .devices
+        .get(id)
+        .map_or(DEFAULT_DIAL_RELEASE_MS, |device| device.dial_release_ms);
+
+    (ms > 0).then(|| Duration::from_millis(ms))
+}
+
+/// Sets how long the dial of the device may be held without twisting and persists it, 0 is forever
+pub async fn store_dial_release(id: &str, ms: u64) {
+    SETTINGS
+        .write()
+        .await
+        .devices
+        .entry(id.to_string())
+        .or_default()
+        .dial_release_ms = ms;
+
+    persist().await;
+}
+
+/// Returns keys that toggle the lock of the device when pressed together
+pub async fn lock_chord_for(id: &str) -> Vec<u8> {
+    SETTINGS
+        .read()
+        .await
+        .devices
+        .get(id)
+        .map(|device| device.lock_chord.clone())
+        .unwrap_or_default()
+}
+
+/// Changes lock chord of the device and persists it
+pub async fn store_lock_chord(id: &str, lock_chord: Vec<u8>) {
+    SETTINGS
+        .write()
+        .await
+        .devices
+        .entry(id.to_string())
+        .or_default()
+        .lock_chord = lock_chord;
+
+    persist().await;
+}
+
+/// Returns tone curve of the device, the kind's default when unset
+pub async fn tone_for(id: &str, kind: &Kind) -> Tone {
+    SETTINGS
+        .read()
+        .await
+        .devices
+        .get(id)
+        .and_then(|device| device.tone)
+        .unwrap_or_else(|| kind.default_tone())
+}
+
+/// Changes tone curve of the device and persists it, `None` goes back to the kind's default
+pub async fn store_tone(id: &str, tone: Option<Tone>) {
+    SETTINGS
+        .write()
+        .await
+        .devices
+        .entry(id.to_string())
+        .or_default()
+        .tone = tone;
+
+    persist().await;
+}
+
+/// Returns night mode tint of the device, `None` while night mode is off
+pub async fn night_for(id: &str) -> Option<Tint> {
+    SETTINGS
+        .read()
+        .await
+        .devices
+        .get(id)
+        .and_then(|device| device.night)
+}
+
+/// Turns night mode of the device on (with its tint) or off and persists it
+pub async fn store_night(id: &str, night: Option<Tint>) {
+    SETTINGS
+        .write()
+        .await
+        .devices
+        .entry(id.to_string())
+        .or_default()
+        .night = night;
+
+    persist().await;
+}
+
+/// Returns folder of pre-encoded key images of the device, if one was configured
+pub async fn asset_dir_for(id: &str) -> Option<String> {
+    SETTINGS
+        .read()
+        .await
+        .devices
+        .get(id)
+        .and_then(|device| device.asset_dir.clone())
+}
+
+/// Changes asset folder of the device and persists it
+pub async fn store_asset_dir(id: &str, asset_dir: Option<String>) {
+    SETTINGS
+        .write()
+        .await
+        .devices
+        .entry(id.to_string())
+        .or_default()
+        .asset_dir = asset_dir;
+
+    persist().await;
+}
+
+/// Returns keys of another device the LCD segments of the device show, if any
+pub async fn mirror_for(id: &str) -> Option<Mirror> {
+    SETTINGS
+        .read()
+        .await
+        .devices
+        .get(id)
+        .and_then(|device| device.mirror.clone())
+}
+
+/// Returns devices whose LCD segments show keys of the source
+pub async fn mirror_targets(source: &str) -> Vec<String> {
+    SETTINGS
+        .read()
+        .await
+        .devices
+        .iter()
+        .filter(|(_, device)| {
+            device
+                .mirror
+                .as_ref()
+                .is_some_and(|mirror| mirror.source == source)
+        })
+        .map(|(id, _)| id.clone())
+        .collect()
+}
+
+/// Changes mirrored keys of the device and persists it
+pub async fn store_mirror(id: &str, mirror: Option<Mirror>) {
+    SETTINGS
+        .write()
+        .await
+        .devices
+        .entry(id.to_string())
+        .or_default()
+        .mirror = mirror;
+
+    persist().await;
+}
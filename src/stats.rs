@@ -0,0 +1,267 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    fmt,
+    sync::LazyLock,
+    time::Duration,
+};
+
+use tokio::sync::Mutex;
+
+/// Upper bounds of latency histogram buckets in milliseconds, anything slower goes to the last bucket
+pub const LATENCY_BUCKETS_MS: [u64; 8] = [5, 10, 20, 50, 100, 200, 500, 1000];
+
+/// Distribution of how long an operation took
+#[derive(Debug, Clone, Default)]
+pub struct LatencyHistogram {
+    /// Samples per bucket, see [LATENCY_BUCKETS_MS]
+    pub buckets: [u64; LATENCY_BUCKETS_MS.len() + 1],
+    pub total: Duration,
+    pub max: Duration,
+}
+
+impl LatencyHistogram {
+    fn record(&mut self, elapsed: Duration) {
+        let ms = elapsed.as_millis() as u64;
+        let bucket = LATENCY_BUCKETS_MS
+            .iter()
+            .position(|bound| ms <= *bound)
+            .unwrap_or(LATENCY_BUCKETS_MS.len());
+
+        self.buckets[bucket] += 1;
+        self.total += elapsed;
+        self.max = self.max.max(elapsed);
+    }
+
+    pub fn count(&self) -> u64 {
+        self.buckets.iter().sum()
+    }
+
+    pub fn mean(&self) -> Duration {
+        match self.count() {
+            0 => Duration::ZERO,
+            count => self.total / count as u32,
+        }
+    }
+}
+
+impl fmt::Display for LatencyHistogram {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "n={} mean={:.1}ms max={:.1}ms",
+            self.count(),
+            self.mean().as_secs_f64() * 1000.0,
+            self.max.as_secs_f64() * 1000.0
+        )?;
+
+        for (index, count) in self.buckets.iter().enumerate() {
+            match LATENCY_BUCKETS_MS.get(index) {
+                Some(bound) => write!(f, " <={}ms:{}", bound, count)?,
+                None => write!(f, " >{}ms:{}", LATENCY_BUCKETS_MS.last().unwrap(), count)?,
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Counters collected for a device while the plugin is running
+#[derive(Debug, Clone, Default)]
+pub struct DeviceStats {
+    /// Reports dropped as duplicates of the previous one
+    pub duplicate_reports: u64,
+    /// Dial twists dropped because OpenDeck didn't keep up with the input queue
+    pub dropped_twists: u64,
+    /// Input reads that timed out, harmless unless they come in a burst
+    pub read_timeouts: u64,
+    /// Dial twists added to one still waiting for OpenDeck, instead of being queued separately
+    pub merged_twists: u64,
+    /// Dial twists summed into the previous one while it waited out the coalescing window
+    pub coalesced_twists: u64,
+    /// Dial releases sent because the device's release report never came
+    pub synthetic_releases: u64,
+    /// Encoder events OpenDeck didn't accept
+    pub failed_encoder_events: u64,
+    /// Time OpenDeck took to accept encoder events, failed ones included
+    pub encoder_latency: LatencyHistogram,
+    /// Image jobs whose report write to the device failed and were tried again
+    pub upload_retries: u64,
+    /// Image batches whose transfer broke off partway and was restarted from the start
+    pub restarted_transfers: u64,
+    /// Time from queueing a flash until the key was tapped, see [crate::reaction]
+    pub display_latency: LatencyHistogram,
+    /// Time image batches waited for their turn on the bus, see [crate::schedule]
+    pub upload_wait: LatencyHistogram,
+    /// Time from starting an image upload until the device was flushed, keyed by key size in pixels
+    pub upload_latency: BTreeMap<usize, LatencyHistogram>,
+}
+
+impl fmt::Display for DeviceStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "duplicate_reports={} dropped_twists={} read_timeouts={} merged_twists={} coalesced_twists={} synthetic_releases={} failed_encoder_events={} upload_retries={} restarted_transfers={}",
+            self.duplicate_reports,
+            self.dropped_twists,
+            self.read_timeouts,
+            self.merged_twists,
+            self.coalesced_twists,
+            self.synthetic_releases,
+            self.failed_encoder_events,
+            self.upload_retries,
+            self.restarted_transfers
+        )?;
+
+        if self.encoder_latency.count() > 0 {
+            write!(f, "; encoder events: {}", self.encoder_latency)?;
+        }
+
+        if self.display_latency.count() > 0 {
+            write!(f, "; display latency: {}", self.display_latency)?;
+        }
+
+        if self.upload_wait.count() > 0 {
+            write!(f, "; upload wait: {}", self.upload_wait)?;
+        }
+
+        for (size, histogram) in &self.upload_latency {
+            write!(f, "; upload {}px: {}", size, histogram)?;
+        }
+
+        Ok(())
+    }
+}
+
+static STATS: LazyLock<Mutex<HashMap<String, DeviceStats>>> =
+    LazyLock::new(|| Mutex::new(HashMap::new()));
+
+/// Returns counters of the device
+pub async fn get(id: &str) -> DeviceStats {
+    STATS.lock().await.get(id).cloned().unwrap_or_default()
+}
+
+pub async fn count_duplicate_report(id: &str) {
+    STATS
+        .lock()
+        .await
+        .entry(id.to_string())
+        .or_default()
+        .duplicate_reports += 1;
+}
+
+pub async fn count_dropped_twist(id: &str) {
+    STATS
+        .lock()
+        .await
+        .entry(id.to_string())
+        .or_default()
+        .dropped_twists += 1;
+}
+
+pub async fn count_read_timeout(id: &str) {
+    STATS
+        .lock()
+        .await
+        .entry(id.to_string())
+        .or_default()
+        .read_timeouts += 1;
+}
+
+pub async fn count_merged_twist(id: &str) {
+    STATS
+        .lock()
+        .await
+        .entry(id.to_string())
+        .or_default()
+        .merged_twists += 1;
+}
+
+pub async fn count_coalesced_twist(id: &str) {
+    STATS
+        .lock()
+        .await
+        .entry(id.to_string())
+        .or_default()
+        .coalesced_twists += 1;
+}
+
+pub async fn count_synthetic_release(id: &str) {
+    STATS
+        .lock()
+        .await
+        .entry(id.to_string())
+        .or_default()
+        .synthetic_releases += 1;
+}
+
+pub async fn count_upload_retry(id: &str) {
+    STATS
+        .lock()
+        .await
+        .entry(id.to_string())
+        .or_default()
+        .upload_retries += 1;
+}
+
+pub async fn count_restarted_transfer(id: &str) {
+    STATS
+        .lock()
+        .await
+        .entry(id.to_string())
+        .or_default()
+        .restarted_transfers += 1;
+}
+
+/// Records how long sending an encoder event to OpenDeck took and whether it was accepted
+pub async fn record_encoder_event(id: &str, elapsed: Duration, accepted: bool) {
+    let mut stats = STATS.lock().await;
+    let stats = stats.entry(id.to_string()).or_default();
+
+    stats.encoder_latency.record(elapsed);
+
+    if !accepted {
+        stats.failed_encoder_events += 1;
+    }
+}
+
+/// Records time from flashing a key until it was tapped
+pub async fn record_display_latency(id: &str, elapsed: Duration) {
+    STATS
+        .lock()
+        .await
+        .entry(id.to_string())
+        .or_default()
+        .display_latency
+        .record(elapsed);
+}
+
+/// Records how long an image batch waited for its turn
+pub async fn record_upload_wait(id: &str, elapsed: Duration) {
+    STATS
+        .lock()
+        .await
+        .entry(id.to_string())
+        .or_default()
+        .upload_wait
+        .record(elapsed);
+}
+
+/// Records how long uploading an image of `size`×`size` pixels took
+pub async fn record_upload(id: &str, size: usize, elapsed: Duration) {
+    STATS
+        .lock()
+        .await
+        .entry(id.to_string())
+        .or_default()
+        .upload_latency
+        .entry(size)
+        .or_default()
+        .record(elapsed);
+}
+
+/// Logs counters of every device seen during this run
+pub async fn log_summary() {
+    for (id, stats) in STATS.lock().await.iter() {
+        log::info!("Stats for {}: {}", id, stats);
+    }
+}
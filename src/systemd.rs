@@ -0,0 +1,87 @@
+use std::{
+    os::{
+        linux::net::SocketAddrExt,
+        unix::net::{SocketAddr, UnixDatagram},
+    },
+    time::Duration,
+};
+
+use tokio_util::sync::CancellationToken;
+
+use crate::{DEVICES, device::keepalive_stalled};
+
+/// Longest time the device list may stay locked before the plugin is considered hung
+const DEVICES_LOCK_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Sends a state like `READY=1` to the service manager, does nothing when not started by systemd
+pub fn notify(state: &str) {
+    let Ok(path) = std::env::var("NOTIFY_SOCKET") else {
+        return;
+    };
+
+    // Leading @ marks a socket in the abstract namespace
+    let address = match path.strip_prefix('@') {
+        Some(name) => SocketAddr::from_abstract_name(name),
+        None => SocketAddr::from_pathname(&path),
+    };
+
+    let result = address.and_then(|address| {
+        let socket = UnixDatagram::unbound()?;
+        socket.send_to_addr(state.as_bytes(), &address)
+    });
+
+    if let Err(e) = result {
+        log::warn!("Unable to notify systemd ({}): {}", state, e);
+    }
+}
+
+/// Returns watchdog timeout of the service, if it has one meant for this process
+pub fn watchdog_timeout() -> Option<Duration> {
+    let usec = std::env::var("WATCHDOG_USEC").ok()?.parse().ok()?;
+
+    // Unset pid means the whole service, including us
+    if let Ok(pid) = std::env::var("WATCHDOG_PID")
+        && pid.parse() != Ok(std::process::id())
+    {
+        return None;
+    }
+
+    Some(Duration::from_micros(usec))
+}
+
+/// Pings the watchdog at half the timeout while the plugin is healthy
+///
+/// Pings stop once devices stop responding, so systemd restarts the plugin. Losing the OpenDeck
+/// connection already ends the process, a dead socket doesn't need a check of its own.
+pub async fn watchdog_task(timeout: Duration, token: CancellationToken) {
+    log::info!("Pinging systemd watchdog every {:?}", timeout / 2);
+
+    loop {
+        tokio::select! {
+            _ = tokio::time::sleep(timeout / 2) => {},
+            _ = token.cancelled() => break,
+        }
+
+        match unhealthy_reason().await {
+            None => notify("WATCHDOG=1"),
+            Some(reason) => log::error!("Not pinging systemd watchdog: {}", reason),
+        }
+    }
+
+    log::info!("Watchdog task finished");
+}
+
+async fn unhealthy_reason() -> Option<&'static str> {
+    if tokio::time::timeout(DEVICES_LOCK_TIMEOUT, DEVICES.read())
+        .await
+        .is_err()
+    {
+        return Some("device list is locked");
+    }
+
+    if keepalive_stalled() {
+        return Some("devices stopped responding to keepalive");
+    }
+
+    None
+}
@@ -0,0 +1,141 @@
+use image::{DynamicImage, Rgb, RgbImage};
+use mirajazz::{error::MirajazzError, types::DeviceInput};
+
+use crate::{
+    backend::{self, DeckDevice, encode_key_image},
+    inputs::opendeck_to_device,
+    mappings::get_image_format_for_key,
+    settings::DEFAULT_JPEG_QUALITY,
+    text,
+    watcher::get_candidates,
+};
+
+/// Report prefix of input events
+const ACK: [u8; 3] = [65, 67, 75];
+
+/// Runs `opendeck-ajazz-n1 test-pattern`, for checking the key mapping without OpenDeck
+///
+/// Shows the OpenDeck index on every key of the first device found and prints input codes of
+/// presses until interrupted. Returns false if no device could be set up.
+pub async fn run() -> bool {
+    let candidate = match get_candidates().await {
+        Ok(candidates) => candidates.into_iter().next(),
+        Err(e) => {
+            eprintln!("Unable to list devices: {}", e);
+            return false;
+        }
+    };
+
+    let Some(candidate) = candidate else {
+        eprintln!("No device found");
+        return false;
+    };
+
+    let device = match backend::connect(&candidate).await {
+        Ok(device) => device,
+        Err(e) => {
+            eprintln!("Unable to connect to {}: {}", candidate.id, e);
+            return false;
+        }
+    };
+
+    if let Err(e) = show_indices(device.as_ref()).await {
+        eprintln!("Unable to show test pattern on {}: {}", candidate.id, e);
+        return false;
+    }
+
+    println!(
+        "Every key of {} shows its OpenDeck index. Press keys to see their input codes, Ctrl+C to quit.",
+        candidate.id
+    );
+
+    let reader = device.report_reader();
+    let mut processor = candidate.kind.input_processor();
+
+    loop {
+        let data = tokio::select! {
+            data = reader.read_report() => data,
+            _ = tokio::signal::ctrl_c() => break,
+        };
+
+        let data = match data {
+            Ok(data) => data,
+            Err(e) => {
+                eprintln!("Unable to read from {}: {}", candidate.id, e);
+                break;
+            }
+        };
+
+        if !data.starts_with(&ACK) || data.len() < 11 {
+            continue;
+        }
+
+        let (input, state) = (data[9], data[10]);
+
+        let meaning = match processor.process(input, state) {
+            Ok(DeviceInput::ButtonStateChange(states)) => match states.iter().position(|s| *s) {
+                Some(key) if key >= device.key_count() => {
+                    format!("face button {}", key - device.key_count())
+                }
+                Some(key) => format!("OpenDeck key {}", key),
+                None => "release".to_string(),
+            },
+            Ok(DeviceInput::EncoderStateChange(states)) => format!("dial pressed: {}", states[0]),
+            Ok(DeviceInput::EncoderTwist(values)) => format!("dial twist {}", values[0]),
+            Ok(_) => "no event".to_string(),
+            Err(_) => "unknown input".to_string(),
+        };
+
+        println!("input={:<3} state={} -> {}", input, state, meaning);
+    }
+
+    device.shutdown().await.ok();
+
+    true
+}
+
+/// Uploads the OpenDeck index to every key, in the key's own format
+async fn show_indices(device: &dyn DeckDevice) -> Result<(), MirajazzError> {
+    let kind = device.kind();
+
+    device.set_mode(3).await?;
+    device.set_brightness(50).await?;
+    device.clear_all_button_images().await?;
+
+    for position in 0..device.key_count() as u8 {
+        let format = get_image_format_for_key(&kind, position);
+
+        let data = encode_key_image(
+            format,
+            numbered(position, format.size),
+            DEFAULT_JPEG_QUALITY,
+        )
+        .await?;
+
+        device
+            .write_image(opendeck_to_device(&kind, position), &data)
+            .await?;
+    }
+
+    device.flush().await
+}
+
+/// White index on black, framed so cropped or shifted images stand out
+fn numbered(position: u8, size: (usize, usize)) -> DynamicImage {
+    let (width, height) = (size.0 as u32, size.1 as u32);
+    let mut image = RgbImage::from_pixel(width, height, Rgb([0, 0, 0]));
+
+    for x in 0..width {
+        image.put_pixel(x, 0, Rgb([255, 0, 0]));
+        image.put_pixel(x, height - 1, Rgb([255, 0, 0]));
+    }
+
+    for y in 0..height {
+        image.put_pixel(0, y, Rgb([255, 0, 0]));
+        image.put_pixel(width - 1, y, Rgb([255, 0, 0]));
+    }
+
+    text::draw_centered(&mut image, &position.to_string(), 0.5, Rgb([255, 255, 255]));
+
+    DynamicImage::ImageRgb8(image)
+}
@@ -0,0 +1,231 @@
+use image::{Rgb, RgbImage};
+
+/// Glyph size in font pixels, before scaling
+pub const GLYPH_WIDTH: u32 = 5;
+pub const GLYPH_HEIGHT: u32 = 7;
+
+/// Gap between glyphs in font pixels
+const SPACING: u32 = 1;
+
+/// Returns rows of the 5×7 glyph, most significant of the low 5 bits is the leftmost pixel
+///
+/// Lowercase letters are drawn as uppercase, anything without a glyph as `?`
+fn glyph(c: char) -> [u8; 7] {
+    match c.to_ascii_uppercase() {
+        '0' => [0x0e, 0x11, 0x13, 0x15, 0x19, 0x11, 0x0e],
+        '1' => [0x04, 0x0c, 0x04, 0x04, 0x04, 0x04, 0x0e],
+        '2' => [0x0e, 0x11, 0x01, 0x02, 0x04, 0x08, 0x1f],
+        '3' => [0x1f, 0x02, 0x04, 0x02, 0x01, 0x11, 0x0e],
+        '4' => [0x02, 0x06, 0x0a, 0x12, 0x1f, 0x02, 0x02],
+        '5' => [0x1f, 0x10, 0x1e, 0x01, 0x01, 0x11, 0x0e],
+        '6' => [0x06, 0x08, 0x10, 0x1e, 0x11, 0x11, 0x0e],
+        '7' => [0x1f, 0x01, 0x02, 0x04, 0x08, 0x08, 0x08],
+        '8' => [0x0e, 0x11, 0x11, 0x0e, 0x11, 0x11, 0x0e],
+        '9' => [0x0e, 0x11, 0x11, 0x0f, 0x01, 0x02, 0x0c],
+        'A' => [0x0e, 0x11, 0x11, 0x1f, 0x11, 0x11, 0x11],
+        'B' => [0x1e, 0x11, 0x11, 0x1e, 0x11, 0x11, 0x1e],
+        'C' => [0x0e, 0x11, 0x10, 0x10, 0x10, 0x11, 0x0e],
+        'D' => [0x1c, 0x12, 0x11, 0x11, 0x11, 0x12, 0x1c],
+        'E' => [0x1f, 0x10, 0x10, 0x1e, 0x10, 0x10, 0x1f],
+        'F' => [0x1f, 0x10, 0x10, 0x1e, 0x10, 0x10, 0x10],
+        'G' => [0x0e, 0x11, 0x10, 0x17, 0x11, 0x11, 0x0f],
+        'H' => [0x11, 0x11, 0x11, 0x1f, 0x11, 0x11, 0x11],
+        'I' => [0x0e, 0x04, 0x04, 0x04, 0x04, 0x04, 0x0e],
+        'J' => [0x07, 0x02, 0x02, 0x02, 0x02, 0x12, 0x0c],
+        'K' => [0x11, 0x12, 0x14, 0x18, 0x14, 0x12, 0x11],
+        'L' => [0x10, 0x10, 0x10, 0x10, 0x10, 0x10, 0x1f],
+        'M' => [0x11, 0x1b, 0x15, 0x15, 0x11, 0x11, 0x11],
+        'N' => [0x11, 0x11, 0x19, 0x15, 0x13, 0x11, 0x11],
+        'O' => [0x0e, 0x11, 0x11, 0x11, 0x11, 0x11, 0x0e],
+        'P' => [0x1e, 0x11, 0x11, 0x1e, 0x10, 0x10, 0x10],
+        'Q' => [0x0e, 0x11, 0x11, 0x11, 0x15, 0x12, 0x0d],
+        'R' => [0x1e, 0x11, 0x11, 0x1e, 0x14, 0x12, 0x11],
+        'S' => [0x0f, 0x10, 0x10, 0x0e, 0x01, 0x01, 0x1e],
+        'T' => [0x1f, 0x04, 0x04, 0x04, 0x04, 0x04, 0x04],
+        'U' => [0x11, 0x11, 0x11, 0x11, 0x11, 0x11, 0x0e],
+        'V' => [0x11, 0x11, 0x11, 0x11, 0x11, 0x0a, 0x04],
+        'W' => [0x11, 0x11, 0x11, 0x15, 0x15, 0x15, 0x0a],
+        'X' => [0x11, 0x11, 0x0a, 0x04, 0x0a, 0x11, 0x11],
+        'Y' => [0x11, 0x11, 0x11, 0x0a, 0x04, 0x04, 0x04],
+        'Z' => [0x1f, 0x01, 0x02, 0x04, 0x08, 0x10, 0x1f],
+        ' ' => [0x00; 7],
+        '-' => [0x00, 0x00, 0x00, 0x1f, 0x00, 0x00, 0x00],
+        '.' => [0x00, 0x00, 0x00, 0x00, 0x00, 0x0c, 0x0c],
+        ':' => [0x00, 0x0c, 0x0c, 0x00, 0x0c, 0x0c, 0x00],
+        '/' => [0x00, 0x01, 0x02, 0x04, 0x08, 0x10, 0x00],
+        '!' => [0x04, 0x04, 0x04, 0x04, 0x04, 0x00, 0x04],
+        _ => [0x0e, 0x11, 0x01, 0x02, 0x04, 0x00, 0x04],
+    }
+}
+
+/// Returns size of the text in image pixels, single line
+pub fn text_size(text: &str, scale: u32) -> (u32, u32) {
+    let chars = text.chars().count() as u32;
+
+    if chars == 0 {
+        return (0, 0);
+    }
+
+    (
+        (chars * (GLYPH_WIDTH + SPACING) - SPACING) * scale,
+        GLYPH_HEIGHT * scale,
+    )
+}
+
+/// Draws text with its top left corner at (x, y), every font pixel as a `scale`×`scale` square
+///
+/// Pixels falling outside the image are skipped
+pub fn draw_text(image: &mut RgbImage, text: &str, x: u32, y: u32, scale: u32, color: Rgb<u8>) {
+    for (index, c) in text.chars().enumerate() {
+        let left = x + index as u32 * (GLYPH_WIDTH + SPACING) * scale;
+
+        for (row, bits) in glyph(c).iter().enumerate() {
+            for col in 0..GLYPH_WIDTH {
+                if bits & (1 << (GLYPH_WIDTH - 1 - col)) == 0 {
+                    continue;
+                }
+
+                for dy in 0..scale {
+                    for dx in 0..scale {
+                        let (px, py) = (left + col * scale + dx, y + row as u32 * scale + dy);
+
+                        if px < image.width() && py < image.height() {
+                            image.put_pixel(px, py, color);
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Draws text centered in the image, at the largest scale that fits `fraction` of its height and the width
+pub fn draw_centered(image: &mut RgbImage, text: &str, fraction: f32, color: Rgb<u8>) {
+    let (width, height) = text_size(text, 1);
+
+    if width == 0 {
+        return;
+    }
+
+    let scale = ((image.height() as f32 * fraction) as u32 / height)
+        .min(image.width() / width)
+        .max(1);
+
+    let (width, height) = text_size(text, scale);
+
+    draw_text(
+        image,
+        text,
+        image.width().saturating_sub(width) / 2,
+        image.height().saturating_sub(height) / 2,
+        scale,
+        color,
+    );
+}
+
+/// Splits text into lines of at most `width` characters, breaking between words where possible
+///
+/// Words longer than a line are cut. If more than `max_lines` lines are needed, the last one that
+/// fits ends in `..`
+pub fn wrap(text: &str, width: usize, max_lines: usize) -> Vec<String> {
+    if width == 0 || max_lines == 0 {
+        return vec![];
+    }
+
+    let mut lines = vec![];
+    let mut line = String::new();
+
+    for word in text.split_whitespace() {
+        let chars: Vec<char> = word.chars().collect();
+
+        for chunk in chars.chunks(width) {
+            let chunk: String = chunk.iter().collect();
+            let length = line.chars().count();
+
+            if length == 0 {
+                line = chunk;
+            } else if length + 1 + chunk.chars().count() <= width {
+                line.push(' ');
+                line.push_str(&chunk);
+            } else {
+                lines.push(std::mem::replace(&mut line, chunk));
+            }
+        }
+    }
+
+    if !line.is_empty() {
+        lines.push(line);
+    }
+
+    if lines.len() > max_lines {
+        lines.truncate(max_lines);
+
+        let last = lines.last_mut().expect("max_lines is not zero");
+        let kept: String = last.chars().take(width.saturating_sub(2)).collect();
+        *last = format!("{}..", kept.trim_end());
+    }
+
+    lines
+}
+
+/// Draws text wrapped into lines and centered, at the largest scale that fits the image
+///
+/// Scale is capped so a short word isn't blown up to fill the key. Text that doesn't fit at
+/// scale 1 is cut off, see [wrap].
+pub fn draw_wrapped(image: &mut RgbImage, text: &str, color: Rgb<u8>) {
+    // Keeps glyphs off the edges, which some panels crop a little
+    let margin = image.width() / 16;
+    let (width, height) = (
+        image.width().saturating_sub(margin * 2),
+        image.height().saturating_sub(margin * 2),
+    );
+
+    // Lines are one font pixel apart
+    let line_height = GLYPH_HEIGHT + SPACING;
+    let max_scale = (height / (GLYPH_HEIGHT * 3)).max(1);
+
+    let layout = |scale: u32| {
+        let columns = (width + SPACING * scale) / ((GLYPH_WIDTH + SPACING) * scale);
+        let rows = (height + SPACING * scale) / (line_height * scale);
+
+        (columns as usize, rows as usize)
+    };
+
+    // Smaller text beats words cut in half, unless even the smallest scale has to cut them
+    let longest_word = text
+        .split_whitespace()
+        .map(|word| word.chars().count())
+        .max()
+        .unwrap_or(0);
+
+    let (scale, lines) = (1..=max_scale)
+        .rev()
+        .find_map(|scale| {
+            let (columns, rows) = layout(scale);
+            let lines = wrap(text, columns, usize::MAX);
+
+            (lines.len() <= rows && (longest_word <= columns || scale == 1))
+                .then_some((scale, lines))
+        })
+        .unwrap_or_else(|| {
+            let (columns, rows) = layout(1);
+
+            (1, wrap(text, columns, rows))
+        });
+
+    let block_height = (lines.len() as u32 * line_height).saturating_sub(SPACING) * scale;
+    let top = image.height().saturating_sub(block_height) / 2;
+
+    for (index, line) in lines.iter().enumerate() {
+        let (line_width, _) = text_size(line, scale);
+
+        draw_text(
+            image,
+            line,
+            image.width().saturating_sub(line_width) / 2,
+            top + index as u32 * line_height * scale,
+            scale,
+            color,
+        );
+    }
+}
@@ -0,0 +1,136 @@
+use std::{collections::HashMap, sync::LazyLock, time::Duration};
+
+use chrono::{Local, Timelike};
+use image::{DynamicImage, Rgb};
+use serde::{Deserialize, Serialize};
+use tokio::sync::Mutex;
+use tokio_util::sync::CancellationToken;
+
+use crate::{device::redraw_from_cache, settings};
+
+/// How often scheduled theme switches are checked
+const CHECK_INTERVAL: Duration = Duration::from_secs(30);
+
+/// Icon set shown on the device
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Theme {
+    /// Images as sent by OpenDeck
+    #[default]
+    Light,
+    /// Dark variants, either set explicitly per key or derived from the light image
+    Dark,
+}
+
+impl Theme {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "light" => Some(Self::Light),
+            "dark" => Some(Self::Dark),
+            _ => None,
+        }
+    }
+}
+
+/// Dark variants of key images, keyed by device id and OpenDeck position
+static DARK_IMAGES: LazyLock<Mutex<HashMap<(String, u8), DynamicImage>>> =
+    LazyLock::new(|| Mutex::new(HashMap::new()));
+
+/// Sets or removes (with `None`) the dark variant of the key image
+pub async fn set_dark_image(id: &str, position: u8, image: Option<DynamicImage>) {
+    let mut images = DARK_IMAGES.lock().await;
+
+    match image {
+        Some(image) => images.insert((id.to_string(), position), image),
+        None => images.remove(&(id.to_string(), position)),
+    };
+}
+
+/// Returns theme the device should show right now
+pub async fn current(id: &str) -> Theme {
+    let (theme, dark_window) = settings::theme_for(id).await;
+
+    match dark_window {
+        Some(window) => {
+            let now = Local::now();
+
+            if window.contains(now.hour() * 60 + now.minute()) {
+                Theme::Dark
+            } else {
+                Theme::Light
+            }
+        }
+        None => theme,
+    }
+}
+
+/// What the current theme does to a key image, looked up before rendering
+pub enum KeyTheme {
+    /// Image is shown as sent
+    Light,
+    /// Explicit dark variant replaces the image
+    DarkImage(DynamicImage),
+    /// Dark variant is derived from the image
+    Derived,
+}
+
+impl KeyTheme {
+    /// Returns image to show on the key, CPU heavy for derived variants
+    pub fn apply(self, image: DynamicImage) -> DynamicImage {
+        match self {
+            Self::Light => image,
+            Self::DarkImage(dark) => dark,
+            Self::Derived => derive_dark(image),
+        }
+    }
+}
+
+/// Returns what the current theme does to the key image
+pub async fn for_key(id: &str, position: u8) -> KeyTheme {
+    if current(id).await == Theme::Light {
+        return KeyTheme::Light;
+    }
+
+    match DARK_IMAGES.lock().await.get(&(id.to_string(), position)) {
+        Some(dark) => KeyTheme::DarkImage(dark.clone()),
+        None => KeyTheme::Derived,
+    }
+}
+
+/// Inverts luminance while keeping colors, so white icons on black turn into black on white and back
+fn derive_dark(image: DynamicImage) -> DynamicImage {
+    let mut image = image.to_rgb8();
+
+    for Rgb([r, g, b]) in image.pixels_mut() {
+        let luma = 0.299 * *r as f32 + 0.587 * *g as f32 + 0.114 * *b as f32;
+        let shift = 255.0 - 2.0 * luma;
+
+        for channel in [r, g, b] {
+            *channel = (*channel as f32 + shift).round().clamp(0.0, 255.0) as u8;
+        }
+    }
+
+    DynamicImage::ImageRgb8(image)
+}
+
+/// Redraws the device from cached images whenever its theme changes
+pub async fn theme_task(id: String, token: CancellationToken) {
+    let mut shown = current(&id).await;
+
+    loop {
+        tokio::select! {
+            _ = tokio::time::sleep(CHECK_INTERVAL) => {},
+            _ = settings::changed() => {},
+            _ = token.cancelled() => break,
+        }
+
+        let theme = current(&id).await;
+
+        if theme != shown {
+            log::info!("Switching {} to {:?} theme", id, theme);
+
+            shown = theme;
+            redraw_from_cache(&id).await;
+        }
+    }
+}
@@ -0,0 +1,266 @@
+use image::DynamicImage;
+use serde::{Deserialize, Serialize};
+
+/// Tone curve applied to key images before encoding, the small LCDs render midtones darker than monitors
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Tone {
+    /// Exponent applied to channel values, below 1 brightens midtones
+    pub gamma: f32,
+    /// Spread around mid-gray, above 1 increases contrast
+    pub contrast: f32,
+    /// Added to every channel after gamma and contrast, as a fraction of full scale
+    pub lift: f32,
+}
+
+impl Tone {
+    /// Leaves images as they are
+    pub const IDENTITY: Tone = Tone {
+        gamma: 1.0,
+        contrast: 1.0,
+        lift: 0.0,
+    };
+
+    /// Parses tone in `<gamma>,<contrast>,<lift>` format
+    pub fn parse(value: &str) -> Option<Self> {
+        let mut parts = value.split(',').map(|part| part.trim().parse::<f32>().ok());
+
+        let tone = Self {
+            gamma: parts.next()??,
+            contrast: parts.next()??,
+            lift: parts.next()??,
+        };
+
+        let valid = parts.next().is_none()
+            && (0.1..=10.0).contains(&tone.gamma)
+            && (0.0..=10.0).contains(&tone.contrast)
+            && (-1.0..=1.0).contains(&tone.lift);
+
+        valid.then_some(tone)
+    }
+
+    /// Returns new value of every possible channel value, per color channel
+    ///
+    /// The tint multiplies the result of the curve, so both end up in one lookup
+    fn tables(&self, tint: Tint) -> [[u8; 256]; 3] {
+        let factors = [tint.red, tint.green, tint.blue];
+
+        factors.map(|factor| {
+            std::array::from_fn(|value| {
+                let value = (value as f32 / 255.0).powf(self.gamma);
+                let value = ((value - 0.5) * self.contrast + 0.5 + self.lift).clamp(0.0, 1.0);
+
+                (value * factor * 255.0).round() as u8
+            })
+        })
+    }
+
+    /// Applies the curve and then the tint (if any) to color channels, alpha is kept
+    pub fn apply(&self, image: DynamicImage, tint: Option<Tint>) -> DynamicImage {
+        let tint = tint.unwrap_or(Tint::NONE);
+
+        if *self == Self::IDENTITY && tint == Tint::NONE {
+            return image;
+        }
+
+        let tables = self.tables(tint);
+
+        match image {
+            DynamicImage::ImageRgba8(mut image) => {
+                for pixel in image.pixels_mut() {
+                    for (channel, table) in pixel.0[..3].iter_mut().zip(&tables) {
+                        *channel = table[*channel as usize];
+                    }
+                }
+
+                DynamicImage::ImageRgba8(image)
+            }
+            image => {
+                let mut image = image.to_rgb8();
+
+                for pixel in image.pixels_mut() {
+                    for (channel, table) in pixel.0.iter_mut().zip(&tables) {
+                        *channel = table[*channel as usize];
+                    }
+                }
+
+                DynamicImage::ImageRgb8(image)
+            }
+        }
+    }
+}
+
+/// Per-channel multiplier for using the device at night, cuts blue light of bright icons
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Tint {
+    pub red: f32,
+    pub green: f32,
+    pub blue: f32,
+}
+
+impl Tint {
+    /// Leaves colors as they are
+    pub const NONE: Tint = Tint {
+        red: 1.0,
+        green: 1.0,
+        blue: 1.0,
+    };
+
+    /// Warm tint used when night mode is turned on without factors
+    pub const NIGHT: Tint = Tint {
+        red: 1.0,
+        green: 0.8,
+        blue: 0.55,
+    };
+
+    /// Parses tint in `<red>,<green>,<blue>` format, factors between 0 and 1
+    pub fn parse(value: &str) -> Option<Self> {
+        let mut parts = value.split(',').map(|part| part.trim().parse::<f32>().ok());
+
+        let tint = Self {
+            red: parts.next()??,
+            green: parts.next()??,
+            blue: parts.next()??,
+        };
+
+        let valid = parts.next().is_none()
+            && [tint.red, tint.green, tint.blue]
+                .iter()
+                .all(|factor| (0.0..=1.0).contains(factor));
+
+        valid.then_some(tint)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use image::{Rgb, RgbImage, Rgba, RgbaImage};
+
+    use super::*;
+
+    fn rgb(value: u8) -> DynamicImage {
+        DynamicImage::ImageRgb8(RgbImage::from_pixel(1, 1, Rgb([value; 3])))
+    }
+
+    fn applied(tone: Tone, value: u8) -> u8 {
+        tone.apply(rgb(value), None).to_rgb8().get_pixel(0, 0).0[0]
+    }
+
+    #[test]
+    fn identity_keeps_every_value() {
+        for value in 0..=255 {
+            assert_eq!(applied(Tone::IDENTITY, value), value);
+        }
+    }
+
+    #[test]
+    fn gamma_below_one_brightens_midtones_only() {
+        let tone = Tone {
+            gamma: 0.5,
+            ..Tone::IDENTITY
+        };
+
+        assert_eq!(applied(tone, 0), 0);
+        assert_eq!(applied(tone, 64), 128);
+        assert_eq!(applied(tone, 255), 255);
+    }
+
+    #[test]
+    fn contrast_spreads_around_mid_gray_and_lift_shifts() {
+        let contrast = Tone {
+            contrast: 2.0,
+            ..Tone::IDENTITY
+        };
+        let lift = Tone {
+            lift: 0.1,
+            ..Tone::IDENTITY
+        };
+
+        assert_eq!(applied(contrast, 63), 0);
+        assert_eq!(applied(contrast, 192), 255);
+        assert_eq!(applied(lift, 100), 126);
+        assert_eq!(applied(lift, 250), 255);
+    }
+
+    #[test]
+    fn alpha_is_kept() {
+        let image = DynamicImage::ImageRgba8(RgbaImage::from_pixel(1, 1, Rgba([64, 64, 64, 100])));
+        let tone = Tone {
+            gamma: 0.5,
+            ..Tone::IDENTITY
+        };
+
+        assert_eq!(tone.apply(image, None).to_rgba8().get_pixel(0, 0).0, [128, 128, 128, 100]);
+    }
+
+    #[test]
+    fn parse_needs_three_values_in_range() {
+        assert_eq!(
+            Tone::parse("0.8, 1.1, 0.02"),
+            Some(Tone {
+                gamma: 0.8,
+                contrast: 1.1,
+                lift: 0.02
+            })
+        );
+        assert_eq!(Tone::parse("0.8,1.1"), None);
+        assert_eq!(Tone::parse("0.8,1.1,0,1"), None);
+        assert_eq!(Tone::parse("0,1,0"), None);
+        assert_eq!(Tone::parse("1,1,2"), None);
+    }
+
+    #[test]
+    fn tint_scales_each_channel() {
+        let image = DynamicImage::ImageRgb8(RgbImage::from_pixel(1, 1, Rgb([200, 200, 200])));
+        let tinted = Tone::IDENTITY.apply(image, Some(Tint::NIGHT));
+
+        assert_eq!(tinted.to_rgb8().get_pixel(0, 0).0, [200, 160, 110]);
+    }
+
+    #[test]
+    fn tint_applies_after_the_curve_and_keeps_alpha() {
+        let image = DynamicImage::ImageRgba8(RgbaImage::from_pixel(1, 1, Rgba([64, 64, 64, 100])));
+        let tone = Tone {
+            gamma: 0.5,
+            ..Tone::IDENTITY
+        };
+        let tint = Tint {
+            red: 1.0,
+            green: 0.5,
+            blue: 0.0,
+        };
+
+        // Gamma first lifts 64 to 128, the tint then halves green and removes blue
+        assert_eq!(
+            tone.apply(image, Some(tint)).to_rgba8().get_pixel(0, 0).0,
+            [128, 64, 0, 100]
+        );
+    }
+
+    #[test]
+    fn no_tint_leaves_the_image_alone() {
+        assert_eq!(
+            Tone::IDENTITY
+                .apply(rgb(77), Some(Tint::NONE))
+                .to_rgb8()
+                .get_pixel(0, 0)
+                .0,
+            [77; 3]
+        );
+    }
+
+    #[test]
+    fn tint_parse_needs_three_factors_up_to_one() {
+        assert_eq!(
+            Tint::parse("1, 0.7, 0.4"),
+            Some(Tint {
+                red: 1.0,
+                green: 0.7,
+                blue: 0.4
+            })
+        );
+        assert_eq!(Tint::parse("1,0.7"), None);
+        assert_eq!(Tint::parse("1,0.7,0.4,1"), None);
+        assert_eq!(Tint::parse("1,1.2,0.4"), None);
+        assert_eq!(Tint::parse("1,-0.1,0.4"), None);
+    }
+}
@@ -0,0 +1,285 @@
+use std::{
+    collections::HashMap,
+    time::{Duration, Instant},
+};
+
+use mirajazz::state::DeviceStateUpdate;
+use serde::{Deserialize, Serialize};
+
+use crate::reader::InputUpdate;
+
+/// Spin speed in ticks per second up to which twists are forwarded as they are
+const BASE_SPEED: f32 = 8.0;
+
+/// Largest factor a twist gets multiplied by
+const MAX_FACTOR: f32 = 10.0;
+
+/// Pause after which the next twist starts a new spin
+const SPIN_TIMEOUT: Duration = Duration::from_millis(300);
+
+/// How fast spins of the dial are turned into larger deltas
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TwistCurve {
+    /// Every tick is sent as it is
+    #[default]
+    Off,
+    /// Delta grows with speed above [BASE_SPEED]
+    Linear,
+    /// Delta grows with square of speed, fine control when slow, long jumps when fast
+    Quadratic,
+    /// Delta doubles for every [TwistSteps] threshold the gap to the previous tick is below
+    Steps,
+}
+
+/// Thresholds of [TwistCurve::Steps], per device
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TwistSteps {
+    /// Gaps between ticks (ms), every one a tick comes faster than doubles its delta
+    pub thresholds_ms: Vec<u64>,
+    /// Largest factor a tick gets multiplied by
+    pub max_factor: u8,
+}
+
+impl Default for TwistSteps {
+    /// 1, 2 or 4 ticks for gaps over 80ms, under 80ms and under 40ms
+    fn default() -> Self {
+        Self {
+            thresholds_ms: vec![80, 40],
+            max_factor: 4,
+        }
+    }
+}
+
+impl TwistSteps {
+    /// Parses thresholds like `80,40` and the largest factor
+    pub fn parse(thresholds: &str, max_factor: &str) -> Option<Self> {
+        let thresholds_ms = thresholds
+            .split(',')
+            .map(|value| value.parse::<u64>().ok().filter(|ms| *ms > 0))
+            .collect::<Option<Vec<_>>>()?;
+        let max_factor = max_factor.parse::<u8>().ok().filter(|factor| *factor > 0)?;
+
+        Some(Self {
+            thresholds_ms,
+            max_factor,
+        })
+    }
+
+    /// Returns factor for a tick that came `gap` after the previous one
+    fn factor(&self, gap: Duration) -> f32 {
+        let passed = self
+            .thresholds_ms
+            .iter()
+            .filter(|ms| gap < Duration::from_millis(**ms))
+            .count() as u32;
+
+        2f32.powi(passed.min(8) as i32).min(self.max_factor as f32)
+    }
+}
+
+impl TwistCurve {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "off" => Some(Self::Off),
+            "linear" => Some(Self::Linear),
+            "quadratic" => Some(Self::Quadratic),
+            "steps" => Some(Self::Steps),
+            _ => None,
+        }
+    }
+
+    /// Returns factor for a twist of `delta` ticks that came `gap` after the previous one of
+    /// the same spin, `None` gap for the first twist of a spin
+    fn factor(&self, steps: &TwistSteps, delta: i8, gap: Option<Duration>) -> f32 {
+        // The first tick of a spin is always sent as it is, so single clicks stay exact
+        let Some(gap) = gap else {
+            return 1.0;
+        };
+
+        let speed = delta.unsigned_abs() as f32 / gap.as_secs_f32().max(0.001);
+        let ratio = (speed / BASE_SPEED).max(1.0);
+
+        match self {
+            Self::Off => 1.0,
+            Self::Linear => ratio.min(MAX_FACTOR),
+            Self::Quadratic => (ratio * ratio).min(MAX_FACTOR),
+            Self::Steps => steps.factor(gap),
+        }
+    }
+}
+
+/// Measures speed of the dials from consecutive twists, per device
+#[derive(Debug, Default)]
+pub struct TwistVelocity {
+    /// When the previous twist of every encoder arrived and its direction
+    last: HashMap<u8, (Instant, i8)>,
+}
+
+impl TwistVelocity {
+    /// Scales twist by the speed it was spun at, other updates pass through
+    pub fn scale(
+        &mut self,
+        curve: TwistCurve,
+        steps: &TwistSteps,
+        input: InputUpdate,
+    ) -> InputUpdate {
+        self.scale_at(curve, steps, input, Instant::now())
+    }
+
+    /// Scales twist that arrived at `now`
+    fn scale_at(
+        &mut self,
+        curve: TwistCurve,
+        steps: &TwistSteps,
+        input: InputUpdate,
+        now: Instant,
+    ) -> InputUpdate {
+        let DeviceStateUpdate::EncoderTwist(encoder, delta) = input.update else {
+            return input;
+        };
+
+        let direction = delta.signum();
+
+        let gap = match self.last.insert(encoder, (now, direction)) {
+            Some((last, last_direction))
+                if last_direction == direction && now - last < SPIN_TIMEOUT =>
+            {
+                Some(now - last)
+            }
+            // Changing direction or a pause starts a new spin at the base speed
+            _ => None,
+        };
+
+        let scaled = (delta as f32 * curve.factor(steps, delta, gap))
+            .round()
+            .clamp(i8::MIN as f32, i8::MAX as f32) as i8;
+
+        InputUpdate {
+            update: DeviceStateUpdate::EncoderTwist(encoder, scaled),
+            ..input
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Feeds twists of encoder 0 arriving at the given ms, returns the deltas sent on
+    fn spin(curve: TwistCurve, steps: &TwistSteps, twists: &[(u64, i8)]) -> Vec<i8> {
+        let mut velocity = TwistVelocity::default();
+        let start = Instant::now();
+
+        twists
+            .iter()
+            .map(|(ms, delta)| {
+                let input = DeviceStateUpdate::EncoderTwist(0, *delta).into();
+                let now = start + Duration::from_millis(*ms);
+
+                match velocity.scale_at(curve, steps, input, now).update {
+                    DeviceStateUpdate::EncoderTwist(_, delta) => delta,
+                    _ => unreachable!(),
+                }
+            })
+            .collect()
+    }
+
+    #[test]
+    fn steps_double_per_threshold_passed() {
+        let steps = TwistSteps::default();
+
+        // First tick unscaled, then gaps of 100, 60, 30 and 10ms
+        assert_eq!(
+            spin(
+                TwistCurve::Steps,
+                &steps,
+                &[(0, 1), (100, 1), (160, 1), (190, 1), (200, 1)]
+            ),
+            [1, 1, 2, 4, 4]
+        );
+    }
+
+    #[test]
+    fn steps_are_capped_at_the_max_factor() {
+        let steps = TwistSteps {
+            thresholds_ms: vec![80, 40, 20],
+            max_factor: 3,
+        };
+
+        assert_eq!(
+            spin(TwistCurve::Steps, &steps, &[(0, -1), (10, -1), (15, -2)]),
+            [-1, -3, -6]
+        );
+    }
+
+    #[test]
+    fn pause_and_direction_change_start_a_new_spin() {
+        let steps = TwistSteps::default();
+
+        // 30ms gaps would be 4x, but a reversal and a pause past the spin timeout reset it
+        assert_eq!(
+            spin(
+                TwistCurve::Steps,
+                &steps,
+                &[(0, 1), (30, 1), (60, -1), (90, -1), (500, -1)]
+            ),
+            [1, 4, -1, -4, -1]
+        );
+    }
+
+    #[test]
+    fn off_sends_every_tick_as_it_is() {
+        assert_eq!(
+            spin(
+                TwistCurve::Off,
+                &TwistSteps::default(),
+                &[(0, 1), (5, 1), (10, 2)]
+            ),
+            [1, 1, 2]
+        );
+    }
+
+    #[test]
+    fn spins_are_tracked_per_encoder() {
+        let steps = TwistSteps::default();
+        let mut velocity = TwistVelocity::default();
+        let start = Instant::now();
+
+        let mut twist = |encoder, ms| {
+            let input = DeviceStateUpdate::EncoderTwist(encoder, 1).into();
+
+            match velocity
+                .scale_at(
+                    TwistCurve::Steps,
+                    &steps,
+                    input,
+                    start + Duration::from_millis(ms),
+                )
+                .update
+            {
+                DeviceStateUpdate::EncoderTwist(_, delta) => delta,
+                _ => unreachable!(),
+            }
+        };
+
+        assert_eq!(twist(0, 0), 1);
+        // A fast tick of the other encoder is the first of its own spin
+        assert_eq!(twist(1, 10), 1);
+        assert_eq!(twist(0, 30), 4);
+    }
+
+    #[test]
+    fn steps_parse_needs_positive_values() {
+        assert_eq!(
+            TwistSteps::parse("100,50,25", "8"),
+            Some(TwistSteps {
+                thresholds_ms: vec![100, 50, 25],
+                max_factor: 8
+            })
+        );
+        assert_eq!(TwistSteps::parse("80,0", "4"), None);
+        assert_eq!(TwistSteps::parse("80,40", "0"), None);
+        assert_eq!(TwistSteps::parse("fast", "4"), None);
+    }
+}
@@ -0,0 +1,267 @@
+use std::{collections::HashMap, process::Stdio, sync::LazyLock};
+
+use image::{DynamicImage, RgbImage, imageops};
+use mirajazz::error::MirajazzError;
+use tokio::{io::AsyncReadExt, process::Command, sync::Mutex};
+use tokio_util::sync::CancellationToken;
+
+use crate::{
+    DEVICES, TOKENS, backend::{DeckDevice, encode_key_image}, cache, compose, device::{self, convert_colors, device_color_order}, inputs::opendeck_to_device,
+    mappings::get_image_format_for_key, settings, watcher::device_tracker,
+};
+
+/// Size every key tile is rendered at, mirajazz scales it down for the smaller LCDs
+const TILE_SIZE: u32 = 96;
+
+/// Highest frame rate accepted, the device can't keep up with more across several keys
+pub const MAX_FPS: u32 = 10;
+
+/// Rectangle of keys the video is shown on, in OpenDeck positions
+#[derive(Debug, Clone, Copy)]
+pub struct Region {
+    /// Top left key
+    pub position: u8,
+    pub cols: u8,
+    pub rows: u8,
+}
+
+impl Region {
+    /// Parses region in `<position>:<cols>x<rows>` format
+    pub fn parse(value: &str) -> Option<Self> {
+        let (position, size) = value.split_once(':')?;
+        let (cols, rows) = size.split_once('x')?;
+
+        Some(Self {
+            position: position.parse().ok()?,
+            cols: cols.parse().ok()?,
+            rows: rows.parse().ok()?,
+        })
+    }
+
+    /// Returns OpenDeck positions of the region, row by row
+    fn positions(&self, layout_cols: u8) -> Vec<u8> {
+        let (row, col) = (self.position / layout_cols, self.position % layout_cols);
+
+        (row..row + self.rows)
+            .flat_map(|r| (col..col + self.cols).map(move |c| r * layout_cols + c))
+            .collect()
+    }
+}
+
+/// Running previews, keyed by device id
+static PLAYING: LazyLock<Mutex<HashMap<String, CancellationToken>>> =
+    LazyLock::new(|| Mutex::new(HashMap::new()));
+
+/// Starts playing `source` (anything ffmpeg can open) on the region, replacing any running preview
+pub async fn play(id: &str, region: Region, fps: u32, source: String) -> Result<(), String> {
+    if fps == 0 || fps > MAX_FPS {
+        return Err(format!("fps must be between 1 and {}", MAX_FPS));
+    }
+
+    let kind = DEVICES
+        .read()
+        .await
+        .get(id)
+        .map(|device| device.kind())
+        .ok_or("unknown device")?;
+
+    let (rows, cols) = kind.layout();
+    let (row, col) = (
+        region.position as usize / cols,
+        region.position as usize % cols,
+    );
+
+    if region.cols == 0
+        || region.rows == 0
+        || col + region.cols as usize > cols
+        || row + region.rows as usize > rows
+    {
+        return Err("region doesn't fit the device".to_string());
+    }
+
+    let device_token = TOKENS
+        .read()
+        .await
+        .get(id)
+        .cloned()
+        .ok_or("unknown device")?;
+
+    // Stops together with the device
+    let token = device_token.child_token();
+
+    if let Some(previous) = PLAYING.lock().await.insert(id.to_string(), token.clone()) {
+        previous.cancel();
+    }
+
+    let positions = region.positions(cols as u8);
+
+    let tracker = device_tracker(id).await.ok_or("unknown device")?;
+
+    tracker.spawn(video_task(
+        id.to_string(),
+        region,
+        positions,
+        fps,
+        source,
+        token,
+    ));
+
+    Ok(())
+}
+
+/// Stops the preview and lets OpenDeck redraw the keys
+pub async fn stop(id: &str) -> Result<(), String> {
+    let token = PLAYING
+        .lock()
+        .await
+        .remove(id)
+        .ok_or("nothing is playing")?;
+    token.cancel();
+
+    openaction::device_plugin::rerender_images(id.to_string())
+        .await
+        .map_err(|e| e.to_string())
+}
+
+async fn video_task(
+    id: String,
+    region: Region,
+    positions: Vec<u8>,
+    fps: u32,
+    source: String,
+    token: CancellationToken,
+) {
+    let (width, height) = (
+        region.cols as u32 * TILE_SIZE,
+        region.rows as u32 * TILE_SIZE,
+    );
+
+    log::info!("Playing {} on {} at {:?}, {} fps", source, id, region, fps);
+
+    let child = Command::new("ffmpeg")
+        .args(["-loglevel", "error", "-re", "-i", &source, "-an", "-vf"])
+        .arg(format!(
+            "fps={},scale={}:{}:force_original_aspect_ratio=increase,crop={}:{}",
+            fps, width, height, width, height
+        ))
+        .args(["-f", "rawvideo", "-pix_fmt", "rgb24", "-"])
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::inherit())
+        .kill_on_drop(true)
+        .spawn();
+
+    let mut child = match child {
+        Ok(child) => child,
+        Err(e) => {
+            log::error!("Unable to start ffmpeg: {}", e);
+            PLAYING.lock().await.remove(&id);
+            return;
+        }
+    };
+
+    let mut stdout = child.stdout.take().expect("stdout is piped");
+    let mut frame = vec![0u8; (width * height * 3) as usize];
+
+    // Last tile uploaded to every key, keys that didn't change are skipped
+    let mut shown: Vec<Option<RgbImage>> = vec![None; positions.len()];
+
+    loop {
+        let read = tokio::select! {
+            v = stdout.read_exact(&mut frame) => v,
+            _ = token.cancelled() => break,
+        };
+
+        if let Err(e) = read {
+            log::info!("Video {} finished: {}", source, e);
+            break;
+        }
+
+        let image = RgbImage::from_raw(width, height, frame.clone()).expect("frame size matches");
+
+        if !show_frame(&id, region, &positions, &image, &mut shown, &token).await {
+            break;
+        }
+    }
+
+    child.kill().await.ok();
+
+    // Only forget the preview if it wasn't replaced by another one
+    let mut playing = PLAYING.lock().await;
+    if !token.is_cancelled() || playing.get(&id).is_some_and(|t| t.is_cancelled()) {
+        playing.remove(&id);
+    }
+
+    log::info!("Video task finished for {}", id);
+}
+
+/// Queues tiles that changed since the last frame, returns false if device is gone
+async fn show_frame(
+    id: &str,
+    region: Region,
+    positions: &[u8],
+    image: &RgbImage,
+    shown: &mut [Option<RgbImage>],
+    token: &CancellationToken,
+) -> bool {
+    let mut tiles = vec![];
+
+    for (index, position) in positions.iter().enumerate() {
+        let (x, y) = (
+            (index as u32 % region.cols as u32) * TILE_SIZE,
+            (index as u32 / region.cols as u32) * TILE_SIZE,
+        );
+
+        let tile = imageops::crop_imm(image, x, y, TILE_SIZE, TILE_SIZE).to_image();
+
+        if shown[index].as_ref() == Some(&tile) {
+            continue;
+        }
+
+        shown[index] = Some(tile.clone());
+        tiles.push((*position, tile));
+    }
+
+    if tiles.is_empty() {
+        return true;
+    }
+
+    // Written by the image worker, so frames never cut into another upload
+    device::show_video_tiles(id, tiles, token.clone()).await
+}
+
+/// Writes tile to the key at OpenDeck position, without flushing
+///
+/// Returns the physical position written to
+pub async fn upload_tile(
+    device: &dyn DeckDevice,
+    id: &str,
+    position: u8,
+    tile: RgbImage,
+) -> Result<u8, MirajazzError> {
+    let kind = device.kind();
+
+    // Layers can't be drawn on a video frame, redraws go back to the key image
+    compose::forget_base(id, position).await;
+
+    let position = settings::profile_for(id).await.apply(&kind, position);
+
+    // Key content is no longer what the last regular upload left there
+    cache::forget_shown(id, position).await;
+
+    let color_order = device_color_order(id, &kind).await;
+    let quality = settings::jpeg_quality_for(id).await;
+
+    let data = encode_key_image(
+        get_image_format_for_key(&kind, position),
+        convert_colors(color_order, DynamicImage::ImageRgb8(tile)),
+        quality,
+    )
+    .await?;
+
+    device
+        .write_image(opendeck_to_device(&kind, position), &data)
+        .await?;
+
+    Ok(position)
+}
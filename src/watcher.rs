@@ -5,12 +5,16 @@ use mirajazz::{
     types::{DeviceLifecycleEvent, HidDeviceInfo},
 };
 
-use tokio_util::sync::CancellationToken;
+use std::{collections::HashSet, sync::LazyLock, time::Duration};
+
+use tokio::sync::Mutex;
+use tokio_util::{sync::CancellationToken, task::TaskTracker};
 
 use crate::{
-    DEVICES, TOKENS, TRACKER,
-    device::device_task,
-    mappings::{CandidateDevice, DEVICE_NAMESPACE, Kind, QUERIES},
+    DEVICE_TRACKERS, DEVICES, TOKENS,
+    device::{RegistrationTurn, device_task},
+    mappings::{CandidateDevice, DEVICE_NAMESPACE, Kind, QUERIES, Transport},
+    settings,
 };
 
 fn get_device_id(dev: &HidDeviceInfo) -> Option<String> {
@@ -24,59 +28,238 @@ fn get_device_id(dev: &HidDeviceInfo) -> Option<String> {
     ))
 }
 
-fn device_info_to_candidate(dev: HidDeviceInfo) -> Option<CandidateDevice> {
-    let id = get_device_id(&dev)?;
+/// Returns ids older plugin versions gave the device, see [settings::resolve_device_id]
+fn legacy_device_ids(dev: &HidDeviceInfo) -> Vec<String> {
+    let Some(serial) = &dev.serial_number else {
+        return vec![];
+    };
+
+    // v1 scheme, suffixed with the namespace
+    vec![format!(
+        "{}-{}-{}",
+        DEVICE_NAMESPACE, serial, DEVICE_NAMESPACE
+    )]
+}
+
+/// Returns id the device is used under, its current one or a legacy one profiles are bound to
+async fn resolve_device_id(dev: &HidDeviceInfo) -> Option<String> {
+    let id = get_device_id(dev)?;
+
+    Some(settings::resolve_device_id(&id, &legacy_device_ids(dev)).await)
+}
+
+async fn device_info_to_candidate(dev: HidDeviceInfo) -> Option<CandidateDevice> {
+    let id = resolve_device_id(&dev).await?;
     let kind = Kind::from_vid_pid(dev.vendor_id, dev.product_id)?;
 
-    Some(CandidateDevice { id, dev, kind })
+    Some(CandidateDevice {
+        id,
+        transport: Transport::Hid(dev),
+        kind,
+    })
+}
+
+/// Virtual device requested through `OPENDECK_N1_MOCK=<serial>`, for development without hardware
+fn mock_candidate() -> Option<CandidateDevice> {
+    let serial = std::env::var("OPENDECK_N1_MOCK").ok()?;
+
+    Some(CandidateDevice {
+        id: format!("{}-{}", DEVICE_NAMESPACE, serial),
+        transport: Transport::Mock,
+        kind: Kind::N1,
+    })
 }
 
 /// Returns devices that matches known pid/vid pairs
-async fn get_candidates() -> Result<Vec<CandidateDevice>, MirajazzError> {
+pub async fn get_candidates() -> Result<Vec<CandidateDevice>, MirajazzError> {
     log::info!("Looking for candidate devices");
 
-    let mut candidates: Vec<CandidateDevice> = Vec::new();
+    let mut candidates: Vec<CandidateDevice> = mock_candidate().into_iter().collect();
 
     for dev in list_devices(&QUERIES).await? {
-        if let Some(candidate) = device_info_to_candidate(dev.clone()) {
+        if let Some(candidate) = device_info_to_candidate(dev.clone()).await {
             candidates.push(candidate);
         } else {
             continue;
         }
     }
 
+    // Enumeration order isn't stable between runs, sort by serial and then by USB path
+    candidates.sort_by_cached_key(|candidate| {
+        let path = match &candidate.transport {
+            Transport::Hid(dev) => format!("{:?}", dev.id),
+            Transport::Mock => String::new(),
+        };
+
+        (candidate.id.clone(), path)
+    });
+
     Ok(candidates)
 }
 
-pub async fn watcher_task(token: CancellationToken) -> Result<(), MirajazzError> {
-    let tracker = TRACKER.lock().await.clone();
+/// Spawns device task into a tracker of its own
+///
+/// Device tasks come and go with every reconnect, so they are kept out of the global [crate::TRACKER]
+/// and their trackers are dropped once everything spawned for the device finished
+async fn spawn_device(candidate: CandidateDevice, turn: RegistrationTurn) {
+    let token = CancellationToken::new();
+
+    TOKENS
+        .write()
+        .await
+        .insert(candidate.id.clone(), token.clone());
+
+    let tracker = TaskTracker::new();
+    let id = candidate.id.clone();
+
+    tracker.spawn(device_task(candidate, token, turn));
+
+    let mut trackers = DEVICE_TRACKERS.lock().await;
+    trackers.retain(|(_, tracker)| !tracker.is_empty());
+    trackers.push((id, tracker));
+}
+
+/// Returns tracker of the current device task, so tasks spawned for the device are waited for with it
+pub async fn device_tracker(id: &str) -> Option<TaskTracker> {
+    DEVICE_TRACKERS
+        .lock()
+        .await
+        .iter()
+        .rev()
+        .find(|(tracker_id, _)| tracker_id == id)
+        .map(|(_, tracker)| tracker.clone())
+}
+
+/// How often devices that couldn't be opened for lack of permissions are tried again
+const PERMISSION_RETRY: Duration = Duration::from_secs(3);
+
+/// Connected devices that couldn't be opened for lack of permissions, by device id
+///
+/// udev applies newly installed rules to present devices only on the next event, e.g. a
+/// `udevadm trigger`, and sends none the watcher would see. Retrying catches the fix without a replug.
+static UNOPENABLE: LazyLock<Mutex<HashSet<String>>> = LazyLock::new(|| Mutex::new(HashSet::new()));
+
+/// Records that the device couldn't be opened for lack of permissions, true if it's news
+pub async fn mark_unopenable(id: &str) -> bool {
+    UNOPENABLE.lock().await.insert(id.to_string())
+}
+
+/// Records that the device was opened, logging it if permissions were missing before
+pub async fn mark_opened(id: &str) {
+    if UNOPENABLE.lock().await.remove(id) {
+        log::info!("Permissions of {} were fixed, connecting", id);
+    }
+}
+
+/// Tries again to open devices that lacked permissions, forgets the ones that are gone
+async fn retry_unopenable() -> Result<(), MirajazzError> {
+    if UNOPENABLE.lock().await.is_empty() || !may_claim() {
+        return Ok(());
+    }
+
+    let candidates = get_candidates().await?;
+
+    let mut unopenable = UNOPENABLE.lock().await;
+    unopenable.retain(|id| candidates.iter().any(|candidate| &candidate.id == id));
+    let retry: Vec<CandidateDevice> = candidates
+        .into_iter()
+        .filter(|candidate| unopenable.contains(&candidate.id))
+        .collect();
+    drop(unopenable);
+
+    for candidate in retry {
+        // Previous attempt may still be running
+        let running = device_tracker(&candidate.id)
+            .await
+            .is_some_and(|tracker| !tracker.is_empty());
+
+        if running || DEVICES.read().await.contains_key(&candidate.id) {
+            continue;
+        }
+
+        log::debug!("Trying to open {} again", candidate.id);
+        spawn_device(candidate, RegistrationTurn::default()).await;
+    }
+
+    Ok(())
+}
+
+/// Returns false while devices belong to another session on the seat
+fn may_claim() -> bool {
+    #[cfg(all(target_os = "linux", feature = "session-guard"))]
+    return crate::session::is_active();
+
+    #[cfg(not(all(target_os = "linux", feature = "session-guard")))]
+    true
+}
+
+/// Spawns device tasks for connected devices that aren't in use yet
+pub async fn claim_devices() -> Result<(), MirajazzError> {
+    if !may_claim() {
+        log::info!("Session is not active, leaving devices alone");
+        return Ok(());
+    }
 
     // Scans for connected devices that (possibly) we can use
     let candidates = get_candidates().await?;
 
     log::info!("Looking for connected devices");
 
-    for candidate in candidates {
+    let devices = DEVICES.read().await;
+    let candidates: Vec<CandidateDevice> = candidates
+        .into_iter()
+        .filter(|candidate| !devices.contains_key(&candidate.id))
+        .collect();
+    drop(devices);
+
+    let turns = RegistrationTurn::chain(candidates.len());
+
+    for (candidate, turn) in candidates.into_iter().zip(turns) {
         log::info!("New candidate {:#?}", candidate);
 
-        let token = CancellationToken::new();
+        spawn_device(candidate, turn).await;
+    }
 
-        TOKENS
-            .write()
-            .await
-            .insert(candidate.id.clone(), token.clone());
+    Ok(())
+}
 
-        tracker.spawn(device_task(candidate, token));
+/// Stops device task, closes the device and removes it from OpenDeck
+pub async fn release_device(id: &str) {
+    if let Some(token) = TOKENS.write().await.remove(id) {
+        log::info!("Sending cancel request for {}", id);
+        token.cancel();
     }
 
+    DEVICES.write().await.remove(id);
+
+    // Unregister device with OpenAction (ignore errors)
+    let _ = openaction::device_plugin::unregister_device(id.to_string()).await;
+}
+
+pub async fn watcher_task(token: CancellationToken) -> Result<(), MirajazzError> {
+    // Persisted per-device settings are needed during device init
+    settings::wait_loaded(Duration::from_secs(2)).await;
+
+    claim_devices().await?;
+
     let mut watcher = DeviceWatcher::new();
     let mut watcher_stream = watcher.watch(&QUERIES).await?;
 
     log::info!("Watcher is ready");
 
+    let mut retry = tokio::time::interval(PERMISSION_RETRY);
+    retry.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+
     loop {
         let ev = tokio::select! {
             v = watcher_stream.next() => v,
+            _ = retry.tick() => {
+                if let Err(e) = retry_unopenable().await {
+                    log::error!("Failed to retry devices without permissions: {}", e);
+                }
+
+                continue;
+            }
             _ = token.cancelled() => None
         };
 
@@ -85,37 +268,29 @@ pub async fn watcher_task(token: CancellationToken) -> Result<(), MirajazzError>
 
             match ev {
                 DeviceLifecycleEvent::Connected(info) => {
-                    if let Some(candidate) = device_info_to_candidate(info) {
+                    if let Some(candidate) = device_info_to_candidate(info).await {
                         // Don't add existing device again
                         if DEVICES.read().await.contains_key(&candidate.id) {
                             continue;
                         }
 
-                        let token = CancellationToken::new();
-
-                        TOKENS
-                            .write()
-                            .await
-                            .insert(candidate.id.clone(), token.clone());
+                        if !may_claim() {
+                            log::info!("Session is not active, ignoring {}", candidate.id);
+                            continue;
+                        }
 
                         log::debug!("Spawning task for new device: {:?}", candidate);
-                        tracker.spawn(device_task(candidate, token));
+                        spawn_device(candidate, RegistrationTurn::default()).await;
                         log::debug!("Spawned");
                     }
                 }
                 DeviceLifecycleEvent::Disconnected(info) => {
-                    let id = get_device_id(&info)
+                    let id = resolve_device_id(&info)
+                        .await
                         .expect("Unable to get device id, check mappings in Kind::from_vid_pid");
 
-                    if let Some(token) = TOKENS.write().await.remove(&id) {
-                        log::info!("Sending cancel request for {}", id);
-                        token.cancel();
-                    }
-
-                    DEVICES.write().await.remove(&id);
-
-                    // Unregister device with OpenAction (ignore errors)
-                    let _ = openaction::device_plugin::unregister_device(id.clone()).await;
+                    release_device(&id).await;
+                    UNOPENABLE.lock().await.remove(&id);
 
                     log::info!("Disconnected device {}", id);
                 }